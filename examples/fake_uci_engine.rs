@@ -0,0 +1,126 @@
+//! A fake UCI engine for the test suite.
+//!
+//! The engine reads a script from the file named after the executable with a
+//! `.script` suffix (e.g. `engine.script` when run as `engine`), or from the
+//! file pointed to by `FAKE_UCI_ENGINE_SCRIPT`. The script consists of handlers
+//! for the commands sent by the GUI:
+//!
+//! ```text
+//! # comment
+//! on uci
+//!     send id name Fake
+//!     send uciok
+//! on go
+//!     sleep 100
+//!     send bestmove e2e4
+//! ```
+//!
+//! A handler is chosen by the first word of the command. Its actions are:
+//! * `send <line>` - writes the line to stdout,
+//! * `sleep <ms>` - waits for the given number of milliseconds,
+//! * `exit <code>` - exits with the given code,
+//! * `crash` - aborts the process,
+//! * `hang` - stops responding forever.
+//!
+//! Commands without a handler are ignored, except for `quit` which exits.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+use std::{env, fs, process, thread};
+
+enum Action {
+    Send(String),
+    Sleep(Duration),
+    Exit(i32),
+    Crash,
+    Hang,
+}
+
+fn parse_action(line: &str) -> Result<Action, String> {
+    let (word, arg) = match line.find(' ') {
+        Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+        None => (line, ""),
+    };
+    let action = match word {
+        "send" => Action::Send(arg.to_owned()),
+        "sleep" => Action::Sleep(Duration::from_millis(
+            arg.parse().map_err(|_| format!("invalid delay: {}", arg))?,
+        )),
+        "exit" => Action::Exit(arg.parse().map_err(|_| format!("invalid code: {}", arg))?),
+        "crash" => Action::Crash,
+        "hang" => Action::Hang,
+        _ => return Err(format!("unknown action: {}", line)),
+    };
+    Ok(action)
+}
+
+fn parse_script(script: &str) -> Result<HashMap<String, Vec<Action>>, String> {
+    let mut handlers = HashMap::new();
+    let mut current = None;
+    for line in script.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(command) = line.strip_prefix("on ") {
+            let command = command.trim().to_owned();
+            handlers.insert(command.clone(), Vec::new());
+            current = Some(command);
+            continue;
+        }
+        let command = current
+            .as_ref()
+            .ok_or_else(|| format!("action outside of a handler: {}", line))?;
+        let action = parse_action(line)?;
+        handlers
+            .get_mut(command)
+            .expect("handler exists")
+            .push(action);
+    }
+    Ok(handlers)
+}
+
+fn script_path() -> String {
+    env::var("FAKE_UCI_ENGINE_SCRIPT").unwrap_or_else(|_| {
+        let argv0 = env::args().next().expect("argv[0] should be set");
+        format!("{}.script", argv0)
+    })
+}
+
+fn main() {
+    let path = script_path();
+    let script = fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("cannot read the script {}: {}", path, e);
+        process::exit(101);
+    });
+    let handlers = parse_script(&script).unwrap_or_else(|e| {
+        eprintln!("invalid script {}: {}", path, e);
+        process::exit(101);
+    });
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for line in stdin.lock().lines() {
+        let line = line.expect("reading stdin");
+        let command = line.split_whitespace().next().unwrap_or("");
+        for action in handlers.get(command).into_iter().flatten() {
+            match action {
+                Action::Send(line) => {
+                    writeln!(stdout, "{}", line).expect("writing stdout");
+                    stdout.flush().expect("flushing stdout");
+                }
+                Action::Sleep(delay) => thread::sleep(*delay),
+                Action::Exit(code) => process::exit(*code),
+                Action::Crash => process::abort(),
+                Action::Hang => loop {
+                    thread::sleep(Duration::from_secs(60));
+                },
+            }
+        }
+        if command == "quit" {
+            break;
+        }
+    }
+}
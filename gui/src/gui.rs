@@ -1,19 +1,36 @@
+mod moves;
+mod panel;
+
 use anyhow::{Context, Result};
 use glib::clone;
 use golemate::backends::UciOutput;
 use gtk::prelude::*;
 use gtk::{
-    main_quit, Button, ButtonsType, DialogFlags, Entry, HeaderBar, Label, MessageDialog,
-    MessageType, Orientation, SpinButton, WindowType,
+    main_quit, Button, ButtonsType, ComboBoxText, DialogFlags, Entry, FileChooserAction,
+    FileChooserDialog, FileFilter, HeaderBar, Label, MessageDialog, MessageType, Orientation,
+    ProgressBar, ResponseType, SpinButton, ToggleButton, WindowType,
 };
-use shakmaty::fen::Fen;
+use shakmaty::{fen::Fen, Chess};
 
+use std::cell::RefCell;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
 use std::thread;
-
-use golemate::analysis;
-use golemate::backends::{GWasmUci, NativeUci, UciBackend};
+use std::time::Duration;
+
+use golemate::analysis::{self, SearchInfo};
+use golemate::backends::{LiveEvent, ProgressEvent, ProgressObserver, UciBackend};
+use golemate::book::{self, Book};
+use golemate::config::{BackendKind, Config, Profile};
+use golemate::history::{AnalysisHistory, RecordedUci};
+use golemate::pgn;
+use golemate::review;
+use golemate::variant::Variant;
+use moves::MoveList;
+use panel::AnalysisPanel;
 
 pub struct App {
     pub window: gtk::Window,
@@ -44,17 +61,317 @@ const APP_NAME: &str = "Golemate";
 
 fn launch_golemate<B: Deref<Target = dyn UciBackend>>(
     backend: B,
+    variant: Variant,
     fen: &str,
     depth: u32,
 ) -> Result<UciOutput> {
-    let cmds = backend.generate_uci(fen, depth);
+    let cmds = backend.generate_variant_uci(variant, fen, depth);
     let output = backend.execute_uci(cmds).context("Executing UCI")?;
     Ok(output)
 }
 
+/// Records the analyses in the history shared with the CLI
+fn record_history(backend: Box<dyn UciBackend>) -> Box<dyn UciBackend> {
+    let history = AnalysisHistory::default_path()
+        .context("cannot locate the history database")
+        .and_then(|path| AnalysisHistory::open(&path));
+    match history {
+        Ok(history) => Box::new(RecordedUci::new(backend, history)),
+        Err(e) => {
+            log::warn!("Not recording the analysis: {:?}", e);
+            backend
+        }
+    }
+}
+
+/// Reviews the games of a PGN file, describing the reviews
+fn review_pgn(
+    profile: &Profile,
+    path: &Path,
+    progress: Arc<dyn ProgressObserver>,
+) -> Result<String> {
+    let depth = profile.depth.expect("the depth is always set");
+    let text = std::fs::read_to_string(path).context("reading the PGN file")?;
+    let games = pgn::parse_pgn(&text).context("parsing the PGN file")?;
+    if games.is_empty() {
+        anyhow::bail!("there are no games in the file");
+    }
+    let mut backend = record_history(profile.batch_backend()?);
+    backend.set_progress_observer(progress);
+    let reviews = games
+        .iter()
+        .map(|game| {
+            review::review_game(&*backend, game, profile.variant(), depth).map(|r| r.describe())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(reviews.join("\n\n"))
+}
+
+/// Asks for a PGN file to open
+fn choose_pgn(window: &gtk::Window) -> Option<PathBuf> {
+    let chooser = FileChooserDialog::with_buttons(
+        Some("Review a game"),
+        Some(window),
+        FileChooserAction::Open,
+        &[
+            ("_Cancel", ResponseType::Cancel),
+            ("_Open", ResponseType::Accept),
+        ],
+    );
+    let filter = FileFilter::new();
+    filter.set_name(Some("PGN files"));
+    filter.add_pattern("*.pgn");
+    chooser.add_filter(&filter);
+    let response = chooser.run();
+    let path = chooser.get_filename();
+    chooser.destroy();
+    path.filter(|_| response == ResponseType::Accept)
+}
+
+fn show_message(window: &gtk::Window, result: Result<String>) {
+    let (dialog_type, dialog_body) = match result {
+        Ok(text) => (MessageType::Info, text),
+        Err(e) => (MessageType::Error, format!("Error: {:?}", e)),
+    };
+    let dialog = MessageDialog::new(
+        Some(window),
+        DialogFlags::empty(),
+        dialog_type,
+        ButtonsType::Ok,
+        &dialog_body,
+    );
+    dialog.run();
+    dialog.destroy();
+}
+
+/// Requests to the thread running the continuous analysis
+enum LiveCommand {
+    Analyse(String),
+    Stop,
+}
+
+/// Analyses the positions until stopped, passing the `info` lines to the
+/// main loop with the position searched
+fn live_analysis(
+    profile: &Profile,
+    fen: &str,
+    commands: &Receiver<LiveCommand>,
+    updates: &glib::Sender<Result<(String, SearchInfo)>>,
+) -> Result<()> {
+    let mut engine = profile.live_engine()?;
+    let mut fen = fen.to_owned();
+    engine.go_infinite(&format!("fen {}", fen))?;
+    loop {
+        match commands.try_recv() {
+            Ok(LiveCommand::Analyse(next)) => {
+                fen = next;
+                engine.go_infinite(&format!("fen {}", fen))?
+            }
+            Ok(LiveCommand::Stop) | Err(TryRecvError::Disconnected) => {
+                engine.stop()?;
+                return Ok(());
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+        if let Some(LiveEvent::Info(info)) = engine.next_event(POLL_INTERVAL)? {
+            // The receiver is gone if the window has been closed
+            let _ = updates.send(Ok((fen.clone(), info)));
+        }
+    }
+}
+
+/// The FEN of the entry, if it's valid in the variant
+fn valid_fen(entry: &Entry, variant: Variant) -> Option<String> {
+    let text = entry.get_buffer().get_text();
+    let fen: Fen = text.parse().ok()?;
+    variant.validate(&fen).ok()?;
+    Some(text)
+}
+
+enum Evaluation {
+    Book(String),
+    /// The output of the engine, and the most the search may have cost
+    Engine(UciOutput, Option<f64>),
+}
+
+/// Describes the book moves in the position, if there are any
+fn lookup_book(book_path: &Path, fen: &str) -> Result<Option<String>> {
+    let book = Book::open(book_path)?;
+    let fen: Fen = fen.parse().map_err(|_| anyhow::anyhow!("invalid FEN"))?;
+    let pos: Chess = fen.position()?;
+    let entries = book.lookup(&pos);
+    if entries.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(book::describe_book_moves(&pos, &entries)))
+    }
+}
+
+/// Passes the progress events to the main loop
+struct ProgressSender(Mutex<glib::Sender<ProgressEvent>>);
+
+impl ProgressObserver for ProgressSender {
+    fn notify(&self, event: ProgressEvent) {
+        // The receiver is gone if the window has been closed
+        let _ = self.0.lock().expect("poisoned mutex").send(event);
+    }
+}
+
+fn show_progress(progress_bar: &ProgressBar, event: ProgressEvent) {
+    use ProgressEvent::*;
+    match event {
+        TaskSubmitted => {
+            progress_bar.set_fraction(0.0);
+            progress_bar.set_text(Some("Task submitted"));
+        }
+        Cost(cost) => progress_bar.set_text(Some(&cost.describe())),
+        Progress(progress) => {
+            progress_bar.set_fraction(progress);
+            progress_bar.set_text(Some(&format!("{:.0}%", progress * 100.0)));
+        }
+        // Shown in the analysis panel, if anywhere
+        Info(_) => {}
+        TaskFinished => {
+            progress_bar.set_fraction(1.0);
+            progress_bar.set_text(Some("Finished"));
+        }
+    }
+}
+
+/// The controls mirroring the settings of a profile
+#[derive(Clone)]
+struct ProfileControls {
+    stack: gtk::Stack,
+    engine_path: Entry,
+    wasm_path: Entry,
+    js_path: Entry,
+    workspace_path: Entry,
+    datadir_path: Entry,
+    depth: SpinButton,
+    variant: ComboBoxText,
+    book_path: Entry,
+}
+
+fn path_text(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn entry_path(entry: &Entry) -> Option<PathBuf> {
+    Some(entry.get_buffer().get_text())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+impl ProfileControls {
+    fn show(&self, profile: &Profile) {
+        match profile.backend_kind() {
+            Some(BackendKind::Native) => self.stack.set_visible_child_name(NATIVE_PANE_NAME),
+            Some(BackendKind::Gwasm) => self.stack.set_visible_child_name(GWASM_PANE_NAME),
+            None => {}
+        }
+        self.engine_path.set_text(&path_text(&profile.engine));
+        self.wasm_path.set_text(&path_text(&profile.wasm));
+        self.js_path.set_text(&path_text(&profile.js));
+        self.workspace_path.set_text(&path_text(&profile.workspace));
+        self.datadir_path.set_text(&path_text(&profile.datadir));
+        self.book_path.set_text(&path_text(&profile.book));
+        if let Some(depth) = profile.depth {
+            self.depth.set_value(f64::from(depth));
+        }
+        self.variant.set_active_id(Some(profile.variant().name()));
+    }
+
+    /// The profile with the settings edited by the user
+    fn apply(&self, mut profile: Profile) -> Profile {
+        profile.backend = match self
+            .stack
+            .get_visible_child_name()
+            .as_ref()
+            .map(|s| s.as_str())
+        {
+            Some(NATIVE_PANE_NAME) => Some(BackendKind::Native),
+            Some(GWASM_PANE_NAME) => Some(BackendKind::Gwasm),
+            x => panic!("Invalid pane name: {:?}", x),
+        };
+        profile.engine = entry_path(&self.engine_path);
+        profile.wasm = entry_path(&self.wasm_path);
+        profile.js = entry_path(&self.js_path);
+        profile.workspace = entry_path(&self.workspace_path);
+        profile.datadir = entry_path(&self.datadir_path);
+        profile.book = entry_path(&self.book_path);
+        profile.depth = Some(self.depth.get_value_as_int() as u32);
+        profile.variant = self
+            .variant
+            .get_active_id()
+            .and_then(|id| id.as_str().parse().ok());
+        profile
+    }
+}
+
+/// The profiles of the configuration file, the selected one as edited with
+/// the controls
+#[derive(Clone)]
+struct Profiles {
+    config: Rc<RefCell<Config>>,
+    combo: ComboBoxText,
+    controls: ProfileControls,
+}
+
+impl Profiles {
+    /// The selected profile with the settings edited by the user
+    fn active(&self) -> Profile {
+        let selected = self
+            .combo
+            .get_active_id()
+            .and_then(|name| self.config.borrow().profiles.get(name.as_str()).cloned());
+        self.controls.apply(selected.unwrap_or_default())
+    }
+
+    /// Saves the active profile as `name` to the configuration file, and
+    /// selects it
+    fn save(&self, name: &str) -> Result<()> {
+        let path = Config::default_path().context("cannot locate the configuration file")?;
+        // Keeps the changes made to the file since it's been loaded, and
+        // doesn't overwrite a file which can't be read
+        let mut config = if path.exists() {
+            Config::load(&path)?
+        } else {
+            Config::default()
+        };
+        let profile = self.active();
+        let new = !self.config.borrow().profiles.contains_key(name);
+        config.profiles.insert(name.to_owned(), profile);
+        config.save(&path)?;
+        *self.config.borrow_mut() = config;
+
+        if new {
+            self.combo.append(Some(name), name);
+        }
+        self.combo.set_sensitive(true);
+        self.combo.set_active_id(Some(name));
+        Ok(())
+    }
+}
+
+fn load_config() -> Config {
+    Config::load_default().unwrap_or_else(|e| {
+        log::warn!("Cannot load the configuration: {:?}", e);
+        Config::default()
+    })
+}
+
+const SAVE_TEXT: &str = "Save";
 const EVALUATE_TEXT: &str = "Evaluate position";
 const EVALUATING_TEXT: &str = "Evaluating...";
+const REVIEW_TEXT: &str = "Review a game...";
+const REVIEWING_TEXT: &str = "Reviewing...";
+const LIVE_TEXT: &str = "Analyse continuously";
+/// How often the continuous analysis checks for a new position
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
 const VERTICAL_SPACING: i32 = 6;
+const HORIZONTAL_SPACING: i32 = 12;
 const BORDER_WIDTH: u32 = 10;
 
 const NATIVE_PANE_NAME: &str = "Native";
@@ -89,6 +406,19 @@ impl App {
         depth_box.pack_start(&depth_label, false, false, 0);
         depth_box.pack_start(&depth, true, true, 0);
 
+        let variant = ComboBoxText::new();
+        for v in Variant::ALL.iter() {
+            variant.append(Some(v.name()), v.name());
+        }
+        variant.set_active_id(Some(Variant::Standard.name()));
+        let variant_label = Label::new(Some("Variant:"));
+        let variant_box = gtk::Box::new(Orientation::Horizontal, 0);
+        variant_box.pack_start(&variant_label, false, false, 0);
+        variant_box.pack_start(&variant, true, true, 0);
+
+        let book_path = Entry::new();
+        book_path.set_placeholder_text(Some("Opening book path (optional)"));
+
         // Setup the native controls
         let engine_path = Entry::new();
         engine_path.set_placeholder_text(Some("Engine path"));
@@ -101,7 +431,7 @@ impl App {
         let js_path = Entry::new();
         js_path.set_placeholder_text(Some("JS path"));
         let workspace_path = Entry::new();
-        workspace_path.set_placeholder_text(Some("Workspace path"));
+        workspace_path.set_placeholder_text(Some("Workspace path (optional)"));
         let datadir_path = Entry::new();
         datadir_path.set_placeholder_text(Some("Datadir path"));
 
@@ -120,14 +450,188 @@ impl App {
         stackswitcher.set_hexpand(true);
         stackswitcher.set_halign(gtk::Align::Center);
 
+        let controls = ProfileControls {
+            stack: stack.clone(),
+            engine_path,
+            wasm_path,
+            js_path,
+            workspace_path,
+            datadir_path,
+            depth,
+            variant,
+            book_path: book_path.clone(),
+        };
+
+        // Setup the profiles from the configuration file
+        let config = load_config();
+        let profile_box = gtk::Box::new(Orientation::Horizontal, 0);
+        let profile_combo = ComboBoxText::new();
+        for name in config.profiles.keys() {
+            profile_combo.append(Some(name), name);
+        }
+        profile_combo.set_sensitive(!config.profiles.is_empty());
+        let profile_name = Entry::new();
+        profile_name.set_placeholder_text(Some("Profile name"));
+        let save_button = Button::new_with_label(SAVE_TEXT);
+        profile_box.pack_start(&Label::new(Some("Profile:")), false, false, 0);
+        profile_box.pack_start(&profile_combo, true, true, 0);
+        profile_box.pack_start(&profile_name, false, false, 0);
+        profile_box.pack_start(&save_button, false, false, 0);
+        let default_profile = config.default_profile.clone();
+        let profiles = Profiles {
+            config: Rc::new(RefCell::new(config)),
+            combo: profile_combo.clone(),
+            controls: controls.clone(),
+        };
+        profile_combo.connect_changed(clone!(@strong profiles => move |combo| {
+            let selected = combo.get_active_id();
+            let config = profiles.config.borrow();
+            if let Some(profile) = selected.as_ref().and_then(|name| config.profiles.get(name.as_str())) {
+                profiles.controls.show(profile);
+            }
+        }));
+        if let Some(name) = &default_profile {
+            profile_combo.set_active_id(Some(name));
+        }
+        // Saves under the entered name, or over the selected profile
+        save_button.connect_clicked(clone!(@weak window, @strong profiles => move |_| {
+            let entered = profile_name.get_buffer().get_text();
+            let name = match entered.trim() {
+                "" => profiles.combo.get_active_id().map(|name| name.to_string()),
+                name => Some(name.to_owned()),
+            };
+            let saved = match name {
+                Some(name) => profiles.save(&name),
+                None => Err(anyhow::anyhow!("Enter the name of the profile")),
+            };
+            match saved {
+                Ok(()) => profile_name.set_text(""),
+                Err(e) => show_message(&window, Err(e)),
+            }
+        }));
+
         // Setup the main view
         let container = gtk::Box::new(Orientation::Vertical, VERTICAL_SPACING);
+        container.pack_start(&profile_box, false, false, 0);
         container.pack_start(&stackswitcher, false, false, 0);
         container.pack_start(&stack, false, false, 0);
         container.pack_start(&position_fen, false, false, 0);
         container.pack_start(&depth_box, false, false, 0);
+        container.pack_start(&variant_box, false, false, 0);
+        container.pack_start(&book_path, false, false, 0);
         container.pack_start(&eval_button, false, false, 0);
-        window.add(&container);
+        let review_button = Button::new_with_label(REVIEW_TEXT);
+        container.pack_start(&review_button, false, false, 0);
+        let live_button = ToggleButton::new_with_label(LIVE_TEXT);
+        container.pack_start(&live_button, false, false, 0);
+
+        let progress_bar = ProgressBar::new();
+        progress_bar.set_show_text(true);
+        progress_bar.set_text(Some(""));
+        container.pack_start(&progress_bar, false, false, 0);
+
+        let panel = AnalysisPanel::new();
+        let move_list = MoveList::new(&position_fen);
+        move_list.set_variant(profiles.active().variant());
+        controls
+            .variant
+            .connect_changed(clone!(@strong move_list, @strong profiles => move |_| {
+                move_list.set_variant(profiles.active().variant());
+            }));
+        let main_box = gtk::Box::new(Orientation::Horizontal, HORIZONTAL_SPACING);
+        main_box.pack_start(&container, false, false, 0);
+        main_box.pack_start(&move_list.container, false, false, 0);
+        main_box.pack_start(&panel.container, true, true, 0);
+        window.add(&main_box);
+
+        review_button.connect_clicked(
+            clone!(@weak window, @strong profiles, @strong progress_bar => move |review_button| {
+                let path = match choose_pgn(&window) {
+                    Some(path) => path,
+                    None => return,
+                };
+                let review_button = review_button.clone();
+                review_button.set_label(REVIEWING_TEXT);
+                review_button.set_sensitive(false);
+
+                let profile = profiles.active();
+
+                let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+                let (progress_tx, progress_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+                let progress = Arc::new(ProgressSender(Mutex::new(progress_tx)));
+                thread::spawn(move || {
+                    tx.send(review_pgn(&profile, &path, progress)).expect("Send failed");
+                });
+
+                progress_rx.attach(None, clone!(@strong progress_bar => move |event| {
+                    show_progress(&progress_bar, event);
+                    Continue(true)
+                }));
+                rx.attach(None, clone!(@strong window => move |result| {
+                    review_button.set_sensitive(true);
+                    review_button.set_label(REVIEW_TEXT);
+                    show_message(&window, result);
+                    Continue(true)
+                }));
+            }),
+        );
+
+        // The commands of the running continuous analysis
+        let live: Rc<RefCell<Option<mpsc::Sender<LiveCommand>>>> = Rc::new(RefCell::new(None));
+        live_button.connect_toggled(clone!(@strong live, @strong panel, @strong position_fen, @strong profiles => move |live_button| {
+            if !live_button.get_active() {
+                if let Some(commands) = live.borrow_mut().take() {
+                    let _ = commands.send(LiveCommand::Stop);
+                }
+                return;
+            }
+            let profile = profiles.active();
+            let fen = match valid_fen(&position_fen, profile.variant()) {
+                Some(fen) => fen,
+                None => {
+                    panel.show_summary("Invalid FEN");
+                    live_button.set_active(false);
+                    return;
+                }
+            };
+
+            let variant = profile.variant();
+            panel.set_position(&fen);
+            let (commands_tx, commands) = mpsc::channel();
+            let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            thread::spawn(move || {
+                if let Err(e) = live_analysis(&profile, &fen, &commands, &tx) {
+                    let _ = tx.send(Err(e));
+                }
+            });
+            *live.borrow_mut() = Some(commands_tx);
+
+            let live_button = live_button.clone();
+            rx.attach(None, clone!(@strong panel => move |update| {
+                match update {
+                    Ok((fen, info)) => {
+                        panel.set_position(&fen);
+                        panel.show_info(variant, &info);
+                    }
+                    Err(e) => {
+                        panel.show_summary(&format!("Error: {:?}", e));
+                        live_button.set_active(false);
+                    }
+                }
+                Continue(true)
+            }));
+        }));
+        // The continuous analysis follows the position
+        position_fen.connect_changed(
+            clone!(@strong live, @strong profiles => move |position_fen| {
+                if let Some(commands) = live.borrow().as_ref() {
+                    let variant = profiles.active().variant();
+                    if let Some(fen) = valid_fen(position_fen, variant) {
+                        let _ = commands.send(LiveCommand::Analyse(fen));
+                    }
+                }
+            }),
+        );
 
         eval_button.connect_clicked(clone!(@weak window => move |eval_button| {
             let eval_button = eval_button.clone();
@@ -136,64 +640,82 @@ impl App {
 
             let fen = position_fen.get_buffer().get_text();
             let fen2: Result<Fen, _> = fen.clone().parse();
-            let depth = depth.get_value_as_int() as u32;
+            let analysed_fen = fen.clone();
+            let profile = profiles.active();
+            let depth = profile.depth.expect("the depth is always set");
+            let variant = profile.variant();
 
             let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-
-            let engine = PathBuf::from(engine_path.get_buffer().get_text());
-            let wasm = PathBuf::from(wasm_path.get_buffer().get_text());
-            let js = PathBuf::from(js_path.get_buffer().get_text());
-            let workspace = PathBuf::from(workspace_path.get_buffer().get_text());
-            let datadir = PathBuf::from(datadir_path.get_buffer().get_text());
-
-            let visible_child = stack.get_visible_child_name().map(|s| s.as_str().to_owned());
+            let (progress_tx, progress_rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            let progress = Arc::new(ProgressSender(Mutex::new(progress_tx)));
 
             thread::spawn(move || {
-                let backend: Box<dyn UciBackend> = match visible_child.as_ref().map(String::as_str) {
-                    Some(NATIVE_PANE_NAME) => {
-                        Box::new(NativeUci::new(engine))
-                    },
-                    Some(GWASM_PANE_NAME) => {
-                        match GWasmUci::new(&wasm, &js, workspace, datadir) {
-                            Ok(back) => Box::new(back),
-                            Err(e) => {
-                                tx.send(Err(e)).expect("Send failed");
-                                return;
-                            }
+                if let Some(book) = profile.book.as_ref().filter(|_| variant == Variant::Standard) {
+                    match lookup_book(book, &fen) {
+                        Ok(Some(moves)) => {
+                            tx.send(Ok(Evaluation::Book(moves))).expect("Send failed");
+                            return;
                         }
-                    },
-                    x => panic!("Invalid pane name: {:?}", x),
+                        Ok(None) => {}
+                        Err(e) => {
+                            tx.send(Err(e)).expect("Send failed");
+                            return;
+                        }
+                    }
+                }
+                let mut backend = match profile.backend() {
+                    Ok(backend) => record_history(backend),
+                    Err(e) => {
+                        tx.send(Err(e)).expect("Send failed");
+                        return;
+                    }
                 };
-                let res = launch_golemate(backend, &fen, depth);
+                backend.set_progress_observer(progress);
+                let max_cost = backend.max_cost();
+                let res = launch_golemate(backend, variant, &fen, depth)
+                    .map(|output| Evaluation::Engine(output, max_cost));
                 tx.send(res).expect("Send failed");
             });
 
-            rx.attach(None, clone!(@strong window => move |val| {
+            // The search is shown as it goes, for the backends reporting it
+            let searched_fen = analysed_fen.clone();
+            progress_rx.attach(None, clone!(@strong progress_bar, @strong panel => move |event| {
+                match event {
+                    ProgressEvent::Info(info) => {
+                        if !info.pv.is_empty() && info.multipv.unwrap_or(1) == 1 {
+                            panel.set_position(&searched_fen);
+                            panel.show_info(variant, &info);
+                        }
+                    }
+                    event => show_progress(&progress_bar, event),
+                }
+                Continue(true)
+            }));
+
+            rx.attach(None, clone!(@strong window, @strong panel => move |val| {
                 eval_button.set_sensitive(true);
                 eval_button.set_label(EVALUATE_TEXT);
-                let dialog_type;
-                let dialog_body;
                 match val {
-                    Ok(output) => {
+                    Ok(Evaluation::Book(moves)) => {
+                        panel.set_position(&analysed_fen);
+                        panel.show_summary(&moves);
+                    }
+                    Ok(Evaluation::Engine(output, max_cost)) => {
                         let fen2 = fen2.clone().expect("internal error, invalid fen");
-                        let an_res = analysis::interpret_uci(fen2, output).expect("internal analysis error");
-                        dialog_body = an_res.describe();
-                        dialog_type = MessageType::Info;
-                    },
-                    Err(e) => {
-                        dialog_type = MessageType::Error;
-                        dialog_body = format!("Error: {:?}", e);
+                        match analysis::interpret_variant_uci(variant, fen2, output.clone()) {
+                            Ok(mut an_res) => {
+                                an_res.max_cost = max_cost;
+                                panel.set_position(&analysed_fen);
+                                if let Some(info) = analysis::final_lines(&output).first() {
+                                    panel.show_info(variant, info);
+                                }
+                                panel.show_summary(&an_res.describe());
+                            }
+                            Err(e) => show_message(&window, Err(e)),
+                        }
                     }
-                };
-                let dialog = MessageDialog::new(
-                    Some(&window),
-                    DialogFlags::empty(),
-                    dialog_type,
-                    ButtonsType::Ok,
-                    &dialog_body
-                );
-                dialog.run();
-                dialog.destroy();
+                    Err(e) => show_message(&window, Err(e)),
+                }
                 Continue(true)
             }));
         }));
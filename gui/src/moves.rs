@@ -0,0 +1,213 @@
+//! The move list: the game entered from the position of the FEN entry, with
+//! its variations, and the navigation between its positions
+//!
+//! The game follows the rules of chess and Chess960 only: with another
+//! variant selected, the list is disabled and leaves the FEN entry alone.
+
+use anyhow::{anyhow, Result};
+use glib::clone;
+use golemate::game::parse_move;
+use golemate::tree::GameTree;
+use golemate::variant::Variant;
+use gtk::prelude::*;
+use gtk::{Button, Entry, FlowBox, Orientation, PolicyType, ScrolledWindow, SelectionMode};
+use shakmaty::fen::Fen;
+use shakmaty::Chess;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+const SPACING: i32 = 6;
+/// The indentation of a variation, per level, in pixels
+const INDENT: i32 = 16;
+/// The width of the list, in pixels
+const WIDTH: i32 = 220;
+/// The style of the button of the selected position
+const SELECTED_CLASS: &str = "suggested-action";
+/// The style of the move entry holding an illegal move
+const ERROR_CLASS: &str = "error";
+
+/// Shows the game and follows the selected position in the FEN entry
+#[derive(Clone)]
+pub struct MoveList {
+    pub container: gtk::Box,
+    rows: gtk::Box,
+    fen_entry: Entry,
+    move_entry: Entry,
+    tree: Rc<RefCell<Option<GameTree>>>,
+    variant: Rc<Cell<Variant>>,
+}
+
+impl MoveList {
+    pub fn new(fen_entry: &Entry) -> Self {
+        let move_entry = Entry::new();
+        move_entry.set_placeholder_text(Some("Move (SAN or UCI)"));
+
+        let navigation = gtk::Box::new(Orientation::Horizontal, SPACING);
+        let first_button = Button::new_with_label("«");
+        let back_button = Button::new_with_label("‹");
+        let forward_button = Button::new_with_label("›");
+        let last_button = Button::new_with_label("»");
+        for button in &[&first_button, &back_button, &forward_button, &last_button] {
+            navigation.pack_start(*button, true, true, 0);
+        }
+
+        let rows = gtk::Box::new(Orientation::Vertical, 0);
+        let scrolled = ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled.set_policy(PolicyType::Never, PolicyType::Automatic);
+        scrolled.set_size_request(WIDTH, -1);
+        scrolled.add(&rows);
+
+        let container = gtk::Box::new(Orientation::Vertical, SPACING);
+        container.pack_start(&move_entry, false, false, 0);
+        container.pack_start(&navigation, false, false, 0);
+        container.pack_start(&scrolled, true, true, 0);
+
+        let list = Self {
+            container,
+            rows,
+            fen_entry: fen_entry.clone(),
+            move_entry: move_entry.clone(),
+            tree: Rc::new(RefCell::new(None)),
+            variant: Rc::new(Cell::new(Variant::Standard)),
+        };
+        if fen_entry.get_buffer().get_text().is_empty() {
+            *list.tree.borrow_mut() = Some(GameTree::new(Chess::default()));
+            list.update();
+        } else {
+            list.follow_fen();
+        }
+
+        fen_entry.connect_changed(clone!(@strong list => move |_| list.follow_fen()));
+        move_entry.connect_activate(clone!(@strong list => move |move_entry| {
+            let text = move_entry.get_buffer().get_text();
+            match list.play(&text) {
+                Ok(()) => move_entry.set_text(""),
+                Err(e) => list.show_error(Some(&format!("{:#}", e))),
+            }
+        }));
+        move_entry.connect_changed(clone!(@strong list => move |_| list.show_error(None)));
+        first_button.connect_clicked(clone!(@strong list => move |_| {
+            list.navigate(GameTree::first);
+        }));
+        back_button.connect_clicked(clone!(@strong list => move |_| {
+            list.navigate(|tree| {
+                tree.back();
+            });
+        }));
+        forward_button.connect_clicked(clone!(@strong list => move |_| {
+            list.navigate(|tree| {
+                tree.forward();
+            });
+        }));
+        last_button.connect_clicked(clone!(@strong list => move |_| {
+            list.navigate(GameTree::last);
+        }));
+        list
+    }
+
+    /// Follows the variant selected in the settings. The list is disabled
+    /// for the variants it cannot play.
+    pub fn set_variant(&self, variant: Variant) {
+        self.variant.set(variant);
+        if plays(variant) {
+            self.container.set_sensitive(true);
+            self.follow_fen();
+        } else {
+            self.container.set_sensitive(false);
+            *self.tree.borrow_mut() = None;
+            self.show_error(None);
+            self.update();
+        }
+    }
+
+    /// Plays a move in SAN or UCI in the selected position
+    fn play(&self, text: &str) -> Result<()> {
+        let mut played = Err(anyhow!("no game to play the move in"));
+        self.navigate(|tree| {
+            played = parse_move(tree.position(), text.trim())
+                .and_then(|m| tree.play(&m))
+                .map(drop);
+        });
+        played
+    }
+
+    /// Marks the move entry as holding an illegal move, with `error` as its
+    /// tooltip, or clears the mark
+    fn show_error(&self, error: Option<&str>) {
+        let style = self.move_entry.get_style_context();
+        match error {
+            Some(_) => style.add_class(ERROR_CLASS),
+            None => style.remove_class(ERROR_CLASS),
+        }
+        self.move_entry.set_tooltip_text(error);
+    }
+
+    fn navigate(&self, action: impl FnOnce(&mut GameTree)) {
+        if let Some(tree) = self.tree.borrow_mut().as_mut() {
+            action(tree);
+        }
+        self.update();
+    }
+
+    /// Starts a new game when the FEN is edited. The FENs which don't
+    /// describe a legal position, e.g. while they're typed, are ignored.
+    fn follow_fen(&self) {
+        if !plays(self.variant.get()) {
+            return;
+        }
+        let text = self.fen_entry.get_buffer().get_text();
+        if let Some(tree) = self.tree.borrow().as_ref() {
+            if tree.fen() == text {
+                return;
+            }
+        }
+        let tree = match text.parse::<Fen>() {
+            Ok(fen) => GameTree::from_fen(&fen).ok(),
+            Err(_) => None,
+        };
+        if tree.is_some() {
+            *self.tree.borrow_mut() = tree;
+            self.update();
+        }
+    }
+
+    /// Shows the moves, and the selected position in the FEN entry
+    fn update(&self) {
+        for child in self.rows.get_children() {
+            self.rows.remove(&child);
+        }
+        let (rows, current, fen) = match self.tree.borrow().as_ref() {
+            Some(tree) => (tree.rows(), tree.current(), tree.fen()),
+            None => return,
+        };
+        for row in rows {
+            let moves = FlowBox::new();
+            moves.set_selection_mode(SelectionMode::None);
+            moves.set_margin_start(INDENT * row.depth as i32);
+            for (node, label) in row.moves {
+                let button = Button::new_with_label(&label);
+                button.set_relief(gtk::ReliefStyle::None);
+                if node == current {
+                    button.get_style_context().add_class(SELECTED_CLASS);
+                }
+                let list = self.clone();
+                button.connect_clicked(move |_| {
+                    list.navigate(|tree| {
+                        tree.select(node).expect("the node is in the tree");
+                    });
+                });
+                moves.insert(&button, -1);
+            }
+            self.rows.pack_start(&moves, false, false, 0);
+        }
+        self.rows.show_all();
+        if self.fen_entry.get_buffer().get_text() != fen {
+            self.fen_entry.set_text(&fen);
+        }
+    }
+}
+
+/// Whether the list can play games of `variant`
+fn plays(variant: Variant) -> bool {
+    variant == Variant::Standard || variant == Variant::Chess960
+}
@@ -0,0 +1,248 @@
+//! The analysis panel: an evaluation bar, the statistics of the search and
+//! its principal variation, whose positions can be previewed on the board
+
+use glib::clone;
+use golemate::analysis::{self, PvMove, SearchInfo};
+use golemate::variant::Variant;
+use gtk::prelude::*;
+use gtk::{Button, FlowBox, Label, Orientation, ProgressBar, SelectionMode};
+use shakmaty::fen::Fen;
+use shakmaty::Color;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SPACING: i32 = 6;
+const POSITION_TEXT: &str = "Analysed position";
+
+#[derive(Default)]
+struct PanelState {
+    /// The FEN of the analysed position
+    fen: String,
+    /// The caption and the FEN of the previewed move of the variation
+    preview: Option<(String, String)>,
+}
+
+/// Shows the analysis of a position, updated as the search goes on
+#[derive(Clone)]
+pub struct AnalysisPanel {
+    pub container: gtk::Box,
+    bar: ProgressBar,
+    board: Label,
+    caption: Label,
+    stats: Label,
+    pv: FlowBox,
+    summary: Label,
+    state: Rc<RefCell<PanelState>>,
+}
+
+impl AnalysisPanel {
+    pub fn new() -> Self {
+        // White's share of the bar, from the bottom
+        let bar = ProgressBar::new();
+        bar.set_orientation(Orientation::Vertical);
+        bar.set_inverted(true);
+        bar.set_show_text(true);
+
+        let board = Label::new(None);
+        let caption = Label::new(None);
+        let back_button = Button::new_with_label(POSITION_TEXT);
+        let stats = Label::new(None);
+        let pv = FlowBox::new();
+        pv.set_selection_mode(SelectionMode::None);
+        let summary = Label::new(None);
+        summary.set_line_wrap(true);
+        summary.set_selectable(true);
+
+        let details = gtk::Box::new(Orientation::Vertical, SPACING);
+        details.pack_start(&board, false, false, 0);
+        details.pack_start(&caption, false, false, 0);
+        details.pack_start(&back_button, false, false, 0);
+        details.pack_start(&stats, false, false, 0);
+        details.pack_start(&pv, false, false, 0);
+        details.pack_start(&summary, false, false, 0);
+        let container = gtk::Box::new(Orientation::Horizontal, SPACING);
+        container.pack_start(&bar, false, false, 0);
+        container.pack_start(&details, true, true, 0);
+
+        let panel = Self {
+            container,
+            bar,
+            board,
+            caption,
+            stats,
+            pv,
+            summary,
+            state: Rc::new(RefCell::new(PanelState::default())),
+        };
+        panel.clear();
+        back_button.connect_clicked(clone!(@strong panel => move |_| {
+            panel.state.borrow_mut().preview = None;
+            panel.show_board();
+        }));
+        panel
+    }
+
+    /// Shows the analysis of `fen`, starting afresh if it's another position
+    pub fn set_position(&self, fen: &str) {
+        if self.state.borrow().fen == fen {
+            return;
+        }
+        *self.state.borrow_mut() = PanelState {
+            fen: fen.to_owned(),
+            preview: None,
+        };
+        self.clear();
+    }
+
+    fn clear(&self) {
+        self.bar.set_fraction(0.5);
+        self.bar.set_text(Some(""));
+        self.stats.set_text("");
+        self.summary.set_text("");
+        self.show_pv(Vec::new());
+        self.show_board();
+    }
+
+    /// Shows the latest `info` line of the search of the position
+    pub fn show_info(&self, variant: Variant, info: &SearchInfo) {
+        let text = self.state.borrow().fen.clone();
+        let fen: Fen = match text.parse() {
+            Ok(fen) => fen,
+            Err(_) => return,
+        };
+        if let Some(score) = info.score {
+            self.bar.set_fraction(score.white_chance(fen.turn) / 100.0);
+            self.bar.set_text(Some(&score.describe_for_white(fen.turn)));
+        }
+        self.stats.set_text(&describe_stats(info));
+        if let Ok(moves) = analysis::pv_moves(variant, &fen, &info.pv) {
+            let fullmoves = text
+                .split_whitespace()
+                .nth(5)
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(1);
+            let labels = numbered(&moves, fen.turn, fullmoves);
+            self.show_pv(labels.into_iter().zip(moves).collect());
+        }
+    }
+
+    /// Shows a description of the result, e.g. the best move or the book
+    /// moves
+    pub fn show_summary(&self, text: &str) {
+        self.summary.set_text(text);
+    }
+
+    fn show_pv(&self, moves: Vec<(String, PvMove)>) {
+        for child in self.pv.get_children() {
+            self.pv.remove(&child);
+        }
+        for (label, pv_move) in moves {
+            let button = Button::new_with_label(&label);
+            let panel = self.clone();
+            button.connect_clicked(move |_| {
+                let caption = format!("After {}", label);
+                panel.state.borrow_mut().preview = Some((caption, pv_move.fen.clone()));
+                panel.show_board();
+            });
+            self.pv.insert(&button, -1);
+        }
+        self.pv.show_all();
+    }
+
+    /// Draws the previewed position, or the analysed one
+    fn show_board(&self) {
+        let state = self.state.borrow();
+        let (caption, fen) = match &state.preview {
+            Some((caption, fen)) => (caption.as_str(), fen.as_str()),
+            None => (POSITION_TEXT, state.fen.as_str()),
+        };
+        self.board.set_markup(&board_markup(fen));
+        self.caption.set_text(caption);
+    }
+}
+
+/// The depth, the nodes and the speed of the search
+fn describe_stats(info: &SearchInfo) -> String {
+    let mut parts = Vec::new();
+    if let Some(depth) = info.depth {
+        parts.push(format!("Depth {}", depth));
+    }
+    if let Some(nodes) = info.nodes {
+        parts.push(format!("{} nodes", nodes));
+    }
+    if let Some(nps) = info.nps {
+        parts.push(format!("{} nps", nps));
+    }
+    parts.join(", ")
+}
+
+/// The moves with their numbers, e.g. `12. Nf3`, and `12... e5` when the
+/// variation starts with a move of Black
+fn numbered(moves: &[PvMove], turn: Color, fullmoves: u32) -> Vec<String> {
+    let mut number = fullmoves;
+    let mut white = turn == Color::White;
+    let mut labels = Vec::new();
+    for (i, m) in moves.iter().enumerate() {
+        labels.push(if white {
+            format!("{}. {}", number, m.san)
+        } else if i == 0 {
+            format!("{}... {}", number, m.san)
+        } else {
+            m.san.clone()
+        });
+        if !white {
+            number += 1;
+        }
+        white = !white;
+    }
+    labels
+}
+
+/// The board of the FEN drawn with the chess symbols, White at the bottom
+fn board_markup(fen: &str) -> String {
+    // The pockets of Crazyhouse follow the board
+    let board = fen
+        .split(|c| c == ' ' || c == '[')
+        .next()
+        .unwrap_or_default();
+    let mut rows = Vec::new();
+    for rank in board.split('/').take(8) {
+        let mut squares = Vec::new();
+        for c in rank.chars() {
+            match c.to_digit(10) {
+                Some(empty) => squares.extend((0..empty).map(|_| '·')),
+                None => squares.extend(piece_symbol(c)),
+            }
+        }
+        rows.push(
+            squares
+                .iter()
+                .map(char::to_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+    format!(
+        "<span font_family=\"monospace\" size=\"xx-large\">{}</span>",
+        rows.join("\n")
+    )
+}
+
+fn piece_symbol(c: char) -> Option<char> {
+    let symbol = match c {
+        'K' => '♔',
+        'Q' => '♕',
+        'R' => '♖',
+        'B' => '♗',
+        'N' => '♘',
+        'P' => '♙',
+        'k' => '♚',
+        'q' => '♛',
+        'r' => '♜',
+        'b' => '♝',
+        'n' => '♞',
+        'p' => '♟',
+        _ => return None,
+    };
+    Some(symbol)
+}
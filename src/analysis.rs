@@ -1,21 +1,47 @@
 use crate::backends::UciOutput;
-use anyhow::Result;
-use shakmaty::{fen::Fen, uci::Uci, Color, Move};
+use crate::variant::Variant;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use shakmaty::san::SanPlus;
+use shakmaty::variants::{
+    Atomic, Crazyhouse, Giveaway, Horde, KingOfTheHill, RacingKings, ThreeCheck,
+};
+use shakmaty::{fen, fen::Fen, uci::Uci, Chess, Color, FromSetup, Move, Position};
 use std::convert::TryInto;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Advantage {
     Centipawns(u32),
     /// mate in # of moves
     Mate(u32),
+    /// a win according to the tablebase, with the distance to zeroing the
+    /// 50-move counter in # of plies
+    TablebaseWin(u32),
     Equality,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalysisSource {
+    Engine,
+    Tablebase,
+}
+
 pub struct AnalysisResult {
     pub advantage_side: Color,
     pub advantage: Advantage,
     pub depth: u32,
     pub pv: Vec<Uci>,
     pub best_move: Move,
+    /// The reply expected by the engine, to think about on the opponent's time
+    pub ponder: Option<Move>,
+    pub source: AnalysisSource,
+    /// The variant searched, which decides how castling is written in UCI
+    pub variant: Variant,
+    /// The most the search may have cost in GNT, when it's run on Golem.
+    /// The amount actually paid isn't reported by gwasm-api.
+    pub max_cost: Option<f64>,
 }
 
 impl AnalysisResult {
@@ -25,19 +51,278 @@ impl AnalysisResult {
             Equality => "The position is equal.".to_owned(),
             Mate(moves) => format!("{:?} has a mate in {} moves", self.advantage_side, moves),
             Centipawns(cp) => format!("{:?} has {} centipawns advantage", self.advantage_side, cp),
+            TablebaseWin(dtz) => format!(
+                "{:?} wins, zeroing the 50-move counter in {} plies",
+                self.advantage_side, dtz
+            ),
         }
     }
 
     pub fn describe(&self) -> String {
-        format!(
-            "Analysis depth: {}.\n{}.\nThe best move is {}.",
-            self.depth,
+        let origin = match self.source {
+            AnalysisSource::Engine => format!("Analysis depth: {}", self.depth),
+            AnalysisSource::Tablebase => "Tablebase result".to_owned(),
+        };
+        let mut text = format!(
+            "{}.\n{}.\nThe best move is {}.",
+            origin,
             self.describe_advantage(),
             self.best_move
-        )
+        );
+        if let Some(ponder) = &self.ponder {
+            text.push_str(&format!("\nThe expected reply is {}.", ponder));
+        }
+        if let Some(max_cost) = self.max_cost {
+            text.push_str(&format!("\nThe search cost at most {:.6} GNT.", max_cost));
+        }
+        text
+    }
+}
+
+/// The name of the color in the JSON outputs, `white` or `black`
+pub fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+/// Serializes a color by its name, for `#[serde(serialize_with)]`
+pub fn serialize_color<S: Serializer>(
+    color: &Color,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(color_name(*color))
+}
+
+/// The moves are written in UCI, the side as `white` or `black`
+impl Serialize for AnalysisResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let pv: Vec<_> = self.pv.iter().map(ToString::to_string).collect();
+        let mut result = serializer.serialize_struct("AnalysisResult", 9)?;
+        result.serialize_field("best_move", &self.variant.uci(&self.best_move).to_string())?;
+        let ponder = self
+            .ponder
+            .as_ref()
+            .map(|ponder| self.variant.uci(ponder).to_string());
+        result.serialize_field("ponder", &ponder)?;
+        result.serialize_field("pv", &pv)?;
+        result.serialize_field("depth", &self.depth)?;
+        result.serialize_field("advantage_side", color_name(self.advantage_side))?;
+        result.serialize_field("advantage", &self.advantage)?;
+        result.serialize_field("description", &self.describe_advantage())?;
+        result.serialize_field("source", &self.source)?;
+        result.serialize_field("max_cost", &self.max_cost)?;
+        result.end()
+    }
+}
+
+/// The winning chances in percent of the side an evaluation in centipawns
+/// is given for, following lichess
+pub fn win_chance(eval: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.003_682_08 * f64::from(eval)).exp()) - 1.0)
+}
+
+/// The score reported by the engine, from the point of view of the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Score {
+    Cp(i32),
+    /// mate in # of moves, negative if the side to move is mated
+    Mate(i32),
+}
+
+impl Score {
+    /// The winning chances of White in percent, `turn` being the side to
+    /// move. A mate counts as a sure result.
+    pub fn white_chance(self, turn: Color) -> f64 {
+        let chance = match self {
+            Score::Cp(cp) => win_chance(cp),
+            Score::Mate(moves) if moves > 0 => 100.0,
+            Score::Mate(_) => 0.0,
+        };
+        match turn {
+            Color::White => chance,
+            Color::Black => 100.0 - chance,
+        }
+    }
+
+    /// The score from the point of view of White, e.g. `+0.35` or `#-3`,
+    /// `turn` being the side to move
+    pub fn describe_for_white(self, turn: Color) -> String {
+        let sign = match turn {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+        match self {
+            Score::Cp(cp) => format!("{:+.2}", f64::from(sign * cp) / 100.0),
+            Score::Mate(moves) => format!("#{}", sign * moves),
+        }
     }
 }
 
+/// The fields of an `info` line, apart from the free-form ones
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seldepth: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub multipv: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<Score>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nodes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nps: Option<u64>,
+    /// The search time in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<u64>,
+    /// The principal variation in UCI notation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pv: Vec<String>,
+}
+
+impl SearchInfo {
+    /// Parses an `info` line, returning `None` for the other lines and for
+    /// the ones carrying none of the fields, like `info string`.
+    ///
+    /// Malformed values are skipped rather than rejected, the line is only
+    /// informative.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("info") {
+            return None;
+        }
+        let mut info = Self::default();
+        while let Some(word) = words.next() {
+            match word {
+                "string" => break,
+                "depth" => info.depth = words.next().and_then(|d| d.parse().ok()),
+                "seldepth" => info.seldepth = words.next().and_then(|d| d.parse().ok()),
+                "multipv" => info.multipv = words.next().and_then(|n| n.parse().ok()),
+                "nodes" => info.nodes = words.next().and_then(|n| n.parse().ok()),
+                "nps" => info.nps = words.next().and_then(|n| n.parse().ok()),
+                "time" => info.time = words.next().and_then(|t| t.parse().ok()),
+                "score" => {
+                    let kind = words.next();
+                    let value = words.next().and_then(|v| v.parse().ok());
+                    info.score = match (kind, value) {
+                        (Some("cp"), Some(cp)) => Some(Score::Cp(cp)),
+                        (Some("mate"), Some(moves)) => Some(Score::Mate(moves)),
+                        _ => None,
+                    };
+                }
+                "pv" => {
+                    info.pv = words.map(str::to_owned).collect();
+                    break;
+                }
+                // Single-word flags, e.g. the bounds of the score
+                "lowerbound" | "upperbound" => {}
+                _ => {
+                    words.next();
+                }
+            }
+        }
+        Some(info).filter(|info| *info != Self::default())
+    }
+
+    /// A summary such as `depth 12, +0.35, 150000 nodes, 900000 nps: e2e4 e7e5`,
+    /// with the score from the point of view of the side to move
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(depth) = self.depth {
+            parts.push(format!("depth {}", depth));
+        }
+        match self.score {
+            Some(Score::Cp(cp)) => parts.push(format!("{:+.2}", f64::from(cp) / 100.0)),
+            Some(Score::Mate(moves)) => parts.push(format!("#{}", moves)),
+            None => {}
+        }
+        if let Some(nodes) = self.nodes {
+            parts.push(format!("{} nodes", nodes));
+        }
+        if let Some(nps) = self.nps {
+            parts.push(format!("{} nps", nps));
+        }
+        let mut text = parts.join(", ");
+        if !self.pv.is_empty() {
+            text.push_str(": ");
+            text.push_str(&self.pv.join(" "));
+        }
+        text
+    }
+}
+
+/// The last line reported for every `multipv` index, the best one first.
+///
+/// The lines without an index count as the first one, as engines omit it
+/// when a single line is searched.
+pub fn final_lines(output: &[String]) -> Vec<SearchInfo> {
+    let mut lines: Vec<SearchInfo> = Vec::new();
+    for info in output.iter().filter_map(|line| SearchInfo::parse(line)) {
+        let index = info.multipv.unwrap_or(1) as usize;
+        if info.pv.is_empty() || index == 0 {
+            continue;
+        }
+        if lines.len() < index {
+            lines.resize(index, SearchInfo::default());
+        }
+        lines[index - 1] = info;
+    }
+    lines.retain(|line| !line.pv.is_empty());
+    lines
+}
+
+/// A move of a principal variation, with the position it leads to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PvMove {
+    pub san: String,
+    /// The FEN after the move
+    pub fen: String,
+}
+
+/// The principal variation `pv`, in UCI notation, in SAN with the position
+/// after every move. It stops before the first move which is illegal in the
+/// variant.
+pub fn pv_moves(variant: Variant, start: &Fen, pv: &[String]) -> Result<Vec<PvMove>> {
+    match variant {
+        Variant::Standard | Variant::Chess960 => play_pv::<Chess>(start, pv),
+        Variant::Antichess => play_pv::<Giveaway>(start, pv),
+        Variant::Atomic => play_pv::<Atomic>(start, pv),
+        Variant::Crazyhouse => play_pv::<Crazyhouse>(start, pv),
+        Variant::Horde => play_pv::<Horde>(start, pv),
+        Variant::KingOfTheHill => play_pv::<KingOfTheHill>(start, pv),
+        Variant::RacingKings => play_pv::<RacingKings>(start, pv),
+        Variant::ThreeCheck => play_pv::<ThreeCheck>(start, pv),
+    }
+}
+
+fn play_pv<P: Position + FromSetup>(start: &Fen, pv: &[String]) -> Result<Vec<PvMove>> {
+    let mut pos: P = start.position()?;
+    let mut moves = Vec::new();
+    for uci in pv {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => break,
+        };
+        let san = SanPlus::from_move_and_play_unchecked(&mut pos, &m);
+        moves.push(PvMove {
+            san: san.to_string(),
+            fen: fen::fen(&pos),
+        });
+    }
+    Ok(moves)
+}
+
 fn other_color(color: Color) -> Color {
     use Color::*;
     match color {
@@ -46,50 +331,82 @@ fn other_color(color: Color) -> Color {
     }
 }
 
-// TODO create a proper parser, this is too hacky.
-// There's a lot of expects here. All of them are protocol violations
-// and will be removed once we have a proper parser.
+fn next_arg<'a>(words: &mut impl Iterator<Item = &'a str>, name: &str) -> Result<&'a str> {
+    words
+        .next()
+        .with_context(|| format!("missing argument of `{}`", name))
+}
+
+fn parse_uci_move(word: &str) -> Result<Uci> {
+    word.parse()
+        .map_err(|_| anyhow!("invalid UCI move `{}`", word))
+}
+
 pub fn interpret_uci(startpos_fen: Fen, uci: UciOutput) -> Result<AnalysisResult> {
+    interpret_position::<Chess>(Variant::Standard, startpos_fen, uci)
+}
+
+/// Like `interpret_uci`, with the moves checked against the rules of the
+/// variant
+pub fn interpret_variant_uci(
+    variant: Variant,
+    startpos_fen: Fen,
+    uci: UciOutput,
+) -> Result<AnalysisResult> {
+    match variant {
+        Variant::Standard | Variant::Chess960 => {
+            interpret_position::<Chess>(variant, startpos_fen, uci)
+        }
+        Variant::Antichess => interpret_position::<Giveaway>(variant, startpos_fen, uci),
+        Variant::Atomic => interpret_position::<Atomic>(variant, startpos_fen, uci),
+        Variant::Crazyhouse => interpret_position::<Crazyhouse>(variant, startpos_fen, uci),
+        Variant::Horde => interpret_position::<Horde>(variant, startpos_fen, uci),
+        Variant::KingOfTheHill => interpret_position::<KingOfTheHill>(variant, startpos_fen, uci),
+        Variant::RacingKings => interpret_position::<RacingKings>(variant, startpos_fen, uci),
+        Variant::ThreeCheck => interpret_position::<ThreeCheck>(variant, startpos_fen, uci),
+    }
+}
+
+// TODO create a proper parser, this is too hacky.
+fn interpret_position<P: Position + FromSetup + Clone>(
+    variant: Variant,
+    startpos_fen: Fen,
+    uci: UciOutput,
+) -> Result<AnalysisResult> {
     let our_side = startpos_fen.turn;
-    let position: shakmaty::Chess = startpos_fen.position()?;
+    let position: P = startpos_fen.position()?;
 
     let mut pv = Vec::new();
     let mut depth = 0;
     let mut advantage = Advantage::Mate(0);
     let mut advantage_side = Color::White;
     let mut best_move = None;
+    let mut ponder = None;
     for line in uci {
-        if line.is_empty() {
-            continue;
-        }
         let mut words = line.split_whitespace();
-        let cmd = words.next().expect("line should be non-empty");
+        let cmd = match words.next() {
+            Some(cmd) => cmd,
+            None => continue,
+        };
         match cmd {
             "info" => {
-                loop {
-                    let cmdtype = words.next().expect("type should be non-empty");
+                while let Some(cmdtype) = words.next() {
                     match cmdtype {
-                        "String" => break,
+                        "string" => break,
                         "depth" => {
-                            depth = words
-                                .next()
-                                .expect("argument missing")
+                            depth = next_arg(&mut words, cmdtype)?
                                 .parse()
-                                .expect("parse error")
+                                .context("parsing the depth")?
                         }
                         "pv" => {
-                            pv = words
-                                .map(|w| w.parse().expect("UCI move parse error"))
-                                .collect();
+                            pv = words.map(parse_uci_move).collect::<Result<_>>()?;
                             break;
-                        } //
+                        }
                         "score" => {
-                            let sctype = words.next().expect("argument missing");
-                            let scval: i32 = words
-                                .next()
-                                .expect("argument missing")
+                            let sctype = next_arg(&mut words, cmdtype)?;
+                            let scval: i32 = next_arg(&mut words, cmdtype)?
                                 .parse()
-                                .expect("parse error");
+                                .context("parsing the score")?;
                             advantage_side = if scval < 0 {
                                 other_color(our_side.clone())
                             } else {
@@ -100,21 +417,32 @@ pub fn interpret_uci(startpos_fen: Fen, uci: UciOutput) -> Result<AnalysisResult
                                 _ if scval == 0 => Advantage::Equality,
                                 "cp" => Advantage::Centipawns(adv_val),
                                 "mate" => Advantage::Mate(adv_val),
-                                _ => panic!("unexpected score mode"),
+                                _ => bail!("unexpected score mode `{}`", sctype),
                             };
                         }
+                        "lowerbound" | "upperbound" => {}
                         _ => {
-                            words.next().expect("the skipped arg should be non-empty");
+                            next_arg(&mut words, cmdtype)?;
                         }
                     }
                 }
             }
             "bestmove" => {
-                let bmove = words.next().expect("argument missing");
-                let bmove: Uci = bmove.parse().expect("UCI move parse error");
+                let bmove = parse_uci_move(next_arg(&mut words, cmd)?)?;
                 let bmove = bmove
                     .to_move(&position)
-                    .expect("engine returned an illegalm move");
+                    .map_err(|_| anyhow!("the engine returned an illegal move {}", bmove))?;
+                // An illegal ponder move is only a bad guess, not an error
+                ponder = match (words.next(), words.next()) {
+                    (Some("ponder"), Some(word)) => {
+                        let mut after = position.clone();
+                        after.play_unchecked(&bmove);
+                        parse_uci_move(word)
+                            .ok()
+                            .and_then(|reply| reply.to_move(&after).ok())
+                    }
+                    _ => None,
+                };
                 best_move = Some(bmove)
             }
             _ => {}
@@ -126,6 +454,10 @@ pub fn interpret_uci(startpos_fen: Fen, uci: UciOutput) -> Result<AnalysisResult
         advantage_side,
         depth,
         pv,
-        best_move: best_move.expect("best move not set"),
+        best_move: best_move.context("the engine didn't return the best move")?,
+        ponder,
+        source: AnalysisSource::Engine,
+        variant,
+        max_cost: None,
     })
 }
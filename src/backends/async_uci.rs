@@ -0,0 +1,95 @@
+//! An asynchronous interface to the backends, enabled by the `async` feature
+//!
+//! The native engines are driven with the process I/O of tokio. Backends
+//! which only block, like gWASM waiting for Golem, run on the blocking
+//! thread pool of tokio, so that they don't stall the executor either.
+//! Dropping the future of an analysis cancels it, as far as the backend
+//! can be interrupted.
+
+use super::{Cancellation, ProgressObserver, UciBackend, UciInput, UciOption, UciOutput};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::warn;
+use std::sync::Arc;
+
+/// A backend usable from async code, running many analyses concurrently
+#[async_trait]
+pub trait AsyncUciBackend: UciBackend {
+    /// Like `execute_uci`, but without blocking the executor
+    async fn execute_async(&self, uci: UciInput) -> Result<UciOutput>;
+}
+
+#[async_trait]
+impl<B: AsyncUciBackend + ?Sized> AsyncUciBackend for Box<B> {
+    async fn execute_async(&self, uci: UciInput) -> Result<UciOutput> {
+        (**self).execute_async(uci).await
+    }
+}
+
+/// Runs a blocking backend on the blocking thread pool of tokio
+pub struct BlockingUci<B> {
+    backend: Arc<B>,
+}
+
+impl<B: UciBackend + 'static> BlockingUci<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend: Arc::new(backend),
+        }
+    }
+}
+
+impl<B: UciBackend + 'static> UciBackend for BlockingUci<B> {
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        self.backend.execute_uci(uci)
+    }
+
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        self.backend.get_uci_opts()
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        match Arc::get_mut(&mut self.backend) {
+            Some(backend) => backend.set_progress_observer(observer),
+            // A cancelled analysis may still be running
+            None => warn!("Cannot set the progress observer while the backend is busy"),
+        }
+    }
+
+    fn engine_id(&self) -> String {
+        self.backend.engine_id()
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        self.backend.max_cost()
+    }
+
+    fn execute_cancellable(&self, uci: UciInput, cancel: &Cancellation) -> Result<UciOutput> {
+        self.backend.execute_cancellable(uci, cancel)
+    }
+
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        self.backend.execute_batch(inputs)
+    }
+}
+
+#[async_trait]
+impl<B: UciBackend + 'static> AsyncUciBackend for BlockingUci<B> {
+    async fn execute_async(&self, uci: UciInput) -> Result<UciOutput> {
+        let backend = self.backend.clone();
+        let guard = CancelOnDrop(Cancellation::new());
+        let cancel = guard.0.clone();
+        tokio::task::spawn_blocking(move || backend.execute_cancellable(uci, &cancel))
+            .await
+            .context("the analysis thread has panicked")?
+    }
+}
+
+/// Cancels the blocking analysis when its future is dropped before it's done
+struct CancelOnDrop(Cancellation);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
@@ -1,22 +1,195 @@
-use super::{UciBackend, UciInput, UciOption, UciOutput};
-use anyhow::{anyhow, Context, Result};
+use super::{fingerprint, override_options, UciBackend, UciInput, UciOption, UciOutput};
+use super::{ignore_progress, Cancellation, KeepWorkspace};
+use super::{ProgressEvent, ProgressObserver, TaskCost};
+use anyhow::{anyhow, bail, Context, Result};
 use gwasm_api::prelude::*;
+use gwasm_api::task::{ComputedTask, Task};
+use gwasm_api::timeout::Timeout;
+use log::{info, warn};
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::{fs, io};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+/// The task settings used unless they're configured. Golemate always passes
+/// them to gwasm-api, so that the maximal cost of a task is known.
+pub const DEFAULT_TASK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+pub const DEFAULT_SUBTASK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+pub const DEFAULT_MAX_PRICE: f64 = 1.0;
+pub const DEFAULT_GOLEM_ADDRESS: &str = "127.0.0.1";
+pub const DEFAULT_GOLEM_PORT: u16 = 61001;
+/// How often a computation checks whether it has been cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Something that can compute a gWASM task, typically a Golem node.
+///
+/// This is the seam between the backend and the network: it gets a task
+/// laid out in the workspace and returns it once the outputs are in place,
+/// or fails soon after `cancel` is cancelled. Whatever still works on the
+/// task after that must hold on to `workspace`, which is removed once the
+/// last handle to it is dropped.
+pub trait GolemCompute: Send + Sync {
+    fn compute(
+        &self,
+        task: Task,
+        workspace: Arc<Workspace>,
+        progress: Arc<dyn ProgressObserver>,
+        cancel: &Cancellation,
+    ) -> Result<ComputedTask>;
+}
+
+/// Computes tasks on a Golem client over its RPC interface.
+pub struct GolemRpc {
+    datadir: PathBuf,
+    address: String,
+    port: u16,
+    mainnet: bool,
+}
+
+impl GolemRpc {
+    /// Connects to a testnet client listening on the default RPC port.
+    pub fn new(datadir: PathBuf) -> Self {
+        Self {
+            datadir,
+            address: DEFAULT_GOLEM_ADDRESS.to_owned(),
+            port: DEFAULT_GOLEM_PORT,
+            mainnet: false,
+        }
+    }
+
+    /// Sets the address of the client RPC interface.
+    pub fn address(mut self, address: String) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Sets the port of the client RPC interface.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Sets whether the client runs on the mainnet instead of the testnet.
+    pub fn mainnet(mut self, mainnet: bool) -> Self {
+        self.mainnet = mainnet;
+        self
+    }
+}
+
+/// gwasm-api can't abort a task once it's submitted, so a cancelled task
+/// is left to the client, which computes it until the task timeout and is
+/// still paid for. The computation is only waited for on a separate thread,
+/// so that the caller is released right away, and that thread keeps the
+/// workspace until gwasm-api is done with it.
+impl GolemCompute for GolemRpc {
+    fn compute(
+        &self,
+        task: Task,
+        workspace: Arc<Workspace>,
+        progress: Arc<dyn ProgressObserver>,
+        cancel: &Cancellation,
+    ) -> Result<ComputedTask> {
+        if cancel.is_cancelled() {
+            bail!("the task was cancelled");
+        }
+        let net = if self.mainnet {
+            Net::MainNet
+        } else {
+            Net::TestNet
+        };
+        let (datadir, address, port) = (self.datadir.clone(), self.address.clone(), self.port);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _workspace = workspace;
+            let task = compute(
+                datadir,
+                &address,
+                port,
+                net,
+                task,
+                ProgressTracker(progress),
+            );
+            // The receiver is gone if the task was cancelled
+            let _ = sender.send(task);
+        });
+        loop {
+            match receiver.recv_timeout(CANCEL_POLL_INTERVAL) {
+                Ok(task) => return Ok(task?),
+                Err(mpsc::RecvTimeoutError::Timeout) if cancel.is_cancelled() => {
+                    bail!("the task was cancelled, but the Golem client computes it anyway")
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    bail!("the gwasm-api thread has panicked")
+                }
+            }
+        }
+    }
+}
+
+/// The directory a task is laid out in. It's removed once the last handle
+/// to it is dropped, unless it's kept.
+pub struct Workspace {
+    path: PathBuf,
+    keep: AtomicBool,
+}
+
+impl Workspace {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn keep(&self) {
+        self.keep.store(true, Ordering::SeqCst);
+        info!("Keeping the workspace {}", self.path.display());
+    }
+}
+
+impl Drop for Workspace {
+    fn drop(&mut self) {
+        if !*self.keep.get_mut() {
+            if let Err(e) = fs::remove_dir_all(&self.path) {
+                warn!("Failed to remove the workspace: {}", e);
+            }
+        }
+    }
+}
 
 pub struct GWasmUci {
     wasm: Vec<u8>,
     js: Vec<u8>,
-    workspace: PathBuf,
-    datadir: PathBuf,
+    workspace_root: Option<PathBuf>,
+    keep_workspace: KeepWorkspace,
+    task_timeout: Duration,
+    subtask_timeout: Duration,
+    max_price: f64,
+    budget: Option<f64>,
+    replicas: u32,
+    options: Vec<UciOption>,
+    golem: Box<dyn GolemCompute>,
+    progress: Arc<dyn ProgressObserver>,
 }
 
 impl GWasmUci {
+    /// Every task gets a fresh workspace created in `workspace_root`,
+    /// or in the system temporary directory if it's `None`.
     pub fn new(
         wasm_path: &Path,
         js_path: &Path,
-        workspace: PathBuf,
+        workspace_root: Option<PathBuf>,
         datadir: PathBuf,
+    ) -> Result<Self> {
+        Self::with_golem(wasm_path, js_path, workspace_root, GolemRpc::new(datadir))
+    }
+
+    /// Like `new`, but computes the tasks using `golem` instead of a Golem client.
+    pub fn with_golem<G: GolemCompute + 'static>(
+        wasm_path: &Path,
+        js_path: &Path,
+        workspace_root: Option<PathBuf>,
+        golem: G,
     ) -> Result<Self> {
         use std::fs::read;
         let wasm = read(wasm_path).context("reading the engine WASM")?;
@@ -24,22 +197,96 @@ impl GWasmUci {
         Ok(Self {
             wasm,
             js,
-            workspace,
-            datadir,
+            workspace_root,
+            keep_workspace: KeepWorkspace::default(),
+            task_timeout: DEFAULT_TASK_TIMEOUT,
+            subtask_timeout: DEFAULT_SUBTASK_TIMEOUT,
+            max_price: DEFAULT_MAX_PRICE,
+            budget: None,
+            replicas: 1,
+            options: Vec::new(),
+            golem: Box::new(golem),
+            progress: ignore_progress(),
         })
     }
-}
 
-impl UciBackend for GWasmUci {
-    fn get_uci_opts(&self) -> Vec<UciOption<'static>> {
-        // TODO detect based on golem info
-        vec![UciOption {
-            name: "Hash",
-            value: 128,
-        }]
+    /// Sets whether the task workspaces are removed after the computation.
+    pub fn keep_workspace(mut self, keep: KeepWorkspace) -> Self {
+        self.keep_workspace = keep;
+        self
     }
 
-    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+    /// Sets the time after which the whole task is cancelled.
+    pub fn task_timeout(mut self, timeout: Duration) -> Self {
+        self.task_timeout = timeout;
+        self
+    }
+
+    /// Sets the time after which a subtask is given to another provider.
+    pub fn subtask_timeout(mut self, timeout: Duration) -> Self {
+        self.subtask_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximal price of an hour of computation, in GNT.
+    pub fn max_price(mut self, price: f64) -> Self {
+        self.max_price = price;
+        self
+    }
+
+    /// Refuses to submit tasks which may cost more than `budget` GNT.
+    pub fn budget(mut self, budget: Option<f64>) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Sets the number of copies of the analysis submitted as subtasks of
+    /// the task, so that a result comes even if some providers fail.
+    ///
+    /// Every copy is paid for: the maximal cost is multiplied by `replicas`.
+    /// The first result containing the best move is used, the results are
+    /// not compared.
+    pub fn replicas(mut self, replicas: u32) -> Self {
+        self.replicas = replicas.max(1);
+        self
+    }
+
+    /// Sets UCI options, overriding the defaults of the backend.
+    pub fn uci_options(mut self, options: Vec<UciOption>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The most the task may cost: every replica paid the maximal price
+    /// until the subtask timeout
+    fn task_max_cost(&self) -> f64 {
+        let hours = self.subtask_timeout.as_secs_f64() / 3600.0;
+        self.max_price * hours * f64::from(self.replicas)
+    }
+
+    fn create_workspace(&self) -> Result<Workspace> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("golemate-");
+        match &self.workspace_root {
+            Some(root) => {
+                fs::create_dir_all(root).context("creating the workspace root")?;
+                builder.tempdir_in(root)
+            }
+            None => builder.tempdir(),
+        }
+        .map(|dir| Workspace {
+            path: dir.into_path(),
+            keep: AtomicBool::new(false),
+        })
+        .context("creating the workspace")
+    }
+
+    fn compute_in(
+        &self,
+        workspace: Arc<Workspace>,
+        uci: UciInput,
+        cancel: &Cancellation,
+    ) -> Result<UciOutput> {
         let binary = GWasmBinary {
             js: &self.js,
             wasm: &self.wasm,
@@ -49,51 +296,138 @@ impl UciBackend for GWasmUci {
         uci.push_str("\n");
         let input = uci.as_bytes();
 
-        fs::create_dir(&self.workspace)
-            .map_err(|e| match e.kind() {
-                io::ErrorKind::AlreadyExists => anyhow!("Workspace already exists"),
-                _ => e.into(),
-            })
-            .context("creating the workspace")?;
-
-        let task = TaskBuilder::try_new(&self.workspace, binary)?
+        let mut builder = TaskBuilder::try_new(workspace.path(), binary)?
             .name("golemate")
-            .push_subtask_data(input)
-            .build()
-            .context("building the gwasm task")?;
-
-        // FIXME address, port
-        let computed_task = compute(
-            self.datadir.clone(),
-            "127.0.0.1",
-            61001,
-            Net::TestNet,
-            task,
-            ProgressTracker,
+            .bid(self.max_price)
+            .timeout(to_timeout(self.task_timeout)?)
+            .subtask_timeout(to_timeout(self.subtask_timeout)?);
+        for _ in 0..self.replicas {
+            builder = builder.push_subtask_data(input);
+        }
+        let task = builder.build().context("building the gwasm task")?;
+
+        let task = self
+            .golem
+            .compute(task, workspace, self.progress.clone(), cancel)
+            .context("computing the gwasm task")?;
+
+        self.progress.notify(ProgressEvent::Cost(TaskCost {
+            max: self.task_max_cost(),
+        }));
+        best_subtask_output(task)
+    }
+}
+
+impl UciBackend for GWasmUci {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        // TODO detect based on golem info
+        override_options(vec![UciOption::new("Hash", 128)], &self.options)
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        self.progress = observer;
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        Some(self.task_max_cost())
+    }
+
+    fn engine_id(&self) -> String {
+        format!(
+            "gwasm:{:016x}",
+            fingerprint(&[&self.wasm[..], &self.js[..]])
         )
-        .context("computing the gwasm task")?;
+    }
+
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        self.execute_cancellable(uci, &Cancellation::new())
+    }
+
+    fn execute_cancellable(&self, uci: UciInput, cancel: &Cancellation) -> Result<UciOutput> {
+        if let Some(budget) = self.budget {
+            let max_cost = self.task_max_cost();
+            if max_cost > budget {
+                bail!(
+                    "the task may cost up to {:.4} GNT, which exceeds the budget of {:.4} GNT",
+                    max_cost,
+                    budget
+                );
+            }
+        }
+
+        // A cancelled task may still be computed in the workspace, so it's
+        // only removed once the Golem client is done with it
+        let workspace = Arc::new(self.create_workspace()?);
+        let output = self.compute_in(workspace.clone(), uci, cancel);
+
+        let keep = match self.keep_workspace {
+            KeepWorkspace::Never => false,
+            KeepWorkspace::OnFailure => output.is_err(),
+            KeepWorkspace::Always => true,
+        };
+        if keep {
+            workspace.keep();
+        }
 
-        single_subtask_get_output(computed_task)
+        output
     }
 }
 
-fn single_subtask_get_output(task: gwasm_api::task::ComputedTask) -> Result<UciOutput> {
+fn to_timeout(duration: Duration) -> Result<Timeout> {
+    let secs = duration.as_secs();
+    let timeout = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    timeout
+        .parse()
+        .map_err(|_| anyhow!("invalid timeout {}", timeout))
+}
+
+fn subtask_get_output(subtask: &mut gwasm_api::task::ComputedSubtask) -> Result<UciOutput> {
     use std::io::BufRead;
     use std::io::Result as IoResult;
 
-    let mut subtask = task.subtasks;
-    assert_eq!(subtask.len(), 1);
-    let mut outputs = subtask[0].data.values_mut();
-    let output = outputs.next().expect("subtask values should be non-empty");
-    //outputs.next().expect_none("too many subtask values");
+    let mut outputs = subtask.data.values_mut();
+    let output = outputs.next().context("the subtask has no output")?;
+    if outputs.next().is_some() {
+        bail!("the subtask has too many outputs");
+    }
     let res: IoResult<Vec<_>> = output.lines().collect();
-    res.map_err(Into::into)
+    res.context("reading the subtask output")
 }
 
-struct ProgressTracker;
+/// Picks the output of the first subtask which found the best move.
+///
+/// All the subtasks are replicas of the same analysis.
+fn best_subtask_output(task: ComputedTask) -> Result<UciOutput> {
+    let mut fallback = None;
+    for mut subtask in task.subtasks {
+        let output = subtask_get_output(&mut subtask);
+        let has_best_move = match &output {
+            Ok(lines) => lines.iter().any(|l| l.starts_with("bestmove")),
+            Err(_) => false,
+        };
+        if has_best_move {
+            return output;
+        }
+        if fallback.is_none() {
+            fallback = Some(output);
+        }
+    }
+    fallback.unwrap_or_else(|| Err(anyhow!("the task has no subtasks")))
+}
+
+/// Forwards the progress reported by gwasm-api to the observer
+struct ProgressTracker(Arc<dyn ProgressObserver>);
 
 impl ProgressUpdate for ProgressTracker {
     fn update(&self, progress: f64) {
-        println!("Current progress = {}", progress);
+        self.0.notify(ProgressEvent::Progress(progress));
+    }
+
+    fn start(&self) {
+        self.0.notify(ProgressEvent::TaskSubmitted);
+    }
+
+    fn stop(&self) {
+        self.0.notify(ProgressEvent::TaskFinished);
     }
 }
@@ -0,0 +1,356 @@
+//! Resource limits and a sandbox for the native engines
+//!
+//! The limits are applied to the engine process between `fork` and `exec`:
+//! rlimits for the memory, the CPU affinity, Landlock (Linux 5.13 or newer)
+//! for the filesystem and a seccomp filter for the network. Apart from the
+//! wall-clock limit they're only supported on Linux.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceLimits {
+    /// The address space of the engine, in MiB
+    pub memory: Option<u64>,
+    /// The CPUs the engine may run on
+    pub cpus: Vec<usize>,
+    /// The time after which the engine is killed, in seconds
+    pub wall_clock: Option<u64>,
+    pub sandbox: Option<Sandbox>,
+}
+
+/// What a sandboxed engine may access
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sandbox {
+    /// Readable paths, besides the system directories and the directory
+    /// of the engine
+    pub read: Vec<PathBuf>,
+    /// Writable paths
+    pub write: Vec<PathBuf>,
+    /// Whether the engine may open network sockets
+    pub network: bool,
+}
+
+/// Always readable by a sandboxed engine, so that it can be loaded
+const SYSTEM_PATHS: [&str; 5] = ["/usr", "/lib", "/lib64", "/lib32", "/etc"];
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn wall_clock(&self) -> Option<Duration> {
+        self.wall_clock.map(Duration::from_secs)
+    }
+
+    /// Makes `cmd`, running `engine`, apply the limits to the child process.
+    ///
+    /// The wall-clock limit is left to the caller.
+    pub(crate) fn apply(&self, engine: &Path, cmd: &mut Command) -> Result<()> {
+        let limits = Self {
+            wall_clock: None,
+            ..self.clone()
+        };
+        if limits.is_empty() {
+            return Ok(());
+        }
+        #[cfg(target_os = "linux")]
+        {
+            linux::apply(&limits, engine, cmd)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (engine, cmd);
+            anyhow::bail!("the resource limits are only supported on Linux")
+        }
+    }
+}
+
+impl Sandbox {
+    fn readable_paths(&self, engine: &Path) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = SYSTEM_PATHS
+            .iter()
+            .map(PathBuf::from)
+            .filter(|path| path.exists())
+            .collect();
+        // The engine may be a symlink
+        let resolved = engine.canonicalize().ok();
+        for engine in std::iter::once(engine).chain(resolved.as_deref()) {
+            if let Some(dir) = engine.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                paths.push(dir.to_owned());
+            }
+        }
+        paths.extend(self.read.iter().cloned());
+        paths
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{ResourceLimits, Sandbox};
+    use anyhow::{bail, Context, Result};
+    use std::ffi::CString;
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::RawFd;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::Command;
+
+    const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    /// Every access right of the first Landlock ABI
+    const ACCESS_FS_ALL: u64 = (1 << 13) - 1;
+    const ACCESS_FS_READ: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+    /// The access rights that apply to files rather than directories
+    const ACCESS_FS_FILE: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// A Landlock ruleset, built before forking the engine
+    struct Ruleset(RawFd);
+
+    impl Drop for Ruleset {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.0) };
+        }
+    }
+
+    impl Ruleset {
+        fn new(engine: &Path, sandbox: &Sandbox) -> Result<Self> {
+            let attr = RulesetAttr {
+                handled_access_fs: ACCESS_FS_ALL,
+            };
+            let fd = unsafe {
+                libc::syscall(
+                    SYS_LANDLOCK_CREATE_RULESET,
+                    &attr as *const RulesetAttr,
+                    mem::size_of::<RulesetAttr>(),
+                    0,
+                )
+            };
+            if fd < 0 {
+                let err = io::Error::last_os_error();
+                return Err(err).context("the kernel doesn't support Landlock, cannot sandbox");
+            }
+            let ruleset = Self(fd as RawFd);
+            for path in sandbox.readable_paths(engine) {
+                ruleset.allow(&path, ACCESS_FS_READ)?;
+            }
+            for path in &sandbox.write {
+                ruleset.allow(path, ACCESS_FS_ALL)?;
+            }
+            Ok(ruleset)
+        }
+
+        fn allow(&self, path: &Path, access: u64) -> Result<()> {
+            let c_path = CString::new(path.as_os_str().as_bytes())?;
+            let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("opening {} for the sandbox", path.display()));
+            }
+            let rule = PathBeneathAttr {
+                allowed_access: if path.is_dir() {
+                    access
+                } else {
+                    access & ACCESS_FS_FILE
+                },
+                parent_fd: fd,
+            };
+            let res = unsafe {
+                libc::syscall(
+                    SYS_LANDLOCK_ADD_RULE,
+                    self.0,
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                )
+            };
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            if res < 0 {
+                return Err(err)
+                    .with_context(|| format!("allowing {} in the sandbox", path.display()));
+            }
+            Ok(())
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct SockFilter {
+        code: u16,
+        jt: u8,
+        jf: u8,
+        k: u32,
+    }
+
+    #[repr(C)]
+    struct SockFprog {
+        len: libc::c_ushort,
+        filter: *const SockFilter,
+    }
+
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JEQ_K: u16 = 0x15;
+    const BPF_JGE_K: u16 = 0x35;
+    const BPF_RET_K: u16 = 0x06;
+
+    const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+    /// Offsets in `struct seccomp_data`
+    const DATA_NR: u32 = 0;
+    const DATA_ARCH: u32 = 4;
+    const DATA_ARG0: u32 = 16;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+    /// The x32 system calls on x86_64
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    fn stmt(code: u16, k: u32) -> SockFilter {
+        SockFilter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+        SockFilter { code, jt, jf, k }
+    }
+
+    /// Fails the creation of the network sockets with `EACCES`
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn network_filter() -> Result<Vec<SockFilter>> {
+        Ok(vec![
+            stmt(BPF_LD_W_ABS, DATA_ARCH),
+            jump(BPF_JEQ_K, AUDIT_ARCH, 1, 0),
+            stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD_W_ABS, DATA_NR),
+            jump(BPF_JGE_K, X32_SYSCALL_BIT, 0, 1),
+            stmt(BPF_RET_K, SECCOMP_RET_KILL_PROCESS),
+            jump(BPF_JEQ_K, libc::SYS_socket as u32, 1, 0),
+            stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+            stmt(BPF_LD_W_ABS, DATA_ARG0),
+            jump(BPF_JEQ_K, libc::AF_INET as u32, 3, 0),
+            jump(BPF_JEQ_K, libc::AF_INET6 as u32, 2, 0),
+            jump(BPF_JEQ_K, libc::AF_PACKET as u32, 1, 0),
+            stmt(BPF_RET_K, SECCOMP_RET_ALLOW),
+            stmt(BPF_RET_K, SECCOMP_RET_ERRNO | libc::EACCES as u32),
+        ])
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn network_filter() -> Result<Vec<SockFilter>> {
+        bail!("cutting off the network isn't supported on this architecture")
+    }
+
+    fn check(res: libc::c_int) -> io::Result<()> {
+        if res < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(super) fn apply(limits: &ResourceLimits, engine: &Path, cmd: &mut Command) -> Result<()> {
+        let memory = match limits.memory {
+            Some(mib) => match mib.checked_mul(1024 * 1024) {
+                Some(bytes) => Some(bytes as libc::rlim_t),
+                None => bail!("the memory limit of {} MiB is too large", mib),
+            },
+            None => None,
+        };
+
+        let affinity = if limits.cpus.is_empty() {
+            None
+        } else {
+            let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+            for &cpu in &limits.cpus {
+                if cpu >= libc::CPU_SETSIZE as usize {
+                    bail!("invalid CPU number {}", cpu);
+                }
+                unsafe { libc::CPU_SET(cpu, &mut set) };
+            }
+            Some(set)
+        };
+
+        let ruleset = match &limits.sandbox {
+            Some(sandbox) => Some(Ruleset::new(engine, sandbox)?),
+            None => None,
+        };
+        let filter = match &limits.sandbox {
+            Some(sandbox) if !sandbox.network => Some(network_filter()?),
+            _ => None,
+        };
+
+        // Only async-signal-safe calls are allowed after forking, so
+        // everything has been prepared above
+        let child_setup = move || {
+            if let Some(bytes) = memory {
+                let limit = libc::rlimit {
+                    rlim_cur: bytes,
+                    rlim_max: bytes,
+                };
+                check(unsafe { libc::setrlimit(libc::RLIMIT_AS, &limit) })?;
+            }
+            if let Some(set) = &affinity {
+                check(unsafe {
+                    libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), set)
+                })?;
+            }
+            if ruleset.is_some() || filter.is_some() {
+                check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+            }
+            if let Some(ruleset) = &ruleset {
+                let res = unsafe { libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset.0, 0) };
+                check(res as libc::c_int)?;
+            }
+            if let Some(filter) = &filter {
+                let prog = SockFprog {
+                    len: filter.len() as libc::c_ushort,
+                    filter: filter.as_ptr(),
+                };
+                check(unsafe {
+                    libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER,
+                        &prog as *const SockFprog,
+                    )
+                })?;
+            }
+            Ok(())
+        };
+        unsafe { cmd.pre_exec(child_setup) };
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+//! An engine searching in the background: infinite analysis and pondering
+
+use super::session::{keyword, Session};
+use super::{ResourceLimits, UciOption, UciOutput};
+use crate::analysis::SearchInfo;
+use anyhow::{bail, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// What a live engine is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchState {
+    Idle,
+    Searching,
+    /// Thinking on the opponent's time, until `ponderhit` or `stop`
+    Pondering,
+}
+
+/// A line printed by the engine during a search
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiveEvent {
+    Info(SearchInfo),
+    /// The search is over, the moves are in UCI notation
+    BestMove {
+        best_move: String,
+        ponder: Option<String>,
+    },
+}
+
+/// A native engine kept running, whose searches are followed line by line.
+///
+/// Unlike the backends, which run a whole UCI session at once, the engine
+/// can search without a limit (`go infinite`) until it's stopped, and think
+/// on the opponent's time (`go ponder`).
+pub struct LiveEngine {
+    session: Session,
+    state: SearchState,
+    /// The output of the current search
+    output: UciOutput,
+    wall_clock: Option<Duration>,
+    /// When the current search exceeds the wall-clock limit, none while it
+    /// has no end, i.e. while it's infinite or pondering
+    deadline: Option<Instant>,
+}
+
+impl LiveEngine {
+    /// Starts the engine and sets its options.
+    ///
+    /// The wall-clock limit applies to the start, and to every search
+    /// once it has an end: from the `go` command of the searches with
+    /// limits, and from `ponderhit` or `stop` for the others.
+    pub fn start(
+        engine_path: &Path,
+        options: &[UciOption],
+        limits: &ResourceLimits,
+    ) -> Result<Self> {
+        let options = options.iter().map(UciOption::uci_set_msg).collect();
+        let wall_clock = limits.wall_clock();
+        let deadline = wall_clock.map(|limit| Instant::now() + limit);
+        Ok(Self {
+            session: Session::start(engine_path, limits, options, deadline)?,
+            state: SearchState::Idle,
+            output: Vec::new(),
+            wall_clock,
+            deadline: None,
+        })
+    }
+
+    pub fn state(&self) -> SearchState {
+        self.state
+    }
+
+    /// Analyses `position`, given as the arguments of the `position`
+    /// command such as `fen <FEN>`, until `stop` is called.
+    ///
+    /// The search running already, if any, is abandoned.
+    pub fn go_infinite(&mut self, position: &str) -> Result<()> {
+        self.go(position, "infinite", SearchState::Searching)
+    }
+
+    /// Searches `position` with `limits`, e.g. `depth 15` or
+    /// `movetime 1000`, and returns the output once the engine has found
+    /// the best move
+    pub fn search(&mut self, position: &str, limits: &str) -> Result<UciOutput> {
+        self.go(position, limits, SearchState::Searching)?;
+        self.start_clock();
+        self.wait()
+    }
+
+    /// Thinks on the opponent's time in `position`, the one after the
+    /// expected reply, with the `limits` of the search going on after
+    /// `ponderhit`
+    pub fn go_ponder(&mut self, position: &str, limits: &str) -> Result<()> {
+        self.go(
+            position,
+            &format!("ponder {}", limits),
+            SearchState::Pondering,
+        )
+    }
+
+    /// The opponent has played the expected move: the pondering becomes a
+    /// normal search
+    pub fn ponderhit(&mut self) -> Result<()> {
+        if self.state != SearchState::Pondering {
+            bail!("the engine isn't pondering");
+        }
+        self.session.send("ponderhit")?;
+        self.state = SearchState::Searching;
+        self.start_clock();
+        Ok(())
+    }
+
+    fn go(&mut self, position: &str, limits: &str, state: SearchState) -> Result<()> {
+        if self.state != SearchState::Idle {
+            self.stop()?;
+        }
+        self.output.clear();
+        self.session.send(&format!("position {}", position))?;
+        self.session.send(&format!("go {}", limits))?;
+        self.state = state;
+        self.deadline = None;
+        Ok(())
+    }
+
+    fn start_clock(&mut self) {
+        self.deadline = self.wall_clock.map(|limit| Instant::now() + limit);
+    }
+
+    /// Waits up to `timeout` for the next line of the search.
+    ///
+    /// Returns `None` on timeout, when no search is running, and for the
+    /// lines which are neither `info` nor `bestmove` ones.
+    pub fn next_event(&mut self, timeout: Duration) -> Result<Option<LiveEvent>> {
+        if self.state == SearchState::Idle {
+            return Ok(None);
+        }
+        let line = match self.session.next_line(timeout)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let event = if keyword(&line) == "bestmove" {
+            self.state = SearchState::Idle;
+            let mut words = line.split_whitespace().skip(1);
+            let best_move = words.next().unwrap_or_default().to_owned();
+            let ponder = match (words.next(), words.next()) {
+                (Some("ponder"), Some(ponder)) => Some(ponder.to_owned()),
+                _ => None,
+            };
+            Some(LiveEvent::BestMove { best_move, ponder })
+        } else {
+            SearchInfo::parse(&line).map(LiveEvent::Info)
+        };
+        self.output.push(line);
+        Ok(event)
+    }
+
+    /// Waits for the end of the search and returns its output, including
+    /// the lines already passed to `next_event`.
+    ///
+    /// An infinite search only ends with `stop`.
+    pub fn wait(&mut self) -> Result<UciOutput> {
+        match self.state {
+            SearchState::Pondering => bail!("the engine is pondering until `ponderhit` or `stop`"),
+            SearchState::Searching => {
+                let rest = self.session.read_until("bestmove", self.deadline)?;
+                self.output.extend(rest);
+                self.state = SearchState::Idle;
+            }
+            SearchState::Idle => {}
+        }
+        Ok(std::mem::take(&mut self.output))
+    }
+
+    /// Stops the search and returns its output, with the best move found
+    /// so far
+    pub fn stop(&mut self) -> Result<UciOutput> {
+        if self.state != SearchState::Idle {
+            self.session.send("stop")?;
+            self.state = SearchState::Searching;
+            if self.deadline.is_none() {
+                self.start_clock();
+            }
+        }
+        self.wait()
+    }
+}
@@ -1,22 +1,61 @@
+#[cfg(feature = "async")]
+mod async_uci;
+mod cancel;
 #[cfg(feature = "gwasm")]
 mod gwasm;
+mod limits;
+#[cfg(feature = "native")]
+mod live;
 #[cfg(feature = "native")]
 mod native;
+#[cfg(feature = "native")]
+mod pool;
+mod progress;
+#[cfg(feature = "native")]
+mod session;
+mod transcript;
+mod workspace;
 
+#[cfg(feature = "async")]
+pub use async_uci::*;
+pub use cancel::*;
 #[cfg(feature = "gwasm")]
 pub use gwasm::*;
+pub use limits::*;
+#[cfg(feature = "native")]
+pub use live::*;
 #[cfg(feature = "native")]
 pub use native::*;
+#[cfg(feature = "native")]
+pub use pool::*;
+pub use progress::*;
+pub use transcript::*;
+pub use workspace::*;
 
+use crate::variant::Variant;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-pub struct UciOption<'a> {
-    name: &'a str,
-    value: u32,
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UciOption {
+    name: String,
+    value: String,
 }
 
-impl UciOption<'_> {
-    fn uci_set_msg(&self) -> String {
+impl UciOption {
+    pub fn new<N: Into<String>, V: ToString>(name: N, value: V) -> Self {
+        Self {
+            name: name.into(),
+            value: value.to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn uci_set_msg(&self) -> String {
         format!("setoption name {} value {}", self.name, self.value)
     }
 }
@@ -24,18 +63,123 @@ impl UciOption<'_> {
 pub type UciInput = Vec<String>;
 pub type UciOutput = Vec<String>;
 
-pub trait UciBackend {
+/// Runs UCI sessions on a chess engine.
+///
+/// Backends are `Send + Sync`, so that a backend can be shared between
+/// threads analysing concurrently.
+pub trait UciBackend: Send + Sync {
     fn execute_uci(&self, uci: UciInput) -> Result<UciOutput>;
-    fn get_uci_opts(&self) -> Vec<UciOption<'static>>;
+    fn get_uci_opts(&self) -> Vec<UciOption>;
+    /// Sets the observer notified about the progress of `execute_uci`.
+    ///
+    /// Backends with nothing to report ignore it.
+    fn set_progress_observer(&mut self, _observer: Arc<dyn ProgressObserver>) {}
+    /// Identifies the engine run by the backend, changing whenever the engine does
+    fn engine_id(&self) -> String;
+    /// The most an `execute_uci` call may cost, in GNT, for the backends
+    /// which are paid for
+    fn max_cost(&self) -> Option<f64> {
+        None
+    }
+
+    /// Like `execute_uci`, but gives up once `cancel` is cancelled.
+    ///
+    /// Backends which can't be interrupted finish the session.
+    fn execute_cancellable(&self, uci: UciInput, _cancel: &Cancellation) -> Result<UciOutput> {
+        self.execute_uci(uci)
+    }
+
+    /// Executes independent UCI sessions, returning their outputs in order.
+    ///
+    /// Backends able to run them in parallel override this.
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        inputs
+            .into_iter()
+            .map(|uci| self.execute_uci(uci))
+            .collect()
+    }
 
     fn generate_uci(&self, fen: &str, depth: u32) -> UciInput {
+        self.generate_variant_uci(Variant::Standard, fen, depth)
+    }
+
+    fn generate_variant_uci(&self, variant: Variant, fen: &str, depth: u32) -> UciInput {
         let intro = vec!["uci".to_owned()];
         let outro = vec!["ucinewgame".to_owned(), "quit".to_owned()];
         let mut cmds = intro;
         cmds.extend(self.get_uci_opts().iter().map(UciOption::uci_set_msg));
+        cmds.extend(variant.uci_options().iter().map(UciOption::uci_set_msg));
         cmds.push(format!("position fen {}", fen));
         cmds.push(format!("go depth {}", depth));
         cmds.extend(outro);
         cmds
     }
 }
+
+impl<B: UciBackend + ?Sized> UciBackend for Box<B> {
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        (**self).execute_uci(uci)
+    }
+
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        (**self).get_uci_opts()
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        (**self).set_progress_observer(observer)
+    }
+
+    fn engine_id(&self) -> String {
+        (**self).engine_id()
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        (**self).max_cost()
+    }
+
+    fn execute_cancellable(&self, uci: UciInput, cancel: &Cancellation) -> Result<UciOutput> {
+        (**self).execute_cancellable(uci, cancel)
+    }
+
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        (**self).execute_batch(inputs)
+    }
+
+    fn generate_uci(&self, fen: &str, depth: u32) -> UciInput {
+        (**self).generate_uci(fen, depth)
+    }
+
+    fn generate_variant_uci(&self, variant: Variant, fen: &str, depth: u32) -> UciInput {
+        (**self).generate_variant_uci(variant, fen, depth)
+    }
+}
+
+/// The default options of a backend, with the ones set by the user taking
+/// precedence. UCI option names are case-insensitive.
+pub(crate) fn override_options(defaults: Vec<UciOption>, user: &[UciOption]) -> Vec<UciOption> {
+    let overridden = |option: &UciOption| {
+        user.iter()
+            .any(|o| o.name.eq_ignore_ascii_case(&option.name))
+    };
+    let mut options: Vec<_> = defaults.into_iter().filter(|o| !overridden(o)).collect();
+    options.extend(user.iter().cloned());
+    options
+}
+
+/// A stable 64-bit FNV-1a hash, suitable for naming files
+pub(crate) fn fingerprint(parts: &[&[u8]]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        for byte in part.iter() {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(PRIME);
+        }
+        // Separate the parts, so that ["ab", "c"] and ["a", "bc"] differ
+        hash ^= 0xff;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
@@ -1,62 +1,220 @@
-use super::{UciBackend, UciInput, UciOption, UciOutput};
-use anyhow::{Context, Result};
-use std::convert::TryInto;
-use std::path::PathBuf;
+use super::{ignore_progress, ProgressEvent, ProgressObserver, ResourceLimits};
+use super::{override_options, UciBackend, UciInput, UciOption, UciOutput};
+use crate::analysis::SearchInfo;
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a child with a wall-clock limit is checked
+const WAIT_INTERVAL: Duration = Duration::from_millis(20);
 
 /// Runs a client locally
 pub struct NativeUci {
     engine_path: PathBuf,
+    options: Vec<UciOption>,
+    limits: ResourceLimits,
+    progress: Arc<dyn ProgressObserver>,
 }
 
 impl NativeUci {
     pub fn new(engine_path: PathBuf) -> Self {
-        Self { engine_path }
+        Self {
+            engine_path,
+            options: Vec::new(),
+            limits: ResourceLimits::default(),
+            progress: ignore_progress(),
+        }
+    }
+
+    /// Sets UCI options, overriding the defaults of the backend.
+    pub fn uci_options(mut self, options: Vec<UciOption>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Restricts the resources of the engine process.
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
     }
 }
 
+/// The command running the engine with piped stdin and stdout, confined
+/// by `limits`
+fn engine_command(engine_path: &Path, limits: &ResourceLimits) -> Result<Command> {
+    let mut cmd = Command::new(engine_path);
+    cmd.stdin(Stdio::piped()).stdout(Stdio::piped());
+    limits.apply(engine_path, &mut cmd)?;
+    Ok(cmd)
+}
+
+pub(super) fn spawn_engine(engine_path: &Path, limits: &ResourceLimits) -> Result<Child> {
+    engine_command(engine_path, limits)?
+        .spawn()
+        .context("running the UCI engine")
+}
+
+/// The lines printed by an engine which has run a whole session
+fn session_output(output: Output) -> Result<UciOutput> {
+    use std::io::{self, BufRead, BufReader};
+
+    if output.status.success() {
+        let lines: io::Result<_> = BufReader::new(&output.stdout[..]).lines().collect();
+        lines.map_err(Into::into)
+    } else {
+        use anyhow::anyhow;
+        Err(anyhow!("error executing the engine FIXME"))
+    }
+}
+
+/// The number of threads an engine may use by default
+pub(super) fn available_cpus(limits: &ResourceLimits) -> usize {
+    match limits.cpus.len() {
+        0 => num_cpus::get(),
+        cpus => cpus,
+    }
+}
+
+pub(super) fn native_engine_id(engine_path: &Path) -> String {
+    // Rebuilding the engine changes its modification time
+    let modified = std::fs::metadata(engine_path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_secs());
+    format!("native:{}:{}", engine_path.display(), modified)
+}
+
+/// Waits for `child`, killing it after `limit` if any. `progress` is
+/// notified of the `info` lines as the engine prints them.
+fn wait_for_engine(
+    mut child: Child,
+    limit: Option<Duration>,
+    progress: Arc<dyn ProgressObserver>,
+) -> Result<Output> {
+    use std::io::{BufRead, BufReader};
+
+    drop(child.stdin.take());
+    let stdout = child.stdout.take().context("opening stdout")?;
+    let reader = std::thread::spawn(move || {
+        let mut stdout = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        loop {
+            let start = buf.len();
+            if stdout.read_until(b'\n', &mut buf)? == 0 {
+                return Ok::<_, std::io::Error>(buf);
+            }
+            let line = String::from_utf8_lossy(&buf[start..]);
+            if let Some(info) = SearchInfo::parse(&line) {
+                progress.notify(ProgressEvent::Info(info));
+            }
+        }
+    });
+    let status = match limit {
+        Some(limit) => {
+            let start = Instant::now();
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if start.elapsed() >= limit {
+                    child.kill().context("killing the engine")?;
+                    child.wait()?;
+                    bail!(
+                        "the engine exceeded its wall-clock limit of {} s",
+                        limit.as_secs()
+                    );
+                }
+                std::thread::sleep(WAIT_INTERVAL);
+            }
+        }
+        None => child.wait().context("waiting for the child process")?,
+    };
+    let stdout = reader.join().expect("the engine reader panicked")?;
+    Ok(Output {
+        status,
+        stdout,
+        stderr: Vec::new(),
+    })
+}
+
 impl UciBackend for NativeUci {
-    fn get_uci_opts(&self) -> Vec<UciOption<'static>> {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
         // TODO detect/set values
-        vec![
-            UciOption {
-                name: "Threads",
-                value: num_cpus::get().try_into().unwrap(),
-            },
-            UciOption {
-                name: "Hash",
-                value: 1024,
-            },
-        ]
+        let defaults = vec![
+            UciOption::new("Threads", available_cpus(&self.limits)),
+            UciOption::new("Hash", 1024),
+        ];
+        override_options(defaults, &self.options)
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        self.progress = observer;
+    }
+
+    fn engine_id(&self) -> String {
+        native_engine_id(&self.engine_path)
     }
 
     fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
-        use std::io::{self, BufRead, BufReader, LineWriter, Write};
-        use std::process::{Command, Stdio};
+        use std::io::{LineWriter, Write};
 
-        let mut child = Command::new(&self.engine_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()
-            .context("running the UCI engine")?;
+        let mut child = spawn_engine(&self.engine_path, &self.limits)?;
+        self.progress.notify(ProgressEvent::TaskSubmitted);
 
         {
             let stdin = child.stdin.as_mut().context("opening stdin")?;
             let mut stdin = LineWriter::new(stdin);
             for line in uci {
-                println!("{}", line);
+                debug!("{}", line);
                 writeln!(stdin, "{}", line)?;
             }
         }
 
-        let output = child
-            .wait_with_output()
-            .context("waiting for the child process")?;
-        if output.status.success() {
-            let lines: io::Result<_> = BufReader::new(&output.stdout[..]).lines().collect();
-            lines.map_err(Into::into)
-        } else {
-            use anyhow::anyhow;
-            Err(anyhow!("error executing the engine FIXME"))
+        let output = wait_for_engine(child, self.limits.wall_clock(), self.progress.clone())?;
+        self.progress.notify(ProgressEvent::TaskFinished);
+        session_output(output)
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl super::AsyncUciBackend for NativeUci {
+    async fn execute_async(&self, uci: UciInput) -> Result<UciOutput> {
+        use tokio::io::AsyncWriteExt;
+
+        let cmd = engine_command(&self.engine_path, &self.limits)?;
+        let mut child = tokio::process::Command::from(cmd)
+            .kill_on_drop(true)
+            .spawn()
+            .context("running the UCI engine")?;
+        self.progress.notify(ProgressEvent::TaskSubmitted);
+
+        let mut input = String::new();
+        for line in uci {
+            debug!("{}", line);
+            input.push_str(&line);
+            input.push('\n');
+        }
+        let mut stdin = child.stdin.take().context("opening stdin")?;
+        stdin.write_all(input.as_bytes()).await?;
+        drop(stdin);
+
+        let output = child.wait_with_output();
+        let output = match self.limits.wall_clock() {
+            Some(limit) => tokio::time::timeout(limit, output).await.map_err(|_| {
+                anyhow::anyhow!(
+                    "the engine exceeded its wall-clock limit of {} s",
+                    limit.as_secs()
+                )
+            })?,
+            None => output.await,
         }
+        .context("waiting for the child process")?;
+        self.progress.notify(ProgressEvent::TaskFinished);
+        session_output(output)
     }
 }
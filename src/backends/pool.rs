@@ -0,0 +1,216 @@
+use super::native::{available_cpus, native_engine_id};
+use super::session::{keyword, Session};
+use super::{ignore_progress, ProgressEvent, ProgressObserver, ResourceLimits};
+use super::{override_options, UciBackend, UciInput, UciOption, UciOutput};
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// The hash table shared by the sessions, in MiB
+const TOTAL_HASH: usize = 1024;
+const MIN_HASH: usize = 16;
+
+/// Runs sessions on a pool of engines kept running locally
+///
+/// The engines are started on the first use and restarted when a session
+/// needs other UCI options or after a failure. `execute_batch` spreads the
+/// sessions over the engines. Unless the `Threads` and `Hash` options are
+/// set, the CPUs and the hash table are split between the engines.
+pub struct EnginePool {
+    engine_path: PathBuf,
+    sessions: usize,
+    options: Vec<UciOption>,
+    limits: ResourceLimits,
+    progress: Arc<dyn ProgressObserver>,
+    queue: Mutex<Option<Queue>>,
+}
+
+/// The running engines, waiting for jobs
+struct Queue {
+    jobs: Sender<Job>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+struct Job {
+    index: usize,
+    uci: UciInput,
+    results: Sender<(usize, Result<UciOutput>)>,
+}
+
+impl EnginePool {
+    /// A pool of `sessions` engines
+    pub fn new(engine_path: PathBuf, sessions: usize) -> Self {
+        Self {
+            engine_path,
+            sessions: sessions.max(1),
+            options: Vec::new(),
+            limits: ResourceLimits::default(),
+            progress: ignore_progress(),
+            queue: Mutex::new(None),
+        }
+    }
+
+    /// Sets UCI options, overriding the defaults of the backend.
+    pub fn uci_options(mut self, options: Vec<UciOption>) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Restricts the resources of every engine process.
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn sessions(&self) -> usize {
+        self.sessions
+    }
+
+    fn submit(&self, inputs: Vec<UciInput>) -> Result<Receiver<(usize, Result<UciOutput>)>> {
+        let mut queue = self.queue.lock().expect("the engine pool is poisoned");
+        if queue.is_none() {
+            *queue = Some(self.start());
+        }
+        let queue = queue.as_ref().expect("the engine pool is started");
+
+        let (results, received) = mpsc::channel();
+        for (index, uci) in inputs.into_iter().enumerate() {
+            let job = Job {
+                index,
+                uci,
+                results: results.clone(),
+            };
+            queue
+                .jobs
+                .send(job)
+                .map_err(|_| anyhow!("the engine pool has stopped"))?;
+        }
+        Ok(received)
+    }
+
+    fn start(&self) -> Queue {
+        let (jobs, received) = mpsc::channel();
+        let received = Arc::new(Mutex::new(received));
+        let workers = (0..self.sessions)
+            .map(|_| {
+                let worker = Worker {
+                    engine_path: self.engine_path.clone(),
+                    limits: self.limits.clone(),
+                    session: None,
+                };
+                let jobs = received.clone();
+                thread::spawn(move || worker.run(&jobs))
+            })
+            .collect();
+        Queue { jobs, workers }
+    }
+}
+
+impl Drop for EnginePool {
+    fn drop(&mut self) {
+        let queue = self.queue.get_mut().ok().and_then(Option::take);
+        if let Some(Queue { jobs, workers }) = queue {
+            drop(jobs);
+            for worker in workers {
+                let _ = worker.join();
+            }
+        }
+    }
+}
+
+impl UciBackend for EnginePool {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        let threads = (available_cpus(&self.limits) / self.sessions).max(1);
+        let hash = (TOTAL_HASH / self.sessions).max(MIN_HASH);
+        let defaults = vec![
+            UciOption::new("Threads", threads),
+            UciOption::new("Hash", hash),
+        ];
+        override_options(defaults, &self.options)
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        self.progress = observer;
+    }
+
+    fn engine_id(&self) -> String {
+        native_engine_id(&self.engine_path)
+    }
+
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        self.execute_batch(vec![uci])
+            .pop()
+            .expect("every session has a result")
+    }
+
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        let total = inputs.len();
+        let received = match self.submit(inputs) {
+            Ok(received) => received,
+            Err(e) => return (0..total).map(|_| Err(anyhow!("{:#}", e))).collect(),
+        };
+        self.progress.notify(ProgressEvent::TaskSubmitted);
+        let mut results: Vec<Option<Result<UciOutput>>> = (0..total).map(|_| None).collect();
+        for (done, (index, result)) in received.iter().enumerate() {
+            results[index] = Some(result);
+            if total > 1 {
+                let progress = (done + 1) as f64 / total as f64;
+                self.progress.notify(ProgressEvent::Progress(progress));
+            }
+        }
+        self.progress.notify(ProgressEvent::TaskFinished);
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow!("the engine pool has stopped"))))
+            .collect()
+    }
+}
+
+/// Runs the jobs on one engine
+struct Worker {
+    engine_path: PathBuf,
+    limits: ResourceLimits,
+    session: Option<Session>,
+}
+
+impl Worker {
+    fn run(mut self, jobs: &Mutex<Receiver<Job>>) {
+        loop {
+            let job = match jobs.lock().expect("the job queue is poisoned").recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            let result = self.execute(&job.uci);
+            let failed = result.is_err();
+            let _ = job.results.send((job.index, result));
+            if failed {
+                // Start from scratch, the engine may be stuck
+                self.session = None;
+            }
+        }
+    }
+
+    fn execute(&mut self, uci: &[String]) -> Result<UciOutput> {
+        let deadline = self.limits.wall_clock().map(|limit| Instant::now() + limit);
+        let options: Vec<String> = uci
+            .iter()
+            .filter(|cmd| keyword(cmd) == "setoption")
+            .cloned()
+            .collect();
+        let reusable = self
+            .session
+            .as_ref()
+            .map_or(false, |session| session.options == options);
+        if !reusable {
+            // Stop the previous engine first, to free its memory
+            self.session = None;
+            let session = Session::start(&self.engine_path, &self.limits, options, deadline)?;
+            self.session = Some(session);
+        }
+        let session = self.session.as_mut().expect("the session is started");
+        session.run(uci, deadline)
+    }
+}
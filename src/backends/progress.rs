@@ -0,0 +1,58 @@
+use crate::analysis::SearchInfo;
+use std::sync::Arc;
+
+/// A step of a computation reported by a backend
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// The task has been handed over to the engine or to Golem
+    TaskSubmitted,
+    /// The overall progress of the task, between 0 and 1
+    Progress(f64),
+    /// What the task may cost, reported once it's computed
+    Cost(TaskCost),
+    /// An `info` line of the search, from the backends reading the output
+    /// of the engine as it comes
+    Info(SearchInfo),
+    TaskFinished,
+}
+
+/// The cost of a Golem task, in GNT.
+///
+/// Only the upper bound is known: gwasm-api doesn't report the payments
+/// made to the providers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskCost {
+    /// Following from the task settings
+    pub max: f64,
+}
+
+impl TaskCost {
+    pub fn describe(&self) -> String {
+        format!("Task cost: at most {:.6} GNT", self.max)
+    }
+}
+
+/// Receives the progress of the computations of a backend.
+///
+/// The events may be delivered from a thread other than the one
+/// which called `execute_uci`.
+pub trait ProgressObserver: Send + Sync {
+    fn notify(&self, event: ProgressEvent);
+}
+
+impl<F: Fn(ProgressEvent) + Send + Sync> ProgressObserver for F {
+    fn notify(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+/// The observer used when the caller doesn't supply one
+pub struct IgnoreProgress;
+
+impl ProgressObserver for IgnoreProgress {
+    fn notify(&self, _event: ProgressEvent) {}
+}
+
+pub(crate) fn ignore_progress() -> Arc<dyn ProgressObserver> {
+    Arc::new(IgnoreProgress)
+}
@@ -0,0 +1,152 @@
+//! Engine processes kept running, driven line by line
+
+use super::native::spawn_engine;
+use super::{ResourceLimits, UciOutput};
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long an engine may take to quit before it's killed
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An engine process kept running between the UCI sessions
+pub(super) struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    /// The `setoption` commands sent on start
+    pub(super) options: Vec<String>,
+    /// The reply to `uci`
+    handshake: Vec<String>,
+}
+
+impl Session {
+    pub(super) fn start(
+        engine_path: &Path,
+        limits: &ResourceLimits,
+        options: Vec<String>,
+        deadline: Option<Instant>,
+    ) -> Result<Self> {
+        let mut child = spawn_engine(engine_path, limits)?;
+        let stdin = child.stdin.take().context("opening stdin")?;
+        let stdout = child.stdout.take().context("opening stdout")?;
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let sent = line.map(|line| sender.send(line).is_ok());
+                if !sent.unwrap_or(false) {
+                    break;
+                }
+            }
+        });
+
+        let mut session = Self {
+            child,
+            stdin,
+            lines,
+            options,
+            handshake: Vec::new(),
+        };
+        session.send("uci")?;
+        session.handshake = session.read_until("uciok", deadline)?;
+        for option in session.options.clone() {
+            session.send(&option)?;
+        }
+        session.send("isready")?;
+        session.read_until("readyok", deadline)?;
+        Ok(session)
+    }
+
+    pub(super) fn send(&mut self, cmd: &str) -> Result<()> {
+        debug!("{}", cmd);
+        writeln!(self.stdin, "{}", cmd)
+            .and_then(|_| self.stdin.flush())
+            .context("writing to the engine")
+    }
+
+    /// Reads the output up to the line starting with `last`
+    pub(super) fn read_until(
+        &mut self,
+        last: &str,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<String>> {
+        let mut output = Vec::new();
+        loop {
+            let line = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    match self.lines.recv_timeout(timeout) {
+                        Ok(line) => line,
+                        Err(RecvTimeoutError::Timeout) => {
+                            bail!("the engine exceeded its wall-clock limit")
+                        }
+                        Err(RecvTimeoutError::Disconnected) => bail!("the engine has exited"),
+                    }
+                }
+                None => self
+                    .lines
+                    .recv()
+                    .map_err(|_| anyhow!("the engine has exited"))?,
+            };
+            let done = keyword(&line) == last;
+            output.push(line);
+            if done {
+                return Ok(output);
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next line of the output
+    pub(super) fn next_line(&mut self, timeout: Duration) -> Result<Option<String>> {
+        match self.lines.recv_timeout(timeout) {
+            Ok(line) => Ok(Some(line)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => bail!("the engine has exited"),
+        }
+    }
+
+    /// Runs the commands of a session, apart from the ones handled on start
+    pub(super) fn run(&mut self, uci: &[String], deadline: Option<Instant>) -> Result<UciOutput> {
+        let mut output = Vec::new();
+        for cmd in uci {
+            match keyword(cmd) {
+                "uci" => output.extend(self.handshake.iter().cloned()),
+                "setoption" | "quit" => {}
+                "go" => {
+                    self.send(cmd)?;
+                    output.extend(self.read_until("bestmove", deadline)?);
+                }
+                "isready" => {
+                    self.send(cmd)?;
+                    output.extend(self.read_until("readyok", deadline)?);
+                }
+                _ => self.send(cmd)?,
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let start = Instant::now();
+        while start.elapsed() < QUIT_TIMEOUT {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+pub(super) fn keyword(cmd: &str) -> &str {
+    cmd.split_whitespace().next().unwrap_or("")
+}
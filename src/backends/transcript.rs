@@ -0,0 +1,185 @@
+//! Transcripts of UCI sessions, to reproduce the output of an engine offline
+//!
+//! A transcript is a JSON Lines file, with a line per session: the engine,
+//! its options, the commands sent and the lines printed, or the error.
+
+use super::{ProgressObserver, UciBackend, UciInput, UciOption, UciOutput};
+use anyhow::{anyhow, bail, Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// A session of a transcript
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// The id of the recorded engine
+    pub engine: String,
+    /// The options of the recorded backend
+    pub options: Vec<UciOption>,
+    pub input: UciInput,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<UciOutput>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TranscriptEntry {
+    pub fn new(backend: &dyn UciBackend, input: UciInput, output: &Result<UciOutput>) -> Self {
+        let (output, error) = match output {
+            Ok(lines) => (Some(lines.clone()), None),
+            Err(e) => (None, Some(format!("{:#}", e))),
+        };
+        Self {
+            engine: backend.engine_id(),
+            options: backend.get_uci_opts(),
+            input,
+            output,
+            error,
+        }
+    }
+
+    /// The recorded output, or the recorded error
+    fn result(&self) -> Result<UciOutput> {
+        match (&self.output, &self.error) {
+            (Some(output), _) => Ok(output.clone()),
+            (None, Some(error)) => Err(anyhow!("{}", error)),
+            (None, None) => bail!("the transcript has neither an output nor an error"),
+        }
+    }
+}
+
+/// Reads all the sessions of a transcript file
+pub fn read_transcript(path: &Path) -> Result<Vec<TranscriptEntry>> {
+    let file = File::open(path).context("opening the transcript")?;
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.context("reading the transcript")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .with_context(|| format!("parsing line {} of the transcript", i + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Appends every session run by `backend` to a transcript file
+pub struct RecordUci<B> {
+    backend: B,
+    file: Mutex<File>,
+}
+
+impl<B: UciBackend> RecordUci<B> {
+    /// Records in `path`, after the sessions it may already hold
+    pub fn new(backend: B, path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening the transcript {}", path.display()))?;
+        Ok(Self {
+            backend,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn record(&self, input: UciInput, output: &Result<UciOutput>) {
+        let entry = TranscriptEntry::new(&self.backend, input, output);
+        if let Err(e) = self.write(&entry) {
+            warn!("Failed to record the session in the transcript: {:?}", e);
+        }
+    }
+
+    fn write(&self, entry: &TranscriptEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)?;
+        // A whole line at once, so that concurrent sessions don't interleave
+        let mut file = self.file.lock().expect("poisoned transcript lock");
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+impl<B: UciBackend> UciBackend for RecordUci<B> {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        self.backend.get_uci_opts()
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        self.backend.set_progress_observer(observer)
+    }
+
+    fn engine_id(&self) -> String {
+        self.backend.engine_id()
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        self.backend.max_cost()
+    }
+
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        let output = self.backend.execute_uci(uci.clone());
+        self.record(uci, &output);
+        output
+    }
+
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        let outputs = self.backend.execute_batch(inputs.clone());
+        for (uci, output) in inputs.into_iter().zip(&outputs) {
+            self.record(uci, output);
+        }
+        outputs
+    }
+}
+
+/// Serves the outputs recorded in a transcript instead of running an engine.
+///
+/// A session is answered with the first recorded session having exactly
+/// the same commands, so the options of the backend are the recorded ones,
+/// and the inputs it generates match the recorded inputs.
+pub struct ReplayUci {
+    engine: String,
+    options: Vec<UciOption>,
+    entries: Vec<TranscriptEntry>,
+}
+
+impl ReplayUci {
+    /// Replays `entries`, taking the engine and its options from the first one
+    pub fn new(entries: Vec<TranscriptEntry>) -> Result<Self> {
+        let first = entries.first().context("the transcript is empty")?;
+        Ok(Self {
+            engine: first.engine.clone(),
+            options: first.options.clone(),
+            entries,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let entries = read_transcript(path)
+            .with_context(|| format!("reading the transcript {}", path.display()))?;
+        Self::new(entries)
+    }
+}
+
+impl UciBackend for ReplayUci {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        self.options.clone()
+    }
+
+    fn engine_id(&self) -> String {
+        format!("replay:{}", self.engine)
+    }
+
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        match self.entries.iter().find(|entry| entry.input == uci) {
+            Some(entry) => entry.result(),
+            None => bail!(
+                "no recorded session matches the commands, the transcript has {} sessions",
+                self.entries.len()
+            ),
+        }
+    }
+}
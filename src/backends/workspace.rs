@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// When to keep the workspace of a gWASM task after it has been computed.
+///
+/// Known to every build, so that the configuration files are read alike
+/// whether the gWASM backend is enabled or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeepWorkspace {
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl Default for KeepWorkspace {
+    fn default() -> Self {
+        KeepWorkspace::Never
+    }
+}
@@ -0,0 +1,211 @@
+//! Measures the speed of an engine on a backend
+//!
+//! A fixed set of positions is searched to a fixed depth. The nodes and the
+//! search time come from the `info` lines of the engine, the rest of the
+//! wall-clock time is the overhead of the backend: starting the engine, or
+//! preparing the Golem task and collecting its results. For Golem, the time
+//! between the submission of the task and its completion is measured too.
+//! Golem only reports these two events, so the queueing, the transfers and
+//! the computation within this time can't be told apart.
+
+use crate::analysis::SearchInfo;
+use crate::backends::{ProgressEvent, ProgressObserver, UciBackend};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Positions from the Stockfish bench
+pub const BENCH_POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+];
+
+pub const DEFAULT_BENCH_DEPTH: u32 = 13;
+
+/// The measurements of a single position, times in milliseconds
+#[derive(Debug, Serialize)]
+pub struct PositionBench {
+    pub fen: String,
+    pub nodes: u64,
+    pub engine_time: u64,
+    pub wall_time: u64,
+    /// Between the submission of the Golem task and its completion, for the
+    /// Golem backend
+    pub golem_time: Option<u64>,
+}
+
+/// Times in milliseconds
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub engine: String,
+    pub depth: u32,
+    pub positions: Vec<PositionBench>,
+    pub nodes: u64,
+    pub engine_time: u64,
+    pub wall_time: u64,
+    pub nps: u64,
+}
+
+fn secs(ms: u64) -> f64 {
+    ms as f64 / 1000.0
+}
+
+impl BenchReport {
+    pub fn overhead(&self) -> u64 {
+        self.wall_time.saturating_sub(self.engine_time)
+    }
+
+    pub fn golem_time(&self) -> Option<u64> {
+        let times: Vec<_> = self.positions.iter().filter_map(|p| p.golem_time).collect();
+        if times.is_empty() {
+            None
+        } else {
+            Some(times.iter().sum())
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let count = self.positions.len().max(1) as u64;
+        let mut lines = vec![
+            format!("Benchmark of {} at depth {}", self.engine, self.depth),
+            format!("Positions: {}", self.positions.len()),
+            format!("Nodes: {}", self.nodes),
+            format!("Engine time: {:.3} s", secs(self.engine_time)),
+            format!("Nodes per second: {}", self.nps),
+            format!("Wall-clock time: {:.3} s", secs(self.wall_time)),
+            format!(
+                "Overhead: {:.3} s ({:.3} s per position)",
+                secs(self.overhead()),
+                secs(self.overhead() / count)
+            ),
+        ];
+        if let Some(golem_time) = self.golem_time() {
+            lines.push(format!(
+                "Time in Golem: {:.3} s (queueing, transfers and search)",
+                secs(golem_time)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Reads the nodes searched and the search time in milliseconds from the
+/// `info` lines, taking the largest values reported
+pub fn search_stats(output: &[String]) -> (u64, u64) {
+    let mut nodes = 0;
+    let mut time = 0;
+    for info in output.iter().filter_map(|line| SearchInfo::parse(line)) {
+        nodes = nodes.max(info.nodes.unwrap_or(0));
+        time = time.max(info.time.unwrap_or(0));
+    }
+    (nodes, time)
+}
+
+/// Records when the task is submitted and finished, passing the events on
+struct Timing {
+    forward: Arc<dyn ProgressObserver>,
+    submitted: Mutex<Option<Instant>>,
+    finished: Mutex<Option<Instant>>,
+}
+
+impl Timing {
+    fn reset(&self) {
+        *self.submitted.lock().expect("poisoned mutex") = None;
+        *self.finished.lock().expect("poisoned mutex") = None;
+    }
+
+    fn golem_time(&self) -> Option<Duration> {
+        let submitted = (*self.submitted.lock().expect("poisoned mutex"))?;
+        let finished = (*self.finished.lock().expect("poisoned mutex"))?;
+        Some(finished.saturating_duration_since(submitted))
+    }
+}
+
+impl ProgressObserver for Timing {
+    fn notify(&self, event: ProgressEvent) {
+        let now = Instant::now();
+        match event {
+            ProgressEvent::TaskSubmitted => {
+                self.submitted
+                    .lock()
+                    .expect("poisoned mutex")
+                    .get_or_insert(now);
+            }
+            ProgressEvent::TaskFinished => {
+                self.finished
+                    .lock()
+                    .expect("poisoned mutex")
+                    .get_or_insert(now);
+            }
+            _ => {}
+        }
+        self.forward.notify(event);
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+/// Searches the bench positions on the backend, which shouldn't be cached.
+///
+/// The progress events are passed on to `progress`.
+pub fn run_bench(
+    backend: &mut dyn UciBackend,
+    progress: Arc<dyn ProgressObserver>,
+    depth: u32,
+) -> Result<BenchReport> {
+    let timing = Arc::new(Timing {
+        forward: progress,
+        submitted: Mutex::new(None),
+        finished: Mutex::new(None),
+    });
+    backend.set_progress_observer(timing.clone());
+    // The local backends report the submission and the end of their
+    // sessions too, only the paid ones compute on Golem
+    let on_golem = backend.max_cost().is_some();
+
+    let mut positions = Vec::new();
+    for fen in BENCH_POSITIONS.iter() {
+        timing.reset();
+        let cmds = backend.generate_uci(fen, depth);
+        let start = Instant::now();
+        let output = backend
+            .execute_uci(cmds)
+            .with_context(|| format!("searching {}", fen))?;
+        let wall_time = millis(start.elapsed());
+        let (nodes, engine_time) = search_stats(&output);
+        positions.push(PositionBench {
+            fen: fen.to_string(),
+            nodes,
+            // The engine can't have searched longer than it has run
+            engine_time: engine_time.min(wall_time),
+            wall_time,
+            golem_time: timing.golem_time().filter(|_| on_golem).map(millis),
+        });
+    }
+
+    let nodes = positions.iter().map(|p| p.nodes).sum();
+    let engine_time = positions.iter().map(|p| p.engine_time).sum();
+    let wall_time = positions.iter().map(|p| p.wall_time).sum();
+    Ok(BenchReport {
+        engine: backend.engine_id(),
+        depth,
+        nps: if engine_time > 0 {
+            nodes * 1000 / engine_time
+        } else {
+            0
+        },
+        positions,
+        nodes,
+        engine_time,
+        wall_time,
+    })
+}
@@ -0,0 +1,220 @@
+//! Polyglot opening books
+//!
+//! A book is a sorted array of 16-byte big-endian entries: the Zobrist key of
+//! the position, the move, its weight and a learning value.
+
+mod random;
+
+use anyhow::{bail, Context, Result};
+use random::RANDOM64;
+use shakmaty::san::San;
+use shakmaty::uci::Uci;
+use shakmaty::{attacks, Chess, Color, Move, Role, Setup, Square};
+use std::path::Path;
+
+const ENTRY_SIZE: usize = 16;
+const CASTLING_OFFSET: usize = 768;
+const EN_PASSANT_OFFSET: usize = 772;
+const TURN_OFFSET: usize = 780;
+
+fn role_index(role: Role) -> usize {
+    match role {
+        Role::Pawn => 0,
+        Role::Knight => 1,
+        Role::Bishop => 2,
+        Role::Rook => 3,
+        Role::Queen => 4,
+        Role::King => 5,
+    }
+}
+
+/// Computes the Polyglot key of the position
+pub fn polyglot_key(pos: &Chess) -> u64 {
+    let board = pos.board();
+    let mut key = 0;
+    for (square, piece) in board.pieces() {
+        let kind = 2 * role_index(piece.role) + if piece.color == Color::White { 1 } else { 0 };
+        key ^= RANDOM64[64 * kind + usize::from(square)];
+    }
+
+    let rights = pos.castling_rights();
+    let castling = [Square::H1, Square::A1, Square::H8, Square::A8];
+    for (idx, rook) in castling.iter().enumerate() {
+        if rights.contains(*rook) {
+            key ^= RANDOM64[CASTLING_OFFSET + idx];
+        }
+    }
+
+    // Polyglot hashes the en passant file only if a pawn can capture there
+    if let Some(ep_square) = pos.ep_square() {
+        let turn = pos.turn();
+        let capturers =
+            attacks::pawn_attacks(!turn, ep_square) & board.pawns() & board.by_color(turn);
+        if capturers.any() {
+            key ^= RANDOM64[EN_PASSANT_OFFSET + ep_square.file() as usize];
+        }
+    }
+
+    if pos.turn() == Color::White {
+        key ^= RANDOM64[TURN_OFFSET];
+    }
+    key
+}
+
+/// A move suggested by the book
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookEntry {
+    pub mv: Move,
+    pub weight: u16,
+}
+
+/// Decodes a Polyglot move, in which castling is encoded as the king taking its rook.
+fn decode_move(pos: &Chess, raw: u16) -> Option<Move> {
+    let square = |idx: u16| {
+        let file = (b'a' + (idx & 7) as u8) as char;
+        let rank = (b'1' + ((idx >> 3) & 7) as u8) as char;
+        format!("{}{}", file, rank)
+    };
+    let mut from = square((raw >> 6) & 63);
+    let mut to = square(raw & 63);
+    let promotion = match (raw >> 12) & 7 {
+        0 => "",
+        1 => "n",
+        2 => "b",
+        3 => "r",
+        4 => "q",
+        _ => return None,
+    };
+
+    let king_moves = [
+        ("e1", "h1", "g1"),
+        ("e1", "a1", "c1"),
+        ("e8", "h8", "g8"),
+        ("e8", "a8", "c8"),
+    ];
+    for (king, rook, target) in king_moves.iter() {
+        let from_square: Square = king.parse().ok()?;
+        let is_king = pos.board().role_at(from_square) == Some(Role::King);
+        if from == *king && to == *rook && is_king {
+            from = (*king).to_owned();
+            to = (*target).to_owned();
+        }
+    }
+
+    let uci: Uci = format!("{}{}{}", from, to, promotion).parse().ok()?;
+    uci.to_move(pos).ok()
+}
+
+/// A Polyglot opening book
+pub struct Book {
+    data: Vec<u8>,
+}
+
+impl Book {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).context("reading the opening book")?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        if data.len() % ENTRY_SIZE != 0 {
+            bail!("the book size isn't a multiple of the entry size");
+        }
+        Ok(Self { data })
+    }
+
+    fn len(&self) -> usize {
+        self.data.len() / ENTRY_SIZE
+    }
+
+    fn entry(&self, idx: usize) -> (u64, u16, u16) {
+        let entry = &self.data[idx * ENTRY_SIZE..(idx + 1) * ENTRY_SIZE];
+        let mut key = [0; 8];
+        key.copy_from_slice(&entry[0..8]);
+        let mv = u16::from_be_bytes([entry[8], entry[9]]);
+        let weight = u16::from_be_bytes([entry[10], entry[11]]);
+        (u64::from_be_bytes(key), mv, weight)
+    }
+
+    /// Finds the book moves in the position, the most weighted first.
+    ///
+    /// The illegal moves, which may appear in a broken book, are skipped.
+    pub fn lookup(&self, pos: &Chess) -> Vec<BookEntry> {
+        let key = polyglot_key(pos);
+
+        // The entries are sorted by the key, find the first one matching
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.entry(mid).0 < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let mut entries = Vec::new();
+        for idx in lo..self.len() {
+            let (entry_key, raw_move, weight) = self.entry(idx);
+            if entry_key != key {
+                break;
+            }
+            if let Some(mv) = decode_move(pos, raw_move) {
+                entries.push(BookEntry { mv, weight });
+            }
+        }
+        entries.sort_by(|a, b| b.weight.cmp(&a.weight));
+        entries
+    }
+
+    /// The move with the highest weight
+    pub fn best_move(&self, pos: &Chess) -> Option<BookEntry> {
+        self.lookup(pos).into_iter().next()
+    }
+
+    /// Picks a move randomly, proportionally to the weights.
+    ///
+    /// `random` should be uniformly distributed in `[0, 1)`.
+    pub fn weighted_move(&self, pos: &Chess, random: f64) -> Option<BookEntry> {
+        let entries = self.lookup(pos);
+        let total: u32 = entries.iter().map(|e| u32::from(e.weight)).sum();
+        if total == 0 {
+            return entries.into_iter().next();
+        }
+        let mut point = (random * f64::from(total)) as u32;
+        for entry in entries.iter() {
+            let weight = u32::from(entry.weight);
+            if point < weight {
+                return Some(entry.clone());
+            }
+            point -= weight;
+        }
+        entries.into_iter().last()
+    }
+}
+
+/// Describes the moves found by `Book::lookup`, with their probabilities
+pub fn describe_book_moves(pos: &Chess, entries: &[BookEntry]) -> String {
+    let best = match entries.first() {
+        Some(best) => best,
+        None => return "The position is not in the book.".to_owned(),
+    };
+    let total: u32 = entries.iter().map(|e| u32::from(e.weight)).sum();
+    let moves: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let san = San::from_move(pos, &entry.mv);
+            if total == 0 {
+                san.to_string()
+            } else {
+                let share = 100.0 * f64::from(entry.weight) / f64::from(total);
+                format!("{} ({:.0}%)", san, share)
+            }
+        })
+        .collect();
+    format!(
+        "Book moves: {}.\nThe book move is {}.",
+        moves.join(", "),
+        San::from_move(pos, &best.mv)
+    )
+}
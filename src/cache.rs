@@ -0,0 +1,376 @@
+//! On-disk cache of the engine outputs
+//!
+//! An analysis is identified by the position, the engine and its options.
+//! Only the deepest analysis is kept, and it's served for every request
+//! of a lower or equal depth. The entry holds the output of the engine,
+//! served by `CachedUci`, and its interpretation, the `AnalysisResult`.
+
+use crate::analysis::interpret_variant_uci;
+use crate::backends::{fingerprint, ProgressObserver, UciBackend, UciInput, UciOption, UciOutput};
+use crate::variant::Variant;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use shakmaty::fen::{self, Fen};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Identifies an analysis, regardless of its depth
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheKey {
+    /// The position, without the move counters
+    pub position: String,
+    pub engine: String,
+    /// The canonical name of the variant, whichever name the options used
+    pub variant: String,
+    /// The `setoption` commands, sorted, without the ones selecting the
+    /// variant
+    pub options: Vec<String>,
+}
+
+impl CacheKey {
+    /// Finds the position, the options and the depth of the analysis
+    /// requested by `uci`.
+    ///
+    /// Returns `None` if the search isn't limited by the depth only.
+    pub fn from_uci(engine: String, uci: &[String]) -> Option<(Self, u32)> {
+        let mut position = None;
+        let mut depth = None;
+        let mut options = Vec::new();
+        for line in uci {
+            if line.starts_with("setoption ") {
+                options.push(line.clone());
+            } else if let Some(pos) = line.strip_prefix("position ") {
+                position = Some(pos);
+            } else if let Some(limits) = line.strip_prefix("go ") {
+                let mut words = limits.split_whitespace();
+                match (words.next(), words.next(), words.next()) {
+                    (Some("depth"), Some(value), None) => depth = value.parse().ok(),
+                    _ => return None,
+                }
+            }
+        }
+        options.sort();
+        let mut variant = Variant::Standard;
+        options.retain(|option| match variant_option(option) {
+            Some(selected) => {
+                variant = selected;
+                false
+            }
+            None => true,
+        });
+
+        // Variant positions carry state (pockets, remaining checks) that a
+        // standard chess EPD would drop
+        let unknown_variant = options.iter().any(|o| o.contains(" name UCI_Variant "));
+        let position = match variant {
+            Variant::Standard | Variant::Chess960 if !unknown_variant => {
+                normalize_position(position?)
+            }
+            _ => position?.trim().to_owned(),
+        };
+        let key = Self {
+            position,
+            engine,
+            variant: variant.name().to_owned(),
+            options,
+        };
+        Some((key, depth?))
+    }
+
+    fn variant(&self) -> Variant {
+        self.variant.parse().unwrap_or_default()
+    }
+
+    /// Interprets the output of the analysis, if the position is a FEN
+    fn interpret(&self, output: &[String]) -> Option<serde_json::Value> {
+        let fen: Fen = self.position.strip_prefix("fen ")?.parse().ok()?;
+        let result = interpret_variant_uci(self.variant(), fen, output.to_vec()).ok()?;
+        serde_json::to_value(&result).ok()
+    }
+
+    fn file_name(&self) -> String {
+        let options = self.options.join("\n");
+        let hash = fingerprint(&[
+            self.position.as_bytes(),
+            self.engine.as_bytes(),
+            self.variant.as_bytes(),
+            options.as_bytes(),
+        ]);
+        format!("{:016x}.json", hash)
+    }
+}
+
+/// The variant selected by a `setoption` command, if it selects one the
+/// cache knows. The other variants stay in the options, as any option.
+fn variant_option(option: &str) -> Option<Variant> {
+    if let Some(name) = option.strip_prefix("setoption name UCI_Variant value ") {
+        name.trim().parse().ok()
+    } else if option.trim() == "setoption name UCI_Chess960 value true" {
+        Some(Variant::Chess960)
+    } else {
+        None
+    }
+}
+
+/// Drops the move counters and the en passant square if no capture is possible,
+/// so that transpositions share the cache entry.
+pub(crate) fn normalize_position(position: &str) -> String {
+    let normalized = position
+        .strip_prefix("fen ")
+        .filter(|fen| !fen.contains(" moves "))
+        .and_then(|fen| fen.parse::<Fen>().ok())
+        .and_then(|fen| fen.position::<shakmaty::Chess>().ok())
+        .map(|pos| format!("fen {}", fen::epd(&pos)));
+    normalized.unwrap_or_else(|| position.trim().to_owned())
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: CacheKey,
+    depth: u32,
+    /// Seconds since the Unix epoch
+    created: u64,
+    output: UciOutput,
+    /// The serialized `AnalysisResult`, absent if the output couldn't be
+    /// interpreted
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+}
+
+/// The seconds since the Unix epoch
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// Tells apart the temporary files of the writers of this process
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// A directory of cached engine outputs
+#[derive(Debug, Clone)]
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// `$XDG_CACHE_HOME/golemate`, or `~/.cache/golemate`
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache_home.join("golemate"))
+    }
+
+    fn read_entry(&self, key: &CacheKey) -> Result<Option<CacheEntry>> {
+        let path = self.dir.join(key.file_name());
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path).context("reading the cache entry")?;
+        let entry: CacheEntry = serde_json::from_slice(&data).context("parsing the cache entry")?;
+        // Tell apart hash collisions
+        Ok(Some(entry).filter(|entry| entry.key == *key))
+    }
+
+    /// Returns the output of an analysis at least `depth` deep
+    pub fn get(&self, key: &CacheKey, depth: u32) -> Result<Option<UciOutput>> {
+        let entry = self.read_entry(key)?;
+        Ok(entry
+            .filter(|entry| entry.depth >= depth)
+            .map(|entry| entry.output))
+    }
+
+    /// Returns the serialized `AnalysisResult` of an analysis at least
+    /// `depth` deep
+    pub fn get_result(&self, key: &CacheKey, depth: u32) -> Result<Option<serde_json::Value>> {
+        let entry = self.read_entry(key)?;
+        Ok(entry
+            .filter(|entry| entry.depth >= depth)
+            .and_then(|entry| entry.result))
+    }
+
+    /// Stores the output, unless a deeper analysis is cached already
+    pub fn insert(&self, key: &CacheKey, depth: u32, output: &[String]) -> Result<()> {
+        if let Some(entry) = self.read_entry(key).unwrap_or(None) {
+            if entry.depth > depth {
+                return Ok(());
+            }
+        }
+
+        fs::create_dir_all(&self.dir).context("creating the cache directory")?;
+        let entry = CacheEntry {
+            key: key.clone(),
+            depth,
+            created: now(),
+            output: output.to_vec(),
+            result: key.interpret(output),
+        };
+        let data = serde_json::to_vec(&entry)?;
+        // Write atomically, so that a concurrent reader never sees half of an
+        // entry. Every writer, in this process or another, has its own file.
+        let path = self.dir.join(key.file_name());
+        let tmp_path = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&tmp_path, data).context("writing the cache entry")?;
+        fs::rename(&tmp_path, &path).context("writing the cache entry")
+    }
+
+    /// Removes the entries older than `older_than`, or all if it's `None`.
+    /// Returns the number of removed entries.
+    pub fn prune(&self, older_than: Option<Duration>) -> Result<usize> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let threshold = older_than.map(|age| now().saturating_sub(age.as_secs()));
+        let mut removed = 0;
+        for dir_entry in fs::read_dir(&self.dir).context("listing the cache")? {
+            let path = dir_entry?.path();
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            let expired = match threshold {
+                None => true,
+                Some(threshold) => fs::read(&path)
+                    .ok()
+                    .and_then(|data| serde_json::from_slice::<CacheEntry>(&data).ok())
+                    // Remove the unreadable entries too
+                    .map_or(true, |entry| entry.created < threshold),
+            };
+            if expired {
+                fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Serves the analyses from the cache, running `backend` only on a miss
+pub struct CachedUci<B> {
+    backend: B,
+    cache: AnalysisCache,
+}
+
+impl<B: UciBackend> CachedUci<B> {
+    pub fn new(backend: B, cache: AnalysisCache) -> Self {
+        Self { backend, cache }
+    }
+}
+
+impl<B: UciBackend> UciBackend for CachedUci<B> {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        self.backend.get_uci_opts()
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        self.backend.set_progress_observer(observer)
+    }
+
+    fn engine_id(&self) -> String {
+        self.backend.engine_id()
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        self.backend.max_cost()
+    }
+
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        let request = CacheKey::from_uci(self.engine_id(), &uci);
+        if let Some(output) = self.lookup(request.as_ref()) {
+            return Ok(output);
+        }
+        let output = self.backend.execute_uci(uci)?;
+        self.store(request.as_ref(), &output);
+        Ok(output)
+    }
+
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        let engine_id = self.engine_id();
+        let mut results = Vec::with_capacity(inputs.len());
+        let mut misses = Vec::new();
+        for uci in inputs {
+            let request = CacheKey::from_uci(engine_id.clone(), &uci);
+            match self.lookup(request.as_ref()) {
+                Some(output) => results.push(Some(Ok(output))),
+                None => {
+                    results.push(None);
+                    misses.push((results.len() - 1, request, uci));
+                }
+            }
+        }
+
+        let (slots, misses): (Vec<_>, Vec<_>) = misses
+            .into_iter()
+            .map(|(index, request, uci)| ((index, request), uci))
+            .unzip();
+        let outputs = self.backend.execute_batch(misses);
+        for ((index, request), output) in slots.into_iter().zip(outputs) {
+            if let Ok(output) = &output {
+                self.store(request.as_ref(), output);
+            }
+            results[index] = Some(output);
+        }
+        results
+            .into_iter()
+            .map(|result| result.expect("every session has a result"))
+            .collect()
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl<B: crate::backends::AsyncUciBackend> crate::backends::AsyncUciBackend for CachedUci<B> {
+    async fn execute_async(&self, uci: UciInput) -> Result<UciOutput> {
+        let request = CacheKey::from_uci(self.engine_id(), &uci);
+        if let Some(output) = self.lookup(request.as_ref()) {
+            return Ok(output);
+        }
+        let output = self.backend.execute_async(uci).await?;
+        self.store(request.as_ref(), &output);
+        Ok(output)
+    }
+}
+
+impl<B: UciBackend> CachedUci<B> {
+    fn lookup(&self, request: Option<&(CacheKey, u32)>) -> Option<UciOutput> {
+        let (key, depth) = match request {
+            Some(request) => request,
+            None => {
+                debug!("Not caching the analysis, it's not limited by depth");
+                return None;
+            }
+        };
+        match self.cache.get(key, *depth) {
+            Ok(Some(output)) => {
+                info!("Using the cached analysis");
+                Some(output)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                warn!("Ignoring the broken cache entry: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn store(&self, request: Option<&(CacheKey, u32)>, output: &[String]) {
+        let complete = output.iter().any(|line| line.starts_with("bestmove"));
+        if let (Some((key, depth)), true) = (request, complete) {
+            if let Err(e) = self.cache.insert(key, *depth, output) {
+                warn!("Failed to cache the analysis: {:?}", e);
+            }
+        }
+    }
+}
@@ -1,8 +1,32 @@
-use anyhow::{Context, Result};
-use golemate::{analysis, backends, backends::UciBackend};
-use shakmaty::fen::Fen;
-use std::path::PathBuf;
-use structopt::{clap::ArgGroup, StructOpt};
+use anyhow::{bail, Context, Result};
+use golemate::analysis;
+use golemate::backends::{ProgressEvent, ProgressObserver, RecordUci, ReplayUci, UciBackend};
+use golemate::bench;
+use golemate::book::{self, Book};
+use golemate::cache::{AnalysisCache, CachedUci};
+use golemate::config::{BackendKind, Config, Profile};
+use golemate::engine_info;
+use golemate::epd::{self, EpdRecord};
+use golemate::game::{self, Game};
+#[cfg(feature = "history")]
+use golemate::history::{AnalysisHistory, HistoryQuery, RecordedUci};
+use golemate::pgn;
+use golemate::proxy::UciProxy;
+use golemate::puzzle::{self, PuzzleMiner};
+use golemate::review;
+use golemate::variant::Variant;
+use serde::Serialize;
+use shakmaty::san::San;
+use shakmaty::{fen::Fen, Chess, Color};
+#[cfg(feature = "native")]
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
 
 #[cfg(not(any(feature = "gwasm", feature = "native")))]
 compile_error!("At least one backend must be enabled");
@@ -14,78 +38,642 @@ pub struct GWasmOpts {
     #[structopt(
         short,
         long = "wasm",
-        help = "path to the WASM part of the gWASM binary",
-        group = "backend",
-        requires = "js-path",
-        requires = "workspace",
-        requires = "datadir"
+        help = "path to the WASM part of the gWASM binary"
     )]
     pub wasm_path: Option<PathBuf>,
 
     #[structopt(short, long = "js", help = "path to the JS part of the gWASM binary")]
     pub js_path: Option<PathBuf>,
 
-    #[structopt(long, help = "path to the workspace used by Golem")]
+    #[structopt(
+        long,
+        help = "directory in which the task workspaces are created [default: a temporary directory]"
+    )]
     pub workspace: Option<PathBuf>,
 
+    #[structopt(long, help = "don't remove the task workspace after the computation")]
+    pub keep_workspace: bool,
+
     #[structopt(long, help = "path to the Golem client data directory")]
     pub datadir: Option<PathBuf>,
+
+    #[structopt(long, help = "timeout of the whole Golem task, in seconds")]
+    pub task_timeout: Option<u64>,
+
+    #[structopt(long, help = "timeout of a single subtask, in seconds")]
+    pub subtask_timeout: Option<u64>,
+
+    #[structopt(long, help = "maximal price of an hour of computation, in GNT")]
+    pub max_price: Option<f64>,
+
+    #[structopt(long, help = "don't submit tasks which may cost more GNT than this")]
+    pub budget: Option<f64>,
+
+    #[structopt(
+        long,
+        help = "copies of the analysis computed by different providers, each paid for [default: 1]"
+    )]
+    pub replicas: Option<u32>,
 }
 
+/// Selects the backend and the default limits, shared by the commands
+/// running an engine
 #[derive(Debug, StructOpt)]
-#[structopt(
-    name = "golemate",
-    author = "Marcin Mielniczuk <marmistrz.dev@zoho.eu>",
-    about = "Chess position solver using gWASM"
-)]
-#[structopt(group = ArgGroup::with_name("backend").required(true))]
-pub struct Opts {
-    #[structopt(short, long, help = "position in the FEN format")]
-    pub fen: Fen,
-
-    #[structopt(short, long, help = "analysis depth")]
-    pub depth: u32,
+pub struct BackendOpts {
+    #[structopt(
+        long,
+        help = "configuration file [default: $XDG_CONFIG_HOME/golemate/config.toml]"
+    )]
+    pub config: Option<PathBuf>,
 
-    #[structopt(short, long = "raw", help = "output raw UCI instead of analysis")]
-    pub raw_uci: bool,
+    #[structopt(
+        short = "p",
+        long,
+        help = "engine profile from the configuration file, overridden by the other flags"
+    )]
+    pub profile: Option<String>,
 
     #[cfg(feature = "native")]
     #[structopt(
         short = "e",
         long = "engine",
-        help = "path to a local engine to be used by the native backend",
-        group = "backend"
+        help = "path to a local engine to be used by the native backend"
     )]
     pub engine: Option<PathBuf>,
 
+    #[cfg(feature = "native")]
+    // No short flag, -j is the one of --js
+    #[structopt(
+        long,
+        help = "number of engines analysing the positions of batch and epd in parallel"
+    )]
+    pub sessions: Option<usize>,
+
     #[cfg(feature = "gwasm")]
     #[structopt(flatten)]
     pub gwasm_opts: GWasmOpts,
+
+    #[structopt(short, long, help = "analysis depth")]
+    pub depth: Option<u32>,
+
+    #[structopt(
+        long,
+        help = "chess variant: chess, chess960, antichess, atomic, crazyhouse, horde, kingofthehill, racingkings or 3check [default: chess]"
+    )]
+    pub variant: Option<Variant>,
+
+    #[structopt(long, help = "don't use the analysis cache, used by default")]
+    pub no_cache: bool,
+
+    #[structopt(
+        long,
+        help = "directory of the analysis cache [default: $XDG_CACHE_HOME/golemate]"
+    )]
+    pub cache_dir: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "append the UCI sessions run by the engine to this transcript file"
+    )]
+    pub record: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "serve the engine outputs from this transcript file instead of running the engine"
+    )]
+    pub replay: Option<PathBuf>,
+
+    #[cfg(feature = "history")]
+    #[structopt(long, help = "don't record the analyses in the history")]
+    pub no_history: bool,
+
+    #[cfg(feature = "history")]
+    #[structopt(
+        long,
+        help = "SQLite database of the analysis history [default: $XDG_DATA_HOME/golemate/history.sqlite]"
+    )]
+    pub history_db: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct AnalyseOpts {
+    #[structopt(short, long, required = true, help = "position in the FEN format")]
+    pub fen: Option<Fen>,
+
+    #[structopt(short, long = "raw", help = "output raw UCI instead of analysis")]
+    pub raw_uci: bool,
+
+    #[structopt(
+        long,
+        help = "Polyglot opening book, consulted before running the engine (ignored with --raw)"
+    )]
+    pub book: Option<PathBuf>,
+
+    #[cfg(feature = "syzygy")]
+    #[structopt(
+        long,
+        help = "directory of Syzygy tables, probed before running the engine (ignored with --raw)"
+    )]
+    pub syzygy: Vec<PathBuf>,
+
+    #[cfg(feature = "native")]
+    #[structopt(
+        long,
+        help = "analyse until Enter is pressed, printing the search as it goes (native backend only)"
+    )]
+    pub infinite: bool,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "golemate",
+    author = "Marcin Mielniczuk <marmistrz.dev@zoho.eu>",
+    about = "Chess position solver using gWASM",
+    after_help = "Without a subcommand, golemate analyses the position given by --fen, like `golemate analyse`."
+)]
+#[structopt(setting = AppSettings::SubcommandsNegateReqs)]
+pub struct Opts {
+    #[structopt(flatten)]
+    pub backend: BackendOpts,
+
+    #[structopt(flatten)]
+    pub analyse: AnalyseOpts,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    #[structopt(about = "Analyses a single position")]
+    Analyse {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(flatten)]
+        analyse: AnalyseOpts,
+    },
+    #[structopt(about = "Analyses the positions listed in a file, one FEN or EPD per line")]
+    Batch {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(help = "file with the positions, `-` for the standard input")]
+        input: PathBuf,
+
+        #[structopt(long, help = "print a JSON object per position")]
+        json: bool,
+    },
+    #[structopt(about = "Plays a game against the engine in the terminal")]
+    Play {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(
+            short,
+            long,
+            help = "start position in the FEN format [default: the initial position]"
+        )]
+        fen: Option<Fen>,
+
+        #[structopt(long, help = "play the black pieces")]
+        black: bool,
+
+        #[structopt(long, help = "let the engine think on your time (native backend only)")]
+        ponder: bool,
+    },
+    #[structopt(about = "Plays a match between two engine profiles")]
+    Match {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(help = "profile of the first engine")]
+        first: String,
+
+        #[structopt(help = "profile of the second engine")]
+        second: String,
+
+        #[structopt(
+            long,
+            help = "file with the opening positions, one FEN or EPD per line [default: the initial position]"
+        )]
+        openings: Option<PathBuf>,
+
+        #[structopt(
+            long,
+            default_value = "300",
+            help = "number of plies after which a game is adjudicated as a draw"
+        )]
+        max_plies: u32,
+
+        #[structopt(
+            long,
+            help = "let the engines think on the opponent's time (native backend only)"
+        )]
+        ponder: bool,
+
+        #[structopt(long, help = "print the report as JSON")]
+        json: bool,
+    },
+    #[structopt(about = "Acts as a UCI engine, computing the searches on the backend")]
+    Uci {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+    },
+    #[structopt(about = "Inspects the engine")]
+    Engine(EngineCommand),
+    #[structopt(about = "Measures the engine speed and the overhead of the backend")]
+    Bench {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(help = "profiles to compare [default: the selected backend]")]
+        profiles: Vec<String>,
+
+        #[structopt(long, help = "print the reports as JSON")]
+        json: bool,
+    },
+    #[structopt(about = "Manages the analysis cache")]
+    Cache(CacheCommand),
+    #[structopt(about = "Reviews the games of a PGN file: accuracy, errors and critical moments")]
+    Review {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(help = "PGN file with the games, `-` for the standard input")]
+        pgn: PathBuf,
+
+        #[structopt(long, help = "review only the game with this number, starting from 1")]
+        game: Option<usize>,
+
+        #[structopt(long, help = "print the reviews as JSON")]
+        json: bool,
+    },
+    #[structopt(about = "Finds tactical puzzles in the games of a PGN file")]
+    Puzzles {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(help = "PGN file with the games, `-` for the standard input")]
+        pgn: PathBuf,
+
+        #[structopt(
+            long,
+            default_value = "300",
+            help = "how much better than the second best move the solution must be, in centipawns"
+        )]
+        min_gap: i32,
+
+        #[structopt(
+            long,
+            default_value = "3",
+            help = "the maximal number of moves of the solver"
+        )]
+        max_moves: usize,
+
+        #[structopt(long, help = "print the puzzles as JSON instead of CSV")]
+        json: bool,
+    },
+    #[cfg(feature = "history")]
+    #[structopt(about = "Lists the recorded analyses, the most recent first")]
+    History {
+        #[structopt(
+            short,
+            long,
+            help = "only the analyses of this position, in the FEN format"
+        )]
+        fen: Option<Fen>,
+
+        #[structopt(
+            short,
+            long,
+            help = "only the analyses by the engines whose id contains this, e.g. the engine path"
+        )]
+        engine: Option<String>,
+
+        #[structopt(short = "n", long, help = "show at most this many analyses")]
+        limit: Option<u32>,
+
+        #[structopt(long, help = "print the analyses as JSON")]
+        json: bool,
+
+        #[structopt(
+            long,
+            help = "SQLite database of the analysis history [default: $XDG_DATA_HOME/golemate/history.sqlite]"
+        )]
+        history_db: Option<PathBuf>,
+    },
+    #[structopt(about = "Runs an EPD test suite and scores the best moves")]
+    Epd {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(help = "path to the EPD file")]
+        suite: PathBuf,
+
+        #[structopt(long, help = "print the report as JSON")]
+        json: bool,
+    },
+    #[cfg(feature = "serve")]
+    #[structopt(about = "Serves a REST API analysing the submitted positions in the background")]
+    Serve {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(
+            long,
+            default_value = "127.0.0.1:8080",
+            help = "address to listen on, port 0 picks a free port"
+        )]
+        listen: std::net::SocketAddr,
+
+        #[structopt(
+            long,
+            default_value = "64",
+            help = "number of jobs waiting for analysis, further jobs are refused"
+        )]
+        queue_size: usize,
+
+        #[structopt(
+            long,
+            default_value = "1",
+            help = "number of jobs analysed at the same time"
+        )]
+        workers: usize,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum EngineCommand {
+    #[structopt(about = "Shows the identity, the options and the features of the engine")]
+    Info {
+        #[structopt(flatten)]
+        backend: BackendOpts,
+
+        #[structopt(long, help = "print the report as JSON")]
+        json: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum CacheCommand {
+    #[structopt(about = "Removes the cached analyses")]
+    Prune {
+        #[structopt(long, help = "remove only the analyses older than this many days")]
+        older_than: Option<u64>,
+
+        #[structopt(
+            long,
+            help = "directory of the analysis cache [default: $XDG_CACHE_HOME/golemate]"
+        )]
+        cache_dir: Option<PathBuf>,
+    },
+}
+
+/// The backend options given before the subcommand, overridden by the ones
+/// given after it
+struct Selection<'a> {
+    layers: Vec<&'a BackendOpts>,
+}
+
+impl<'a> Selection<'a> {
+    fn new(global: &'a BackendOpts, local: Option<&'a BackendOpts>) -> Self {
+        let mut layers = vec![global];
+        layers.extend(local);
+        Self { layers }
+    }
+
+    fn config(&self) -> Result<Config> {
+        match self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|opts| opts.config.as_ref())
+        {
+            Some(path) => Config::load(path),
+            None => Config::load_default(),
+        }
+    }
+
+    /// The selected profile, with the command line flags taking precedence
+    fn profile(&self) -> Result<Profile> {
+        let name = self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|opts| opts.profile.as_deref());
+        self.named_profile(name)
+    }
+
+    /// Like `profile`, but selecting the profile by name
+    fn named_profile(&self, name: Option<&str>) -> Result<Profile> {
+        let mut profile = self.config()?.profile(name)?;
+        for opts in &self.layers {
+            opts.apply(&mut profile)?;
+        }
+        Ok(profile)
+    }
+
+    fn cache(&self) -> Option<AnalysisCache> {
+        self.layers
+            .iter()
+            .rev()
+            .find_map(|opts| opts.cache_dir.clone())
+            .or_else(AnalysisCache::default_dir)
+            .map(AnalysisCache::new)
+    }
+
+    /// The backend of the profile, behind the cache unless disabled
+    fn backend(&self, profile: &Profile) -> Result<Box<dyn UciBackend>> {
+        match self.replay()? {
+            Some(replay) => self.wrap(replay),
+            None => self.wrap(profile.backend()?),
+        }
+    }
+
+    /// The backend for analysing many positions, running several engines
+    /// if the profile asks for it
+    fn batch_backend(&self, profile: &Profile) -> Result<Box<dyn UciBackend>> {
+        match self.replay()? {
+            Some(replay) => self.wrap(replay),
+            None => self.wrap(profile.batch_backend()?),
+        }
+    }
+
+    /// The transcript replayed instead of the profile's backend, if any
+    fn replay(&self) -> Result<Option<Box<dyn UciBackend>>> {
+        match self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|opts| opts.replay.as_ref())
+        {
+            Some(path) => Ok(Some(Box::new(ReplayUci::open(path)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// The cache to use, unless disabled
+    fn enabled_cache(&self) -> Option<AnalysisCache> {
+        if self.layers.iter().any(|opts| opts.no_cache) {
+            return None;
+        }
+        let cache = self.cache();
+        if cache.is_none() {
+            log::warn!("Cannot locate the cache directory, not caching");
+        }
+        cache
+    }
+
+    #[cfg(feature = "history")]
+    fn history(&self) -> Result<AnalysisHistory> {
+        let path = self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|opts| opts.history_db.clone())
+            .or_else(AnalysisHistory::default_path)
+            .context("cannot locate the history database")?;
+        AnalysisHistory::open(&path)
+    }
+
+    /// The history to record the analyses in, unless disabled
+    #[cfg(feature = "history")]
+    fn enabled_history(&self) -> Option<AnalysisHistory> {
+        if self.layers.iter().any(|opts| opts.no_history) {
+            return None;
+        }
+        self.history()
+            .map_err(|e| log::warn!("Not recording the analyses: {:?}", e))
+            .ok()
+    }
+
+    /// Adds the transcript, the history, the cache and the progress bar
+    fn wrap(&self, mut backend: Box<dyn UciBackend>) -> Result<Box<dyn UciBackend>> {
+        if let Some(path) = self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|opts| opts.record.as_ref())
+        {
+            backend = Box::new(RecordUci::new(backend, path)?);
+        }
+        // Only the analyses actually run are recorded, not the cache hits
+        #[cfg(feature = "history")]
+        {
+            if let Some(history) = self.enabled_history() {
+                backend = Box::new(RecordedUci::new(backend, history));
+            }
+        }
+        if let Some(cache) = self.enabled_cache() {
+            backend = Box::new(CachedUci::new(backend, cache));
+        }
+        backend.set_progress_observer(Arc::new(ProgressBar::default()));
+        Ok(backend)
+    }
 }
 
-impl Opts {
-    pub fn backend(&self) -> Result<Box<dyn UciBackend>> {
+impl BackendOpts {
+    fn apply(&self, profile: &mut Profile) -> Result<()> {
+        #[cfg(all(feature = "gwasm", feature = "native"))]
+        {
+            if self.engine.is_some() && self.gwasm_opts.wasm_path.is_some() {
+                bail!("--engine and --wasm cannot be used together");
+            }
+        }
         #[cfg(feature = "gwasm")]
         {
             let opt = &self.gwasm_opts;
             if opt.wasm_path.is_some() {
-                let backend = backends::GWasmUci::new(
-                    &opt.wasm_path.clone().expect("inconsistent wasm opts"),
-                    &opt.js_path.clone().expect("inconsistent wasm opts"),
-                    opt.workspace.clone().expect("inconsistent wasm opts"),
-                    opt.datadir.clone().expect("inconsistent wasm opts"),
-                )?;
-                return Ok(Box::new(backend));
+                profile.backend = Some(BackendKind::Gwasm);
+                profile.wasm = opt.wasm_path.clone();
+            }
+            override_with(&mut profile.js, &opt.js_path);
+            override_with(&mut profile.workspace, &opt.workspace);
+            override_with(&mut profile.datadir, &opt.datadir);
+            if opt.keep_workspace {
+                profile.keep_workspace = Some(golemate::backends::KeepWorkspace::Always);
             }
+            override_with(&mut profile.task_timeout, &opt.task_timeout);
+            override_with(&mut profile.subtask_timeout, &opt.subtask_timeout);
+            override_with(&mut profile.max_price, &opt.max_price);
+            override_with(&mut profile.budget, &opt.budget);
+            override_with(&mut profile.replicas, &opt.replicas);
         }
         #[cfg(feature = "native")]
         {
-            if let Some(engine) = &self.engine {
-                let backend = backends::NativeUci::new(engine.to_owned());
-                return Ok(Box::new(backend));
+            if self.engine.is_some() {
+                profile.backend = Some(BackendKind::Native);
+                profile.engine = self.engine.clone();
+            }
+            override_with(&mut profile.sessions, &self.sessions);
+        }
+        override_with(&mut profile.depth, &self.depth);
+        override_with(&mut profile.variant, &self.variant);
+        Ok(())
+    }
+}
+
+impl AnalyseOpts {
+    fn apply(&self, profile: &mut Profile) {
+        #[cfg(feature = "syzygy")]
+        {
+            if !self.syzygy.is_empty() {
+                profile.syzygy = self.syzygy.clone();
+            }
+        }
+        override_with(&mut profile.book, &self.book);
+    }
+}
+
+fn override_with<T: Clone>(setting: &mut Option<T>, flag: &Option<T>) {
+    if flag.is_some() {
+        *setting = flag.clone();
+    }
+}
+
+fn depth(profile: &Profile) -> Result<u32> {
+    profile.depth.context("No analysis depth given, see --help")
+}
+
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+/// Renders the progress of the computation on stderr, keeping stdout clean
+#[derive(Default)]
+struct ProgressBar {
+    drawn: AtomicBool,
+}
+
+impl ProgressBar {
+    fn message(&self, msg: &str) {
+        if self.drawn.swap(false, Ordering::SeqCst) {
+            eprintln!();
+        }
+        eprintln!("{}", msg);
+    }
+}
+
+impl ProgressObserver for ProgressBar {
+    fn notify(&self, event: ProgressEvent) {
+        use ProgressEvent::*;
+        match event {
+            TaskSubmitted => self.message("Task submitted"),
+            Cost(cost) => self.message(&cost.describe()),
+            Progress(progress) => {
+                let progress = progress.max(0.0).min(1.0);
+                let filled = (progress * PROGRESS_BAR_WIDTH as f64).round() as usize;
+                eprint!(
+                    "\r[{}{}] {:3.0}%",
+                    "#".repeat(filled),
+                    "-".repeat(PROGRESS_BAR_WIDTH - filled),
+                    progress * 100.0
+                );
+                self.drawn.store(true, Ordering::SeqCst);
             }
+            // The lines of the search are only printed by the infinite analysis
+            Info(_) => {}
+            TaskFinished => self.message("Task finished"),
         }
-        unreachable!("Internal error: command-line was not properly verified");
     }
 }
 
@@ -95,17 +683,633 @@ fn main() -> Result<()> {
     );
 
     let opts = Opts::from_args();
-    let backend: Box<dyn UciBackend> = opts.backend()?;
-    let cmds = backend.generate_uci(&opts.fen.to_string(), opts.depth);
+    let global = &opts.backend;
+    match &opts.cmd {
+        None => analyse(&Selection::new(global, None), &opts.analyse),
+        Some(Command::Analyse {
+            backend,
+            analyse: analyse_opts,
+        }) => analyse(&Selection::new(global, Some(backend)), analyse_opts),
+        Some(Command::Batch {
+            backend,
+            input,
+            json,
+        }) => run_batch(&Selection::new(global, Some(backend)), input, *json),
+        Some(Command::Play {
+            backend,
+            fen,
+            black,
+            ponder,
+        }) => play(
+            &Selection::new(global, Some(backend)),
+            fen.clone(),
+            *black,
+            *ponder,
+        ),
+        Some(Command::Match {
+            backend,
+            first,
+            second,
+            openings,
+            max_plies,
+            ponder,
+            json,
+        }) => run_match(
+            &Selection::new(global, Some(backend)),
+            (first.as_str(), second.as_str()),
+            openings.as_deref(),
+            *max_plies,
+            *ponder,
+            *json,
+        ),
+        Some(Command::Uci { backend }) => run_proxy(&Selection::new(global, Some(backend))),
+        Some(Command::Engine(EngineCommand::Info { backend, json })) => {
+            engine_info(&Selection::new(global, Some(backend)), *json)
+        }
+        Some(Command::Bench {
+            backend,
+            profiles,
+            json,
+        }) => bench(&Selection::new(global, Some(backend)), profiles, *json),
+        Some(Command::Cache(CacheCommand::Prune {
+            older_than,
+            cache_dir,
+        })) => {
+            let cache = match cache_dir {
+                Some(dir) => Some(AnalysisCache::new(dir.clone())),
+                None => Selection::new(global, None).cache(),
+            }
+            .context("cannot locate the cache directory")?;
+            let older_than = older_than.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let removed = cache.prune(older_than).context("pruning the cache")?;
+            println!("Removed {} cached analyses", removed);
+            Ok(())
+        }
+        Some(Command::Review {
+            backend,
+            pgn,
+            game,
+            json,
+        }) => review_games(&Selection::new(global, Some(backend)), pgn, *game, *json),
+        Some(Command::Puzzles {
+            backend,
+            pgn,
+            min_gap,
+            max_moves,
+            json,
+        }) => find_puzzles(
+            &Selection::new(global, Some(backend)),
+            pgn,
+            *min_gap,
+            *max_moves,
+            *json,
+        ),
+        #[cfg(feature = "history")]
+        Some(Command::History {
+            fen,
+            engine,
+            limit,
+            json,
+            history_db,
+        }) => show_history(
+            &Selection::new(global, None),
+            history_db.as_deref(),
+            fen.as_ref(),
+            engine.as_deref(),
+            *limit,
+            *json,
+        ),
+        Some(Command::Epd {
+            backend,
+            suite,
+            json,
+        }) => run_epd(&Selection::new(global, Some(backend)), suite, *json),
+        #[cfg(feature = "serve")]
+        Some(Command::Serve {
+            backend,
+            listen,
+            queue_size,
+            workers,
+        }) => serve(
+            &Selection::new(global, Some(backend)),
+            listen,
+            *queue_size,
+            *workers,
+        ),
+    }
+}
+
+/// Reads positions given one per line, as FENs or EPDs
+fn read_positions(text: &str) -> Result<Vec<Fen>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            line.parse::<Fen>()
+                .or_else(|_| EpdRecord::parse(line).map(|record| record.fen))
+                .with_context(|| format!("invalid position on line {}", i + 1))
+        })
+        .collect()
+}
+
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut text = String::new();
+        io::Read::read_to_string(&mut io::stdin(), &mut text).context("reading the input")?;
+        Ok(text)
+    } else {
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))
+    }
+}
+
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn initial_position() -> Fen {
+    INITIAL_FEN.parse().expect("invalid initial FEN")
+}
+
+fn run_epd(selection: &Selection, suite: &Path, json: bool) -> Result<()> {
+    let profile = selection.profile()?;
+    let depth = depth(&profile)?;
+    let text = std::fs::read_to_string(suite).context("reading the EPD suite")?;
+    let records = EpdRecord::parse_suite(&text).context("parsing the EPD suite")?;
+    let backend = selection.batch_backend(&profile)?;
+    let report = epd::run_suite(&*backend, &records, depth);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report.describe());
+    }
+    Ok(())
+}
+
+fn review_games(
+    selection: &Selection,
+    input: &Path,
+    number: Option<usize>,
+    json: bool,
+) -> Result<()> {
+    let profile = selection.profile()?;
+    let depth = depth(&profile)?;
+    let mut games = pgn::parse_pgn(&read_input(input)?).context("parsing the PGN")?;
+    if let Some(number) = number {
+        if number == 0 || number > games.len() {
+            bail!("there's no game {}, the file has {}", number, games.len());
+        }
+        games = vec![games.swap_remove(number - 1)];
+    }
+    let backend = selection.batch_backend(&profile)?;
+    let mut reviews = Vec::new();
+    for (i, game) in games.iter().enumerate() {
+        let review = review::review_game(&*backend, game, profile.variant(), depth)
+            .with_context(|| format!("reviewing game {}", number.unwrap_or(i + 1)))?;
+        if !json {
+            println!("{}\n", review.describe());
+        }
+        reviews.push(review);
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reviews)?);
+    }
+    Ok(())
+}
+
+fn find_puzzles(
+    selection: &Selection,
+    input: &Path,
+    min_gap: i32,
+    max_moves: usize,
+    json: bool,
+) -> Result<()> {
+    let profile = selection.profile()?;
+    let depth = depth(&profile)?;
+    let games = pgn::parse_pgn(&read_input(input)?).context("parsing the PGN")?;
+    let backend = selection.batch_backend(&profile)?;
+    let puzzles = PuzzleMiner::new(depth)
+        .variant(profile.variant())
+        .min_gap(min_gap)
+        .max_moves(max_moves)
+        .mine(&*backend, &games)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&puzzles)?);
+    } else {
+        print!("{}", puzzle::to_csv(&puzzles));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "history")]
+/// Lists the analyses of the history at `path`, or of the one selected
+/// before the subcommand
+fn show_history(
+    selection: &Selection,
+    path: Option<&Path>,
+    fen: Option<&Fen>,
+    engine: Option<&str>,
+    limit: Option<u32>,
+    json: bool,
+) -> Result<()> {
+    let history = match path {
+        Some(path) => AnalysisHistory::open(path)?,
+        None => selection.history()?,
+    };
+    let mut query = HistoryQuery::new();
+    if let Some(fen) = fen {
+        query = query.fen(&fen.to_string());
+    }
+    if let Some(engine) = engine {
+        query = query.engine(engine);
+    }
+    if let Some(limit) = limit {
+        query = query.limit(limit);
+    }
+    let records = history.query(&query)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+    } else if records.is_empty() {
+        println!("No recorded analyses");
+    } else {
+        let described: Vec<_> = records.iter().map(|record| record.describe()).collect();
+        println!("{}", described.join("\n\n"));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct BatchResult {
+    fen: String,
+    best_move: Option<String>,
+    pv: Vec<String>,
+    depth: Option<u32>,
+    advantage: Option<String>,
+    error: Option<String>,
+}
+
+fn run_batch(selection: &Selection, input: &Path, json: bool) -> Result<()> {
+    let positions = read_positions(&read_input(input)?)?;
+    let profile = selection.profile()?;
+    let depth = depth(&profile)?;
+    let variant = profile.variant();
+    let backend = selection.batch_backend(&profile)?;
+
+    let cmds = positions
+        .iter()
+        .map(|fen| backend.generate_variant_uci(variant, &fen.to_string(), depth))
+        .collect();
+    let outputs = backend.execute_batch(cmds);
+
+    let mut failed = 0;
+    for (fen, output) in positions.iter().zip(outputs) {
+        let result = output
+            .context("Executing UCI")
+            .and_then(|output| analysis::interpret_variant_uci(variant, fen.clone(), output));
+        if result.is_err() {
+            failed += 1;
+        }
+        if json {
+            let line = match &result {
+                Ok(res) => BatchResult {
+                    fen: fen.to_string(),
+                    best_move: Some(res.variant.uci(&res.best_move).to_string()),
+                    pv: res.pv.iter().map(ToString::to_string).collect(),
+                    depth: Some(res.depth),
+                    advantage: Some(res.describe_advantage()),
+                    error: None,
+                },
+                Err(e) => BatchResult {
+                    fen: fen.to_string(),
+                    best_move: None,
+                    pv: Vec::new(),
+                    depth: None,
+                    advantage: None,
+                    error: Some(format!("{:#}", e)),
+                },
+            };
+            println!("{}", serde_json::to_string(&line)?);
+        } else {
+            match &result {
+                Ok(res) => println!("{}\n{}\n", fen, res.describe()),
+                Err(e) => println!("{}\nError: {:#}\n", fen, e),
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} positions failed", failed, positions.len());
+    }
+    Ok(())
+}
+
+/// Whether the engine of the profile can ponder, warning if it can't
+fn pondering_available(profile: &Profile) -> bool {
+    let available = cfg!(feature = "native") && profile.backend_kind() == Some(BackendKind::Native);
+    if !available {
+        log::warn!("pondering requires the native backend, ignoring --ponder");
+    }
+    available
+}
+
+fn play(selection: &Selection, fen: Option<Fen>, black: bool, ponder: bool) -> Result<()> {
+    let profile = selection.profile()?;
+    let depth = depth(&profile)?;
+    let variant = profile.variant();
+    let backend = selection.backend(&profile)?;
+    // Pondering needs an engine running between the moves, so it's ignored
+    // for the other backends
+    #[cfg_attr(not(feature = "native"), allow(unused_variables))]
+    let ponder = ponder && pondering_available(&profile);
+    #[cfg(feature = "native")]
+    let mut pondering = if ponder {
+        Some(game::PonderingEngine::new(
+            profile.live_engine()?,
+            variant,
+            depth,
+        ))
+    } else {
+        None
+    };
+    let human = if black { Color::Black } else { Color::White };
+    let mut game = Game::new(fen.unwrap_or_else(initial_position))?;
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if let Some((result, termination)) = game.result() {
+            println!("{}\n{} ({})", game.movetext(), result, termination);
+            return Ok(());
+        }
+        if game.turn() == human {
+            print!("{}\nYour move: ", game.fen());
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+            let line = line.trim();
+            if line == "quit" || line == "resign" {
+                println!("{}\nResigned", game.movetext());
+                return Ok(());
+            }
+            match game::parse_move(game.position(), line) {
+                Ok(m) => game.play(&m)?,
+                Err(e) => eprintln!("{}", e),
+            }
+        } else {
+            #[cfg(feature = "native")]
+            let result = match &mut pondering {
+                Some(engine) => engine.engine_move(&game)?,
+                None => game::engine_move(&*backend, variant, &game, depth)?,
+            };
+            #[cfg(not(feature = "native"))]
+            let result = game::engine_move(&*backend, variant, &game, depth)?;
+            println!(
+                "Engine plays {}",
+                San::from_move(game.position(), &result.best_move)
+            );
+            game.play(&result.best_move)?;
+            #[cfg(feature = "native")]
+            {
+                if let Some(engine) = &mut pondering {
+                    engine.ponder(&game, &result)?;
+                }
+            }
+        }
+    }
+}
+
+fn run_match(
+    selection: &Selection,
+    (first, second): (&str, &str),
+    openings: Option<&Path>,
+    max_plies: u32,
+    ponder: bool,
+    json: bool,
+) -> Result<()> {
+    let first_profile = selection.named_profile(Some(first))?;
+    let second_profile = selection.named_profile(Some(second))?;
+    let first_backend = selection.backend(&first_profile)?;
+    let second_backend = selection.backend(&second_profile)?;
+    let first_depth = depth(&first_profile)?;
+    let second_depth = depth(&second_profile)?;
+    let variant = first_profile.variant();
+    let openings = match openings {
+        Some(path) => read_positions(&read_input(path)?)?,
+        None => vec![initial_position()],
+    };
+    // Pondering needs an engine running between the moves, so it's ignored
+    // for the engines of the other backends
+    #[cfg_attr(not(feature = "native"), allow(unused_variables))]
+    let ponders = [&first_profile, &second_profile]
+        .iter()
+        .map(|profile| ponder && pondering_available(profile))
+        .collect::<Vec<_>>();
+    #[cfg(feature = "native")]
+    let pondering = |profile: &Profile, ponders, depth| -> Result<_> {
+        if !ponders {
+            return Ok(None);
+        }
+        let engine = game::PonderingEngine::new(profile.live_engine()?, variant, depth);
+        Ok(Some(RefCell::new(engine)))
+    };
+    #[cfg(feature = "native")]
+    let (first_pondering, second_pondering) = (
+        pondering(&first_profile, ponders[0], first_depth)?,
+        pondering(&second_profile, ponders[1], second_depth)?,
+    );
+
+    let report = game::play_match(
+        game::Player {
+            name: first,
+            backend: &*first_backend,
+            depth: first_depth,
+            #[cfg(feature = "native")]
+            pondering: first_pondering.as_ref(),
+        },
+        game::Player {
+            name: second,
+            backend: &*second_backend,
+            depth: second_depth,
+            #[cfg(feature = "native")]
+            pondering: second_pondering.as_ref(),
+        },
+        variant,
+        &openings,
+        max_plies,
+    )?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report.describe());
+    }
+    Ok(())
+}
+
+fn engine_info(selection: &Selection, json: bool) -> Result<()> {
+    let profile = selection.profile()?;
+    let backend = selection.backend(&profile)?;
+    let info = engine_info::probe(&*backend)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!("{}", info.describe());
+    }
+    Ok(())
+}
+
+fn bench(selection: &Selection, names: &[String], json: bool) -> Result<()> {
+    let profiles = if names.is_empty() {
+        vec![selection.profile()?]
+    } else {
+        names
+            .iter()
+            .map(|name| selection.named_profile(Some(name)))
+            .collect::<Result<_>>()?
+    };
+
+    let mut reports = Vec::new();
+    for profile in &profiles {
+        // The cache would measure nothing
+        let mut backend = profile.backend()?;
+        let depth = profile.depth.unwrap_or(bench::DEFAULT_BENCH_DEPTH);
+        let report = bench::run_bench(&mut *backend, Arc::new(ProgressBar::default()), depth)?;
+        if !json {
+            println!("{}\n", report.describe());
+        }
+        reports.push(report);
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+    Ok(())
+}
+
+/// The depth of the searches without a limit, unless the profile sets one
+const DEFAULT_PROXY_DEPTH: u32 = 20;
+
+fn run_proxy(selection: &Selection) -> Result<()> {
+    let profile = selection.profile()?;
+    let backend = selection.backend(&profile)?;
+    let depth = profile.depth.unwrap_or(DEFAULT_PROXY_DEPTH);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    UciProxy::new(&*backend, depth).serve(stdin.lock(), stdout.lock())
+}
+
+#[cfg(feature = "serve")]
+fn serve(
+    selection: &Selection,
+    listen: &std::net::SocketAddr,
+    queue_size: usize,
+    workers: usize,
+) -> Result<()> {
+    use golemate::server::AnalysisService;
+
+    let config = selection.config()?;
+    let profiles = config
+        .profiles
+        .keys()
+        .map(|name| Ok((name.clone(), selection.named_profile(Some(name))?)))
+        .collect::<Result<_>>()?;
+    let service = AnalysisService::new(selection.profile()?)
+        .profiles(profiles)
+        .cache(selection.enabled_cache())
+        .queue_size(queue_size)
+        .workers(workers);
+    #[cfg(feature = "history")]
+    let service = service.history(selection.enabled_history());
+
+    let mut runtime = tokio::runtime::Runtime::new().context("starting the runtime")?;
+    runtime.block_on(async {
+        let (addr, server) = service.bind(listen)?;
+        println!("Listening on http://{}", addr);
+        io::stdout().flush()?;
+        server.await
+    })
+}
+
+fn analyse(selection: &Selection, opts: &AnalyseOpts) -> Result<()> {
+    let fen = opts.fen.clone().expect("inconsistent analysis opts");
+    let mut profile = selection.profile()?;
+    opts.apply(&mut profile);
+    let depth = depth(&profile)?;
+    let variant = profile.variant();
+    variant.validate(&fen)?;
+    #[cfg(feature = "native")]
+    {
+        if opts.infinite {
+            return analyse_infinite(&profile, variant, fen);
+        }
+    }
+    // Opening books and tablebases only cover standard chess
+    let standard = variant == Variant::Standard;
+
+    if let (Some(book), false, true) = (&profile.book, opts.raw_uci, standard) {
+        let book = Book::open(book)?;
+        let pos: Chess = fen.position()?;
+        let entries = book.lookup(&pos);
+        if !entries.is_empty() {
+            println!("{}", book::describe_book_moves(&pos, &entries));
+            return Ok(());
+        }
+    }
+
+    #[cfg(feature = "syzygy")]
+    {
+        if !profile.syzygy.is_empty() && !opts.raw_uci && standard {
+            let tablebase = golemate::tablebase::Tablebase::open(&profile.syzygy)?;
+            if let Some(result) = tablebase.probe(&fen)? {
+                println!("{}", result.describe());
+                return Ok(());
+            }
+        }
+    }
+
+    let backend = selection.backend(&profile)?;
+    let cmds = backend.generate_variant_uci(variant, &fen.to_string(), depth);
     let output = backend.execute_uci(cmds).context("Executing UCI")?;
     if opts.raw_uci {
         for line in output {
             println!("{}", line);
         }
     } else {
-        let an_res = analysis::interpret_uci(opts.fen, output)?;
+        let mut an_res = analysis::interpret_variant_uci(variant, fen, output)?;
+        an_res.max_cost = backend.max_cost();
         println!("{}", an_res.describe());
     }
 
     Ok(())
 }
+
+/// How often the continuous analysis checks whether it has been stopped
+#[cfg(feature = "native")]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Prints the search as it goes, until the user presses Enter
+#[cfg(feature = "native")]
+fn analyse_infinite(profile: &Profile, variant: Variant, fen: Fen) -> Result<()> {
+    use golemate::backends::LiveEvent;
+
+    let mut engine = profile.live_engine()?;
+    engine.go_infinite(&format!("fen {}", fen))?;
+    eprintln!("Analysing, press Enter to stop");
+    let stopped = Arc::new(AtomicBool::new(false));
+    let stop = stopped.clone();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        // Stop on the end of the input too
+        let _ = io::stdin().read_line(&mut line);
+        stop.store(true, Ordering::SeqCst);
+    });
+    while !stopped.load(Ordering::SeqCst) {
+        match engine.next_event(POLL_INTERVAL)? {
+            Some(LiveEvent::Info(info)) => println!("{}", info.describe()),
+            // The engine may stop by itself, e.g. in a mate
+            Some(LiveEvent::BestMove { .. }) => break,
+            None => {}
+        }
+    }
+    let output = engine.stop().context("stopping the analysis")?;
+    let result = analysis::interpret_variant_uci(variant, fen, output)?;
+    println!("{}", result.describe());
+    Ok(())
+}
@@ -0,0 +1,365 @@
+//! Named engine profiles, read from a TOML configuration file
+//!
+//! ```toml
+//! default_profile = "local"
+//!
+//! [profiles.local]
+//! engine = "/usr/bin/stockfish"
+//! depth = 20
+//! options = { Threads = 8, Hash = 2048 }
+//!
+//! [profiles.golem]
+//! wasm = "stockfish.wasm"
+//! js = "stockfish.js"
+//! datadir = "/home/user/.local/share/golem/default/rinkeby"
+//! max_price = 0.5
+//! golem = { address = "127.0.0.1", port = 61001 }
+//!
+//! [profiles.sandboxed]
+//! engine = "/usr/bin/stockfish"
+//! limits = { memory = 512, cpus = [0, 1], wall_clock = 60, sandbox = {} }
+//! ```
+//!
+//! Relative paths are resolved against the directory of the file, except
+//! for an engine given as a bare command, e.g. `stockfish`, which is looked
+//! up in `PATH`. The GUI saves the profiles edited by the user to the file.
+
+use crate::backends::{ResourceLimits, UciBackend, UciOption};
+use crate::variant::Variant;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "async")]
+use crate::backends::AsyncUciBackend;
+use crate::backends::KeepWorkspace;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The profile used when none is selected explicitly
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Native,
+    Gwasm,
+}
+
+/// The value of a UCI option, as written in the configuration file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionValue::Bool(value) => write!(f, "{}", value),
+            OptionValue::Integer(value) => write!(f, "{}", value),
+            OptionValue::String(value) => f.write_str(value),
+        }
+    }
+}
+
+/// How to reach the Golem client
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GolemConnection {
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub mainnet: bool,
+}
+
+/// An engine together with the backend running it and the default limits
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Inferred from the paths if not given
+    pub backend: Option<BackendKind>,
+    /// Path to the engine run by the native backend
+    pub engine: Option<PathBuf>,
+    pub wasm: Option<PathBuf>,
+    pub js: Option<PathBuf>,
+    pub workspace: Option<PathBuf>,
+    /// Ignored without the gWASM backend
+    pub keep_workspace: Option<KeepWorkspace>,
+    pub datadir: Option<PathBuf>,
+    pub golem: GolemConnection,
+    /// In seconds
+    pub task_timeout: Option<u64>,
+    /// In seconds
+    pub subtask_timeout: Option<u64>,
+    pub max_price: Option<f64>,
+    pub budget: Option<f64>,
+    /// Copies of every search submitted to Golem, each paid for
+    pub replicas: Option<u32>,
+    /// UCI options overriding the defaults of the backend
+    pub options: BTreeMap<String, OptionValue>,
+    pub depth: Option<u32>,
+    pub variant: Option<Variant>,
+    pub book: Option<PathBuf>,
+    pub syzygy: Vec<PathBuf>,
+    /// Only applies to the native backend
+    pub limits: ResourceLimits,
+    /// The number of native engines analysing batches in parallel
+    pub sessions: Option<usize>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/golemate/config.toml`, usually in `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("golemate").join("config.toml"))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).context("parsing the configuration")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading the configuration {}", path.display()))?;
+        let mut config = Self::from_toml(&text)?;
+        if let Some(base) = path.parent() {
+            for profile in config.profiles.values_mut() {
+                profile.resolve_paths(base);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Writes the configuration to `path`, with absolute paths
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // A `Value` writes the plain values before the tables, as TOML requires
+        let value = toml::Value::try_from(self).context("serializing the configuration")?;
+        let text = toml::to_string_pretty(&value).context("serializing the configuration")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("creating the directory {}", dir.display()))?;
+        }
+        fs::write(path, text)
+            .with_context(|| format!("writing the configuration {}", path.display()))
+    }
+
+    /// Loads the file at the default path, if there is one
+    pub fn load_default() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// The profile called `name`, or the default one.
+    ///
+    /// An empty profile is returned if there's no default.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        match name.or_else(|| self.default_profile.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("no profile named `{}` in the configuration", name)),
+            None => Ok(Profile::default()),
+        }
+    }
+}
+
+impl Profile {
+    fn resolve_paths(&mut self, base: &Path) {
+        // A bare command is looked up in `PATH`, like on the command line
+        let engine = self
+            .engine
+            .iter_mut()
+            .filter(|engine| engine.components().count() > 1);
+        let paths = engine
+            .chain(self.wasm.iter_mut())
+            .chain(self.js.iter_mut())
+            .chain(self.workspace.iter_mut())
+            .chain(self.datadir.iter_mut())
+            .chain(self.book.iter_mut())
+            .chain(self.syzygy.iter_mut());
+        let sandbox = self
+            .limits
+            .sandbox
+            .iter_mut()
+            .flat_map(|sandbox| sandbox.read.iter_mut().chain(sandbox.write.iter_mut()));
+        for path in paths.chain(sandbox) {
+            if path.is_relative() {
+                *path = base.join(&path);
+            }
+        }
+    }
+
+    pub fn backend_kind(&self) -> Option<BackendKind> {
+        self.backend.or_else(|| {
+            if self.wasm.is_some() {
+                Some(BackendKind::Gwasm)
+            } else if self.engine.is_some() {
+                Some(BackendKind::Native)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn uci_options(&self) -> Vec<UciOption> {
+        self.options
+            .iter()
+            .map(|(name, value)| UciOption::new(name.as_str(), value))
+            .collect()
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant.unwrap_or_default()
+    }
+
+    /// Sets up the backend described by the profile
+    pub fn backend(&self) -> Result<Box<dyn UciBackend>> {
+        match self.backend_kind() {
+            Some(BackendKind::Native) => self.native_backend(),
+            Some(BackendKind::Gwasm) => self.gwasm_backend(),
+            None => bail!("No backend selected, see --help"),
+        }
+    }
+
+    /// Sets up the backend for analysing many positions, a pool of engines
+    /// if the profile asks for several native sessions
+    pub fn batch_backend(&self) -> Result<Box<dyn UciBackend>> {
+        match (self.backend_kind(), self.sessions) {
+            (Some(BackendKind::Native), Some(sessions)) if sessions > 1 => {
+                self.engine_pool(sessions)
+            }
+            _ => self.backend(),
+        }
+    }
+
+    #[cfg(feature = "native")]
+    fn engine_pool(&self, sessions: usize) -> Result<Box<dyn UciBackend>> {
+        use crate::backends::EnginePool;
+
+        let engine = self
+            .engine
+            .clone()
+            .context("the native backend requires the engine path")?;
+        Ok(Box::new(
+            EnginePool::new(engine, sessions)
+                .uci_options(self.uci_options())
+                .limits(self.limits.clone()),
+        ))
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn engine_pool(&self, _sessions: usize) -> Result<Box<dyn UciBackend>> {
+        self.native_backend()
+    }
+
+    #[cfg(feature = "native")]
+    fn native_backend(&self) -> Result<Box<dyn UciBackend>> {
+        Ok(Box::new(self.native_uci()?))
+    }
+
+    #[cfg(feature = "native")]
+    fn native_uci(&self) -> Result<crate::backends::NativeUci> {
+        use crate::backends::NativeUci;
+
+        let engine = self
+            .engine
+            .clone()
+            .context("the native backend requires the engine path")?;
+        Ok(NativeUci::new(engine)
+            .uci_options(self.uci_options())
+            .limits(self.limits.clone()))
+    }
+
+    /// Starts the engine of the native backend for the searches followed
+    /// live, with the options of the profile and of its variant
+    #[cfg(feature = "native")]
+    pub fn live_engine(&self) -> Result<crate::backends::LiveEngine> {
+        use crate::backends::LiveEngine;
+
+        if self.backend_kind() != Some(BackendKind::Native) {
+            bail!("the continuous analysis and the pondering require the native backend");
+        }
+        let mut options = self.native_uci()?.get_uci_opts();
+        options.extend(self.variant().uci_options());
+        let engine = self
+            .engine
+            .as_ref()
+            .expect("the native backend has an engine");
+        LiveEngine::start(engine, &options, &self.limits)
+    }
+
+    /// Sets up the backend for async code: native engines are driven
+    /// without blocking, the other backends run on the blocking threads
+    #[cfg(feature = "async")]
+    pub fn async_backend(&self) -> Result<Box<dyn AsyncUciBackend>> {
+        use crate::backends::BlockingUci;
+
+        match self.backend_kind() {
+            #[cfg(feature = "native")]
+            Some(BackendKind::Native) => Ok(Box::new(self.native_uci()?)),
+            _ => Ok(Box::new(BlockingUci::new(self.backend()?))),
+        }
+    }
+
+    #[cfg(not(feature = "native"))]
+    fn native_backend(&self) -> Result<Box<dyn UciBackend>> {
+        bail!("golemate has been built without the native backend")
+    }
+
+    #[cfg(feature = "gwasm")]
+    fn gwasm_backend(&self) -> Result<Box<dyn UciBackend>> {
+        use crate::backends::{GWasmUci, GolemRpc};
+        use std::time::Duration;
+
+        let missing = |what| anyhow!("the gWASM backend requires the {}", what);
+        let wasm = self.wasm.as_ref().ok_or_else(|| missing("WASM path"))?;
+        let js = self.js.as_ref().ok_or_else(|| missing("JS path"))?;
+        let datadir = self.datadir.clone().ok_or_else(|| missing("datadir"))?;
+
+        if !self.limits.is_empty() {
+            bail!("resource limits are only supported by the native backend");
+        }
+        let mut golem = GolemRpc::new(datadir).mainnet(self.golem.mainnet);
+        if let Some(address) = &self.golem.address {
+            golem = golem.address(address.clone());
+        }
+        if let Some(port) = self.golem.port {
+            golem = golem.port(port);
+        }
+
+        let mut backend = GWasmUci::with_golem(wasm, js, self.workspace.clone(), golem)?
+            .keep_workspace(self.keep_workspace.unwrap_or_default())
+            .budget(self.budget)
+            .replicas(self.replicas.unwrap_or(1))
+            .uci_options(self.uci_options());
+        if let Some(secs) = self.task_timeout {
+            backend = backend.task_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.subtask_timeout {
+            backend = backend.subtask_timeout(Duration::from_secs(secs));
+        }
+        if let Some(price) = self.max_price {
+            backend = backend.max_price(price);
+        }
+        Ok(Box::new(backend))
+    }
+
+    #[cfg(not(feature = "gwasm"))]
+    fn gwasm_backend(&self) -> Result<Box<dyn UciBackend>> {
+        bail!("golemate has been built without the gWASM backend")
+    }
+}
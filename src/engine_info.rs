@@ -0,0 +1,178 @@
+//! The identity and the options advertised by an engine in the UCI handshake
+
+use crate::backends::UciBackend;
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+/// An option advertised with `option name ... type ...`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EngineOption {
+    pub name: String,
+    /// `check`, `spin`, `combo`, `button` or `string`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub default: Option<String>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    /// The allowed values of a `combo`
+    pub vars: Vec<String>,
+}
+
+/// The features of the engine, deduced from its options
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Capabilities {
+    pub chess960: bool,
+    pub multipv: Option<(i64, i64)>,
+    pub wdl: bool,
+    pub ponder: bool,
+    /// The values of `UCI_Variant`
+    pub variants: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EngineInfo {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub options: Vec<EngineOption>,
+    pub capabilities: Capabilities,
+}
+
+const OPTION_KEYWORDS: [&str; 6] = ["name", "type", "default", "min", "max", "var"];
+
+impl EngineOption {
+    /// Parses the part of the line after `option`
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut fields: Vec<(&str, Vec<&str>)> = Vec::new();
+        for word in line.split_whitespace() {
+            // The name may contain the other keywords, it ends with `type`
+            let in_name = matches!(fields.last(), Some(("name", _)));
+            if OPTION_KEYWORDS.contains(&word) && (!in_name || word == "type") {
+                fields.push((word, Vec::new()));
+            } else {
+                match fields.last_mut() {
+                    Some((_, words)) => words.push(word),
+                    None => bail!("unexpected `{}` in the option", word),
+                }
+            }
+        }
+
+        let value = |key| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, words)| words.join(" "))
+        };
+        let number = |key| -> Result<Option<i64>> {
+            value(key)
+                .map(|v| {
+                    v.parse()
+                        .with_context(|| format!("invalid {} `{}`", key, v))
+                })
+                .transpose()
+        };
+        Ok(Self {
+            name: value("name").context("missing option name")?,
+            kind: value("type").context("missing option type")?,
+            default: value("default"),
+            min: number("min")?,
+            max: number("max")?,
+            vars: fields
+                .iter()
+                .filter(|(k, _)| *k == "var")
+                .map(|(_, words)| words.join(" "))
+                .collect(),
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut details = vec![self.kind.clone()];
+        if let Some(default) = &self.default {
+            details.push(format!("default {}", default));
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            details.push(format!("{}-{}", min, max));
+        }
+        if !self.vars.is_empty() {
+            details.push(self.vars.join("/"));
+        }
+        format!("{} ({})", self.name, details.join(", "))
+    }
+}
+
+impl EngineInfo {
+    /// Reads the output of the `uci` command
+    pub fn parse(output: &[String]) -> Result<Self> {
+        let mut name = None;
+        let mut author = None;
+        let mut options = Vec::new();
+        let mut handshake = false;
+        for line in output {
+            let line = line.trim();
+            if let Some(id) = line.strip_prefix("id ") {
+                if let Some(value) = id.strip_prefix("name ") {
+                    name = Some(value.trim().to_owned());
+                } else if let Some(value) = id.strip_prefix("author ") {
+                    author = Some(value.trim().to_owned());
+                }
+            } else if let Some(option) = line.strip_prefix("option ") {
+                options.push(
+                    EngineOption::parse(option).with_context(|| format!("parsing `{}`", line))?,
+                );
+            } else if line == "uciok" {
+                handshake = true;
+            }
+        }
+        if !handshake {
+            bail!("the engine didn't complete the UCI handshake");
+        }
+
+        let find = |name: &str| {
+            options
+                .iter()
+                .find(|option: &&EngineOption| option.name.eq_ignore_ascii_case(name))
+        };
+        let capabilities = Capabilities {
+            chess960: find("UCI_Chess960").is_some(),
+            multipv: find("MultiPV").map(|o| (o.min.unwrap_or(1), o.max.unwrap_or(1))),
+            wdl: find("UCI_ShowWDL").is_some(),
+            ponder: find("Ponder").is_some(),
+            variants: find("UCI_Variant").map_or_else(Vec::new, |o| o.vars.clone()),
+        };
+        Ok(Self {
+            name,
+            author,
+            options,
+            capabilities,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let yes_no = |b| if b { "yes" } else { "no" };
+        let caps = &self.capabilities;
+        let mut lines = vec![
+            format!("Engine: {}", self.name.as_deref().unwrap_or("unknown")),
+            format!("Author: {}", self.author.as_deref().unwrap_or("unknown")),
+            format!("Chess960: {}", yes_no(caps.chess960)),
+            match caps.multipv {
+                Some((min, max)) => format!("MultiPV: yes ({}-{})", min, max),
+                None => "MultiPV: no".to_owned(),
+            },
+            format!("WDL: {}", yes_no(caps.wdl)),
+            format!("Ponder: {}", yes_no(caps.ponder)),
+        ];
+        if !caps.variants.is_empty() {
+            lines.push(format!("Variants: {}", caps.variants.join(", ")));
+        }
+        lines.push("Options:".to_owned());
+        lines.extend(self.options.iter().map(|o| format!("  {}", o.describe())));
+        lines.join("\n")
+    }
+}
+
+/// Runs only the UCI handshake on the backend
+pub fn probe(backend: &dyn UciBackend) -> Result<EngineInfo> {
+    let output = backend
+        .execute_uci(vec!["uci".to_owned(), "quit".to_owned()])
+        .context("running the UCI handshake")?;
+    EngineInfo::parse(&output)
+}
@@ -0,0 +1,259 @@
+//! Test suites in the Extended Position Description format
+//!
+//! Supported are the `bm` (best move), `am` (avoid move), `dm` (direct mate)
+//! and `id` opcodes. The other opcodes are ignored.
+
+use crate::analysis::{interpret_uci, Advantage, AnalysisResult};
+use crate::backends::UciBackend;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use shakmaty::fen::Fen;
+use shakmaty::san::San;
+use shakmaty::{Chess, Move};
+
+/// A test position
+pub struct EpdRecord {
+    pub fen: Fen,
+    pub id: Option<String>,
+    pub best_moves: Vec<Move>,
+    pub avoid_moves: Vec<Move>,
+    /// The side to move mates in this many moves
+    pub direct_mate: Option<u32>,
+}
+
+/// Splits the operations, respecting the quoted strings.
+fn split_operations(ops: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let mut result = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in ops.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    result.push((opcode, std::mem::take(&mut words)));
+                }
+            }
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated string");
+    }
+    if !word.is_empty() || !words.is_empty() {
+        bail!("operation not terminated with a semicolon");
+    }
+    Ok(result)
+}
+
+pub(crate) fn parse_san(pos: &Chess, san: &str) -> Result<Move> {
+    // Annotations such as check marks are not part of the SAN
+    let trimmed = san.trim_end_matches(|c| "+#!?".contains(c));
+    let parsed: San = trimmed
+        .parse()
+        .map_err(|_| anyhow!("invalid move `{}`", san))?;
+    parsed
+        .to_move(pos)
+        .map_err(|_| anyhow!("illegal move `{}`", san))
+}
+
+impl EpdRecord {
+    pub fn parse(line: &str) -> Result<Self> {
+        // The fields may be separated by any whitespace, e.g. tabs
+        let position: Vec<_> = line.split_whitespace().take(4).collect();
+        if position.len() < 4 {
+            bail!("missing position fields");
+        }
+        // The operations are the rest of the line, with their strings intact
+        let last = position[3];
+        let ops_start = last.as_ptr() as usize - line.as_ptr() as usize + last.len();
+        let ops = line[ops_start..].trim();
+
+        let fen: Fen = format!("{} 0 1", position.join(" "))
+            .parse()
+            .map_err(|_| anyhow!("invalid position"))?;
+        let pos: Chess = fen.position().context("invalid position")?;
+
+        let mut record = EpdRecord {
+            fen,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            direct_mate: None,
+        };
+        for (opcode, operands) in split_operations(ops)? {
+            match opcode.as_str() {
+                "bm" => {
+                    for san in &operands {
+                        record.best_moves.push(parse_san(&pos, san)?);
+                    }
+                }
+                "am" => {
+                    for san in &operands {
+                        record.avoid_moves.push(parse_san(&pos, san)?);
+                    }
+                }
+                "dm" => {
+                    let moves = operands.first().context("missing direct mate length")?;
+                    record.direct_mate = Some(moves.parse().context("invalid direct mate")?);
+                }
+                "id" => record.id = operands.first().cloned(),
+                _ => {}
+            }
+        }
+        Ok(record)
+    }
+
+    /// Parses a whole suite, skipping the empty lines and the `#` comments
+    pub fn parse_suite(text: &str) -> Result<Vec<Self>> {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+            .map(|(idx, line)| {
+                Self::parse(line.trim()).with_context(|| format!("line {}", idx + 1))
+            })
+            .collect()
+    }
+
+    /// Whether the analysis solves the position
+    pub fn is_solved_by(&self, result: &AnalysisResult) -> bool {
+        let best_ok = self.best_moves.is_empty() || self.best_moves.contains(&result.best_move);
+        let avoid_ok = !self.avoid_moves.contains(&result.best_move);
+        let mate_ok = match self.direct_mate {
+            None => true,
+            Some(moves) => match result.advantage {
+                Advantage::Mate(found) => result.advantage_side == self.fen.turn && found <= moves,
+                _ => false,
+            },
+        };
+        best_ok && avoid_ok && mate_ok
+    }
+
+    /// Describes what's expected, e.g. `bm Nf3 e4; dm 3`
+    pub fn describe_expected(&self) -> String {
+        let pos: Chess = self.fen.position().expect("validated while parsing");
+        let san = |moves: &[Move]| {
+            moves
+                .iter()
+                .map(|m| San::from_move(&pos, m).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut expected = Vec::new();
+        if !self.best_moves.is_empty() {
+            expected.push(format!("bm {}", san(&self.best_moves)));
+        }
+        if !self.avoid_moves.is_empty() {
+            expected.push(format!("am {}", san(&self.avoid_moves)));
+        }
+        if let Some(moves) = self.direct_mate {
+            expected.push(format!("dm {}", moves));
+        }
+        expected.join("; ")
+    }
+}
+
+/// The outcome of a single position of a suite
+#[derive(Debug, Serialize)]
+pub struct EpdOutcome {
+    pub id: String,
+    pub fen: String,
+    pub expected: String,
+    /// The best move found, in SAN
+    pub found: Option<String>,
+    pub depth: Option<u32>,
+    pub solved: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EpdReport {
+    pub engine: String,
+    pub depth: u32,
+    pub positions: Vec<EpdOutcome>,
+    pub solved: usize,
+    pub total: usize,
+}
+
+impl EpdReport {
+    pub fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        for outcome in &self.positions {
+            let found = match (&outcome.found, &outcome.error) {
+                (_, Some(error)) => format!("error: {}", error),
+                (Some(found), None) => format!("{} (depth {})", found, outcome.depth.unwrap_or(0)),
+                (None, None) => "-".to_owned(),
+            };
+            lines.push(format!(
+                "{:<6} {:<24} expected {:<20} found {}",
+                if outcome.solved { "OK" } else { "FAIL" },
+                outcome.id,
+                outcome.expected,
+                found
+            ));
+        }
+        lines.push(format!(
+            "Score: {}/{} at depth {}",
+            self.solved, self.total, self.depth
+        ));
+        lines.join("\n")
+    }
+}
+
+/// Analyses every position of the suite to `depth` and scores the best moves.
+///
+/// The positions are analysed as a batch, in parallel if the backend
+/// supports it. A failure to analyse a position counts as not solving it.
+pub fn run_suite(backend: &dyn UciBackend, records: &[EpdRecord], depth: u32) -> EpdReport {
+    let cmds = records
+        .iter()
+        .map(|record| backend.generate_uci(&record.fen.to_string(), depth))
+        .collect();
+    let outputs = backend.execute_batch(cmds);
+
+    let mut positions = Vec::new();
+    for (idx, (record, output)) in records.iter().zip(outputs).enumerate() {
+        let id = record.id.clone().unwrap_or_else(|| format!("#{}", idx + 1));
+        let mut outcome = EpdOutcome {
+            id,
+            fen: record.fen.to_string(),
+            expected: record.describe_expected(),
+            found: None,
+            depth: None,
+            solved: false,
+            error: None,
+        };
+        let result = output
+            .context("executing UCI")
+            .and_then(|output| interpret_uci(record.fen.clone(), output));
+        match result {
+            Ok(result) => {
+                let pos: Chess = record.fen.position().expect("validated while parsing");
+                outcome.found = Some(San::from_move(&pos, &result.best_move).to_string());
+                outcome.depth = Some(result.depth);
+                outcome.solved = record.is_solved_by(&result);
+            }
+            Err(e) => outcome.error = Some(format!("{:#}", e)),
+        }
+        positions.push(outcome);
+    }
+
+    let solved = positions.iter().filter(|outcome| outcome.solved).count();
+    EpdReport {
+        engine: backend.engine_id(),
+        depth,
+        total: positions.len(),
+        solved,
+        positions,
+    }
+}
@@ -0,0 +1,394 @@
+//! Games played by the engines, against each other or against a human
+
+use crate::analysis::{interpret_variant_uci, AnalysisResult};
+#[cfg(feature = "native")]
+use crate::backends::LiveEngine;
+use crate::backends::UciBackend;
+use crate::variant::Variant;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use shakmaty::fen::{self, Fen};
+use shakmaty::san::{San, SanPlus};
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Color, Move, Outcome, Position};
+#[cfg(feature = "native")]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        })
+    }
+}
+
+/// Reads a move in SAN or UCI
+pub fn parse_move(pos: &Chess, text: &str) -> Result<Move> {
+    let text = text.trim_end_matches(|c| "+#!?".contains(c));
+    if let Ok(san) = text.parse::<San>() {
+        if let Ok(m) = san.to_move(pos) {
+            return Ok(m);
+        }
+    }
+    let uci: Uci = text
+        .parse()
+        .map_err(|_| anyhow!("invalid move `{}`", text))?;
+    uci.to_move(pos)
+        .map_err(|_| anyhow!("illegal move `{}`", text))
+}
+
+/// A game of chess or Chess960 from a given start position
+pub struct Game {
+    start: Fen,
+    position: Chess,
+    moves: Vec<SanPlus>,
+    /// Occurrences of the positions, for the threefold repetition
+    seen: HashMap<String, u32>,
+}
+
+impl Game {
+    pub fn new(start: Fen) -> Result<Self> {
+        let position: Chess = start.position().context("illegal start position")?;
+        let mut game = Self {
+            start,
+            position,
+            moves: Vec::new(),
+            seen: HashMap::new(),
+        };
+        game.record_position();
+        Ok(game)
+    }
+
+    fn record_position(&mut self) {
+        *self.seen.entry(fen::epd(&self.position)).or_insert(0) += 1;
+    }
+
+    pub fn start(&self) -> &Fen {
+        &self.start
+    }
+
+    pub fn position(&self) -> &Chess {
+        &self.position
+    }
+
+    pub fn fen(&self) -> String {
+        fen::fen(&self.position)
+    }
+
+    pub fn turn(&self) -> Color {
+        self.position.turn()
+    }
+
+    pub fn moves(&self) -> &[SanPlus] {
+        &self.moves
+    }
+
+    /// Plays a move, which must be legal
+    pub fn play(&mut self, m: &Move) -> Result<()> {
+        if !self.position.is_legal(m) {
+            bail!("illegal move {}", m);
+        }
+        let san = SanPlus::from_move_and_play_unchecked(&mut self.position, m);
+        self.moves.push(san);
+        self.record_position();
+        Ok(())
+    }
+
+    /// The result of the game, if it's over by the rules
+    pub fn result(&self) -> Option<(GameResult, &'static str)> {
+        if let Some(outcome) = self.position.outcome() {
+            return Some(match outcome {
+                Outcome::Decisive {
+                    winner: Color::White,
+                } => (GameResult::WhiteWins, "checkmate"),
+                Outcome::Decisive {
+                    winner: Color::Black,
+                } => (GameResult::BlackWins, "checkmate"),
+                Outcome::Draw if self.position.is_stalemate() => (GameResult::Draw, "stalemate"),
+                Outcome::Draw => (GameResult::Draw, "insufficient material"),
+            });
+        }
+        if self.position.halfmoves() >= 100 {
+            return Some((GameResult::Draw, "fifty-move rule"));
+        }
+        if self.seen.get(&fen::epd(&self.position)) >= Some(&3) {
+            return Some((GameResult::Draw, "threefold repetition"));
+        }
+        None
+    }
+
+    /// The moves in the PGN movetext notation, without the result
+    pub fn movetext(&self) -> String {
+        let mut text = String::new();
+        let mut fullmove = u32::from(self.start.fullmoves);
+        let mut turn = self.start.turn;
+        for (i, san) in self.moves.iter().enumerate() {
+            if i > 0 {
+                text.push(' ');
+            }
+            match turn {
+                Color::White => text.push_str(&format!("{}. ", fullmove)),
+                Color::Black if i == 0 => text.push_str(&format!("{}... ", fullmove)),
+                Color::Black => {}
+            }
+            text.push_str(&san.to_string());
+            if turn == Color::Black {
+                fullmove += 1;
+            }
+            turn = !turn;
+        }
+        text
+    }
+}
+
+/// Asks the engine for the best move in the current position of the game
+pub fn engine_move(
+    backend: &dyn UciBackend,
+    variant: Variant,
+    game: &Game,
+    depth: u32,
+) -> Result<AnalysisResult> {
+    let fen: Fen = game
+        .fen()
+        .parse()
+        .expect("shakmaty returned an invalid FEN");
+    let cmds = backend.generate_variant_uci(variant, &game.fen(), depth);
+    let output = backend.execute_uci(cmds).context("executing UCI")?;
+    let mut result = interpret_variant_uci(variant, fen, output)?;
+    result.max_cost = backend.max_cost();
+    Ok(result)
+}
+
+/// An engine playing a game and thinking on the opponent's time.
+///
+/// After its move, the engine ponders on the reply it expects. If the
+/// opponent plays it, the search goes on (`ponderhit`), otherwise it's
+/// stopped and a new one starts.
+#[cfg(feature = "native")]
+pub struct PonderingEngine {
+    engine: LiveEngine,
+    variant: Variant,
+    depth: u32,
+    /// The position the engine is pondering on, as an EPD
+    pondering: Option<String>,
+}
+
+#[cfg(feature = "native")]
+impl PonderingEngine {
+    pub fn new(engine: LiveEngine, variant: Variant, depth: u32) -> Self {
+        Self {
+            engine,
+            variant,
+            depth,
+            pondering: None,
+        }
+    }
+
+    /// The best move in the current position of the game
+    pub fn engine_move(&mut self, game: &Game) -> Result<AnalysisResult> {
+        let fen = game.fen();
+        let output = if self.pondering.take() == Some(fen::epd(game.position())) {
+            self.engine.ponderhit()?;
+            self.engine.wait()
+        } else {
+            // Stops the pondering on another move, if any
+            self.engine
+                .search(&format!("fen {}", fen), &format!("depth {}", self.depth))
+        };
+        let output = output.context("executing UCI")?;
+        let fen: Fen = fen.parse().expect("shakmaty returned an invalid FEN");
+        interpret_variant_uci(self.variant, fen, output)
+    }
+
+    /// Ponders on the reply expected by `analysis`, the result of
+    /// `engine_move`, once its best move has been played in `game`
+    pub fn ponder(&mut self, game: &Game, analysis: &AnalysisResult) -> Result<()> {
+        let reply = match &analysis.ponder {
+            Some(reply) if game.position().is_legal(reply) => reply,
+            _ => return Ok(()),
+        };
+        let uci = self.variant.uci(reply);
+        // As the protocol requires, the position is the current one with
+        // the expected reply
+        self.engine.go_ponder(
+            &format!("fen {} moves {}", game.fen(), uci),
+            &format!("depth {}", self.depth),
+        )?;
+        let mut expected = game.position().clone();
+        expected.play_unchecked(reply);
+        self.pondering = Some(fen::epd(&expected));
+        Ok(())
+    }
+}
+
+/// A game played by two engines
+#[derive(Serialize)]
+pub struct GameRecord {
+    pub white: String,
+    pub black: String,
+    pub start: String,
+    pub moves: String,
+    pub result: GameResult,
+    pub termination: String,
+}
+
+/// An engine taking part in a game
+#[derive(Clone, Copy)]
+pub struct Player<'a> {
+    pub name: &'a str,
+    pub backend: &'a dyn UciBackend,
+    pub depth: u32,
+    /// The engine thinking on the opponent's time, playing instead of
+    /// `backend` if any
+    #[cfg(feature = "native")]
+    pub pondering: Option<&'a RefCell<PonderingEngine>>,
+}
+
+impl Player<'_> {
+    fn engine_move(&self, variant: Variant, game: &Game) -> Result<AnalysisResult> {
+        #[cfg(feature = "native")]
+        {
+            if let Some(engine) = self.pondering {
+                return engine.borrow_mut().engine_move(game);
+            }
+        }
+        engine_move(self.backend, variant, game, self.depth)
+    }
+
+    /// Ponders after the move of `analysis` if the player can
+    #[cfg_attr(not(feature = "native"), allow(unused_variables))]
+    fn ponder(&self, game: &Game, analysis: &AnalysisResult) -> Result<()> {
+        #[cfg(feature = "native")]
+        {
+            if let Some(engine) = self.pondering {
+                engine.borrow_mut().ponder(game, analysis)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays a game between two engines, adjudicating it as a draw after
+/// `max_plies` plies
+pub fn play_game(
+    white: Player,
+    black: Player,
+    variant: Variant,
+    start: Fen,
+    max_plies: u32,
+) -> Result<GameRecord> {
+    if variant != Variant::Standard && variant != Variant::Chess960 {
+        bail!("only chess and chess960 games can be played");
+    }
+    let start_fen = start.to_string();
+    let mut game = Game::new(start)?;
+    let (result, termination) = loop {
+        if let Some((result, termination)) = game.result() {
+            break (result, termination.to_owned());
+        }
+        if game.moves().len() >= max_plies as usize {
+            break (GameResult::Draw, "adjudication".to_owned());
+        }
+        let player = match game.turn() {
+            Color::White => white,
+            Color::Black => black,
+        };
+        let analysis = player
+            .engine_move(variant, &game)
+            .with_context(|| format!("getting the move of {}", player.name))?;
+        game.play(&analysis.best_move)?;
+        player
+            .ponder(&game, &analysis)
+            .with_context(|| format!("pondering for {}", player.name))?;
+    };
+    Ok(GameRecord {
+        white: white.name.to_owned(),
+        black: black.name.to_owned(),
+        start: start_fen,
+        moves: game.movetext(),
+        result,
+        termination,
+    })
+}
+
+/// The outcome of a match between two engines
+#[derive(Serialize)]
+pub struct MatchReport {
+    pub first: String,
+    pub second: String,
+    /// The points scored by the first engine
+    pub score: f64,
+    pub games: Vec<GameRecord>,
+}
+
+impl MatchReport {
+    pub fn describe(&self) -> String {
+        let mut text = String::new();
+        for (i, game) in self.games.iter().enumerate() {
+            text.push_str(&format!(
+                "Game {}: {} - {} {} ({})\n{}\n",
+                i + 1,
+                game.white,
+                game.black,
+                game.result,
+                game.termination,
+                game.moves
+            ));
+        }
+        let total = self.games.len() as f64;
+        text.push_str(&format!(
+            "Score: {} {} - {} {}",
+            self.first,
+            self.score,
+            total - self.score,
+            self.second
+        ));
+        text
+    }
+}
+
+/// Plays every opening twice, with the engines swapping colors
+pub fn play_match(
+    first: Player,
+    second: Player,
+    variant: Variant,
+    openings: &[Fen],
+    max_plies: u32,
+) -> Result<MatchReport> {
+    let mut games = Vec::new();
+    let mut score = 0.0;
+    for opening in openings {
+        for &first_white in [true, false].iter() {
+            let (white, black) = if first_white {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            let record = play_game(white, black, variant, opening.clone(), max_plies)?;
+            score += match (record.result, first_white) {
+                (GameResult::Draw, _) => 0.5,
+                (GameResult::WhiteWins, true) | (GameResult::BlackWins, false) => 1.0,
+                _ => 0.0,
+            };
+            games.push(record);
+        }
+    }
+    Ok(MatchReport {
+        first: first.name.to_owned(),
+        second: second.name.to_owned(),
+        score,
+        games,
+    })
+}
@@ -0,0 +1,423 @@
+//! A record of the analyses, kept in an SQLite database
+//!
+//! Every analysis run by a backend wrapped in `RecordedUci` is stored with
+//! its position, engine, options, search limits, the parsed `info` lines,
+//! the result, how long it took and the most it may have cost on Golem. The history is
+//! meant for auditing the results over time, so it's never pruned.
+
+use crate::analysis::{Score, SearchInfo};
+use crate::backends::{ProgressObserver, UciBackend, UciInput, UciOption, UciOutput};
+use crate::cache::{normalize_position, now};
+use anyhow::{Context, Result};
+use log::warn;
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS analyses (
+        id INTEGER PRIMARY KEY,
+        created INTEGER NOT NULL,
+        backend TEXT NOT NULL,
+        engine TEXT NOT NULL,
+        position TEXT NOT NULL,
+        options TEXT NOT NULL,
+        limits TEXT NOT NULL,
+        info TEXT NOT NULL,
+        best_move TEXT,
+        ponder TEXT,
+        duration_ms INTEGER NOT NULL,
+        max_cost REAL,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS analyses_position ON analyses (position);
+";
+
+/// An analysis, as recorded in the history
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnalysisRecord {
+    /// Assigned by the database, 0 until the record is stored
+    pub id: i64,
+    /// Seconds since the Unix epoch
+    pub created: u64,
+    /// `native` or `gwasm`
+    pub backend: String,
+    /// The `engine_id` of the backend
+    pub engine: String,
+    /// The argument of the `position` command, without the move counters
+    pub position: String,
+    /// The `setoption` commands
+    pub options: Vec<String>,
+    /// The arguments of the `go` command, e.g. `depth 20`
+    pub limits: String,
+    pub info: Vec<SearchInfo>,
+    pub best_move: Option<String>,
+    pub ponder: Option<String>,
+    pub duration_ms: u64,
+    /// The most the Golem task may have cost, in GNT
+    pub max_cost: Option<f64>,
+    /// Why the analysis failed, if it did
+    pub error: Option<String>,
+}
+
+impl AnalysisRecord {
+    /// Records the session `uci` run by the engine `engine`, which produced
+    /// `output`
+    pub fn new(engine: String, uci: &[String], output: &Result<UciOutput>) -> Self {
+        let mut position = String::new();
+        let mut limits = String::new();
+        let mut options = Vec::new();
+        for line in uci {
+            if line.starts_with("setoption ") {
+                options.push(line.clone());
+            } else if let Some(pos) = line.strip_prefix("position ") {
+                position = normalize_position(pos);
+            } else if let Some(go) = line.strip_prefix("go ") {
+                limits = go.trim().to_owned();
+            }
+        }
+
+        let (lines, error): (&[String], _) = match output {
+            Ok(lines) => (lines, None),
+            Err(e) => (&[], Some(format!("{:#}", e))),
+        };
+        let info = lines.iter().filter_map(|l| SearchInfo::parse(l)).collect();
+        let mut bestmove = lines
+            .iter()
+            .rev()
+            .find_map(|line| line.strip_prefix("bestmove "))
+            .into_iter()
+            .flat_map(str::split_whitespace);
+        let best_move = bestmove.next().map(str::to_owned);
+        let ponder = match (bestmove.next(), bestmove.next()) {
+            (Some("ponder"), Some(ponder)) => Some(ponder.to_owned()),
+            _ => None,
+        };
+
+        Self {
+            id: 0,
+            created: now(),
+            backend: engine.split(':').next().unwrap_or("").to_owned(),
+            engine,
+            position,
+            options,
+            limits,
+            info,
+            best_move,
+            ponder,
+            duration_ms: 0,
+            max_cost: None,
+            error,
+        }
+    }
+
+    /// Records the time the analysis took.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration_ms = duration.as_millis() as u64;
+        self
+    }
+
+    /// Records the most the Golem task may have cost.
+    pub fn max_cost(mut self, max_cost: Option<f64>) -> Self {
+        self.max_cost = max_cost;
+        self
+    }
+
+    /// The last score and depth reported by the engine
+    fn final_info(&self) -> (Option<u32>, Option<Score>) {
+        let depth = self.info.iter().rev().find_map(|info| info.depth);
+        let score = self.info.iter().rev().find_map(|info| info.score);
+        (depth, score)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "#{} at {}: {}",
+                self.id,
+                format_time(self.created),
+                self.engine
+            ),
+            format!("  position {}", self.position),
+        ];
+        if !self.options.is_empty() {
+            lines.push(format!("  {}", self.options.join(", ")));
+        }
+        let mut search = format!(
+            "  go {}, {:.3} s",
+            self.limits,
+            self.duration_ms as f64 / 1000.0
+        );
+        if let Some(max_cost) = self.max_cost {
+            search.push_str(&format!(", at most {:.6} GNT", max_cost));
+        }
+        lines.push(search);
+        let (depth, score) = self.final_info();
+        let result = match (&self.error, &self.best_move) {
+            (Some(error), _) => format!("  failed: {}", error),
+            (None, Some(best_move)) => {
+                let mut result = format!("  best move {}", best_move);
+                if let Some(depth) = depth {
+                    result.push_str(&format!(", depth {}", depth));
+                }
+                match score {
+                    Some(Score::Cp(cp)) => result.push_str(&format!(", score cp {}", cp)),
+                    Some(Score::Mate(moves)) => result.push_str(&format!(", mate in {}", moves)),
+                    None => {}
+                }
+                result
+            }
+            (None, None) => "  no best move".to_owned(),
+        };
+        lines.push(result);
+        lines.join("\n")
+    }
+
+    fn from_row(row: &Row) -> Result<Self> {
+        let options: String = row.get("options")?;
+        let info: String = row.get("info")?;
+        Ok(Self {
+            id: row.get("id")?,
+            created: row.get::<_, i64>("created")? as u64,
+            backend: row.get("backend")?,
+            engine: row.get("engine")?,
+            position: row.get("position")?,
+            options: serde_json::from_str(&options).context("parsing the options")?,
+            limits: row.get("limits")?,
+            info: serde_json::from_str(&info).context("parsing the info lines")?,
+            best_move: row.get("best_move")?,
+            ponder: row.get("ponder")?,
+            duration_ms: row.get::<_, i64>("duration_ms")? as u64,
+            max_cost: row.get("max_cost")?,
+            error: row.get("error")?,
+        })
+    }
+}
+
+/// Selects the records of the history, all of them by default
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    fen: Option<String>,
+    engine: Option<String>,
+    limit: Option<u32>,
+}
+
+impl HistoryQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only the analyses of this position, regardless of the move counters
+    pub fn fen(mut self, fen: &str) -> Self {
+        self.fen = Some(normalize_position(&format!("fen {}", fen)));
+        self
+    }
+
+    /// Only the analyses by the engines whose id contains `engine`, e.g.
+    /// the path to the engine
+    pub fn engine(mut self, engine: &str) -> Self {
+        self.engine = Some(engine.to_owned());
+        self
+    }
+
+    /// Only the `limit` most recent analyses
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// The history database, shared by the clones
+#[derive(Clone)]
+pub struct AnalysisHistory {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl AnalysisHistory {
+    /// Opens the database at `path`, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("creating the history directory")?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("opening the history {}", path.display()))?;
+        // Concurrent golemate processes take turns writing
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(SCHEMA)
+            .context("creating the history tables")?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// `$XDG_DATA_HOME/golemate/history.sqlite`, or
+    /// `~/.local/share/golemate/history.sqlite`
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })?;
+        Some(data_home.join("golemate").join("history.sqlite"))
+    }
+
+    /// Stores the record, returning its id
+    pub fn insert(&self, record: &AnalysisRecord) -> Result<i64> {
+        let conn = self.conn.lock().expect("the history is poisoned");
+        conn.execute(
+            "INSERT INTO analyses (created, backend, engine, position, options, limits, info,
+                best_move, ponder, duration_ms, max_cost, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                record.created as i64,
+                record.backend,
+                record.engine,
+                record.position,
+                serde_json::to_string(&record.options)?,
+                record.limits,
+                serde_json::to_string(&record.info)?,
+                record.best_move,
+                record.ponder,
+                record.duration_ms as i64,
+                record.max_cost,
+                record.error,
+            ],
+        )
+        .context("recording the analysis")?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Returns the matching records, the most recent first
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<AnalysisRecord>> {
+        let conn = self.conn.lock().expect("the history is poisoned");
+        let mut stmt = conn
+            .prepare(
+                "SELECT * FROM analyses
+                 WHERE (?1 IS NULL OR position = ?1) AND (?2 IS NULL OR instr(engine, ?2) > 0)
+                 ORDER BY id DESC
+                 LIMIT ?3",
+            )
+            .context("reading the history")?;
+        let limit = query.limit.map_or(-1, i64::from);
+        let rows = stmt
+            .query_map(params![query.fen, query.engine, limit], |row| {
+                Ok(AnalysisRecord::from_row(row))
+            })
+            .context("reading the history")?;
+        let mut records = Vec::new();
+        for row in rows {
+            records.push(row.context("reading the history")??);
+        }
+        Ok(records)
+    }
+}
+
+/// Records the analyses run by `backend` in the history.
+///
+/// A failure to record is only logged, it doesn't fail the analysis.
+/// The sessions of a batch are recorded with the duration of the whole
+/// batch.
+pub struct RecordedUci<B> {
+    backend: B,
+    history: AnalysisHistory,
+}
+
+impl<B: UciBackend> RecordedUci<B> {
+    pub fn new(backend: B, history: AnalysisHistory) -> Self {
+        Self { backend, history }
+    }
+
+    fn record(&self, uci: &[String], output: &Result<UciOutput>, duration: Duration) {
+        // Sessions which only query the engine aren't analyses
+        if !uci
+            .iter()
+            .any(|cmd| cmd.split_whitespace().next() == Some("go"))
+        {
+            return;
+        }
+        let record = AnalysisRecord::new(self.engine_id(), uci, output)
+            .duration(duration)
+            .max_cost(self.backend.max_cost());
+        if let Err(e) = self.history.insert(&record) {
+            warn!("Failed to record the analysis in the history: {:?}", e);
+        }
+    }
+}
+
+impl<B: UciBackend> UciBackend for RecordedUci<B> {
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        self.backend.get_uci_opts()
+    }
+
+    fn set_progress_observer(&mut self, observer: Arc<dyn ProgressObserver>) {
+        self.backend.set_progress_observer(observer);
+    }
+
+    fn engine_id(&self) -> String {
+        self.backend.engine_id()
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        self.backend.max_cost()
+    }
+
+    fn execute_uci(&self, uci: UciInput) -> Result<UciOutput> {
+        let start = Instant::now();
+        let output = self.backend.execute_uci(uci.clone());
+        self.record(&uci, &output, start.elapsed());
+        output
+    }
+
+    fn execute_batch(&self, inputs: Vec<UciInput>) -> Vec<Result<UciOutput>> {
+        let start = Instant::now();
+        let outputs = self.backend.execute_batch(inputs.clone());
+        let duration = start.elapsed();
+        for (uci, output) in inputs.iter().zip(&outputs) {
+            self.record(uci, output, duration);
+        }
+        outputs
+    }
+}
+
+#[cfg(feature = "async")]
+#[async_trait::async_trait]
+impl<B: crate::backends::AsyncUciBackend> crate::backends::AsyncUciBackend for RecordedUci<B> {
+    async fn execute_async(&self, uci: UciInput) -> Result<UciOutput> {
+        let start = Instant::now();
+        let output = self.backend.execute_async(uci.clone()).await;
+        self.record(&uci, &output, start.elapsed());
+        output
+    }
+}
+
+/// Formats a Unix time as a UTC date, e.g. `2020-06-01 12:00:00 UTC`
+fn format_time(secs: u64) -> String {
+    // The civil_from_days algorithm of Howard Hinnant
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
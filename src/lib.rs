@@ -1,2 +1,21 @@
 pub mod analysis;
 pub mod backends;
+pub mod bench;
+pub mod book;
+pub mod cache;
+pub mod config;
+pub mod engine_info;
+pub mod epd;
+pub mod game;
+#[cfg(feature = "history")]
+pub mod history;
+pub mod pgn;
+pub mod proxy;
+pub mod puzzle;
+pub mod review;
+#[cfg(feature = "serve")]
+pub mod server;
+#[cfg(feature = "syzygy")]
+pub mod tablebase;
+pub mod tree;
+pub mod variant;
@@ -0,0 +1,184 @@
+//! Games in the Portable Game Notation
+//!
+//! Only the main line of a game is read: the comments, the variations and
+//! the numeric annotation glyphs are skipped.
+
+use crate::epd::parse_san;
+use crate::game::Game;
+use anyhow::{bail, Context, Result};
+use shakmaty::fen::Fen;
+use shakmaty::Chess;
+use std::iter::Peekable;
+use std::str::Chars;
+
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// A game as written in the PGN, with the moves not checked yet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    /// The moves of the main line in SAN, without the annotations
+    pub moves: Vec<String>,
+    /// `1-0`, `0-1`, `1/2-1/2` or `*`, if given
+    pub result: Option<String>,
+}
+
+impl PgnGame {
+    /// The value of the tag `name`
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The start position, given by the `FEN` tag or the initial one
+    pub fn start(&self) -> Result<Fen> {
+        let fen = self.tag("FEN").unwrap_or(INITIAL_FEN);
+        fen.parse()
+            .map_err(|_| anyhow::anyhow!("invalid FEN tag `{}`", fen))
+    }
+
+    /// Plays the moves, checking that they're legal
+    pub fn replay(&self) -> Result<Game> {
+        self.play_moves(|_| {})
+    }
+
+    /// The positions of the main line, from the start position to the
+    /// final one
+    pub fn positions(&self) -> Result<Vec<Chess>> {
+        Ok(self.replay_positions()?.1)
+    }
+
+    /// Like `replay`, with the positions of the main line as `positions`
+    /// returns them
+    pub fn replay_positions(&self) -> Result<(Game, Vec<Chess>)> {
+        let mut positions = Vec::new();
+        let game = self.play_moves(|game| positions.push(game.position().clone()))?;
+        Ok((game, positions))
+    }
+
+    /// Plays the moves, calling `visit` with every position of the game
+    fn play_moves(&self, mut visit: impl FnMut(&Game)) -> Result<Game> {
+        if let Some(variant) = self.tag("Variant") {
+            if !["standard", "chess", "chess960"].contains(&variant.to_lowercase().as_str()) {
+                bail!("unsupported variant `{}`", variant);
+            }
+        }
+        let mut game = Game::new(self.start()?)?;
+        visit(&game);
+        for san in &self.moves {
+            let m = parse_san(game.position(), san)
+                .with_context(|| format!("replaying the game at {}", game.movetext()))?;
+            game.play(&m)?;
+            visit(&game);
+        }
+        Ok(game)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.moves.is_empty()
+    }
+}
+
+/// Reads all the games of a PGN file
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::default();
+    // The nesting level of the variations being skipped
+    let mut variations = 0u32;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                chars
+                    .by_ref()
+                    .find(|&c| c == '}')
+                    .context("unterminated comment")?;
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => variations += 1,
+            ')' => {
+                variations = variations
+                    .checked_sub(1)
+                    .context("unbalanced parentheses")?;
+            }
+            '[' if variations == 0 => {
+                // Tags after the moves start the next game, even without a result
+                if !game.moves.is_empty() {
+                    games.push(std::mem::take(&mut game));
+                }
+                game.tags.push(read_tag(&mut chars)?);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}()[];".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                if variations > 0 || token.starts_with('$') {
+                    continue;
+                }
+                match token.as_str() {
+                    "1-0" | "0-1" | "1/2-1/2" | "*" => {
+                        game.result = Some(token);
+                        games.push(std::mem::take(&mut game));
+                    }
+                    _ => {
+                        if let Some(san) = strip_move_number(&token) {
+                            game.moves.push(san.to_owned());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    if variations > 0 {
+        bail!("unbalanced parentheses");
+    }
+    if !game.is_empty() {
+        games.push(game);
+    }
+    Ok(games)
+}
+
+/// Reads `Name "Value"]`, following the opening bracket
+fn read_tag(chars: &mut Peekable<Chars>) -> Result<(String, String)> {
+    let mut name = String::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    loop {
+        let c = chars.next().context("unterminated tag")?;
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => value.push(chars.next().context("unterminated tag")?),
+            ']' if !quoted => break,
+            c if quoted => value.push(c),
+            c if !c.is_whitespace() => name.push(c),
+            _ => {}
+        }
+    }
+    if name.is_empty() {
+        bail!("tag without a name");
+    }
+    Ok((name, value))
+}
+
+/// Drops the move number, e.g. `12.` or `12...`, returning what's left of
+/// the token, if anything
+fn strip_move_number(token: &str) -> Option<&str> {
+    let san = token.trim_start_matches(|c: char| c.is_ascii_digit());
+    let san = if san.starts_with('.') {
+        san.trim_start_matches('.')
+    } else {
+        // Not a move number, e.g. a move such as `O-O`
+        token
+    };
+    Some(san).filter(|san| !san.is_empty())
+}
@@ -0,0 +1,118 @@
+//! A UCI engine forwarding the searches to a backend
+//!
+//! This lets chess GUIs use a Golem-backed engine. Every `go` is computed
+//! synchronously as a separate session of the backend, so `stop` has no
+//! effect and infinite or ponder searches are limited to the default depth.
+//!
+//! A search the backend fails to answer ends the session: the error is
+//! reported with `info string`, and a made-up best move is never sent.
+
+use crate::backends::{UciBackend, UciOption};
+use anyhow::{bail, Context, Result};
+use std::io::{BufRead, Write};
+
+pub struct UciProxy<'a> {
+    backend: &'a dyn UciBackend,
+    default_depth: u32,
+    options: Vec<String>,
+    position: String,
+}
+
+impl<'a> UciProxy<'a> {
+    pub fn new(backend: &'a dyn UciBackend, default_depth: u32) -> Self {
+        Self {
+            backend,
+            default_depth,
+            options: Vec::new(),
+            position: "startpos".to_owned(),
+        }
+    }
+
+    /// Talks UCI until `quit` or the end of the input
+    pub fn serve<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let line = line.trim();
+            let (cmd, args) = match line.find(' ') {
+                Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+                None => (line, ""),
+            };
+            match cmd {
+                "uci" => {
+                    writeln!(output, "id name Golemate ({})", self.backend.engine_id())?;
+                    writeln!(output, "id author Golemate")?;
+                    writeln!(output, "uciok")?;
+                }
+                "isready" => writeln!(output, "readyok")?,
+                "setoption" => self.set_option(line),
+                "position" => self.position = args.to_owned(),
+                "go" => match self.search(args) {
+                    Ok(lines) => {
+                        for line in lines {
+                            writeln!(output, "{}", line)?;
+                        }
+                    }
+                    Err(e) => {
+                        writeln!(output, "info string {:#}", e)?;
+                        output.flush()?;
+                        return Err(e).context("the search failed");
+                    }
+                },
+                "quit" => break,
+                // ucinewgame, stop, ponderhit and debug need no action
+                _ => {}
+            }
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn set_option(&mut self, line: &str) {
+        let name = |option: &str| {
+            let option = option.splitn(2, " name ").nth(1).unwrap_or("");
+            option
+                .split(" value ")
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_lowercase()
+        };
+        let new_name = name(line);
+        self.options.retain(|option| name(option) != new_name);
+        self.options.push(line.to_owned());
+    }
+
+    fn search(&self, limits: &str) -> Result<Vec<String>> {
+        let unbounded = limits
+            .split_whitespace()
+            .any(|word| word == "infinite" || word == "ponder");
+        let limits = if unbounded || limits.is_empty() {
+            format!("depth {}", self.default_depth)
+        } else {
+            limits.to_owned()
+        };
+
+        let mut cmds = vec!["uci".to_owned()];
+        cmds.extend(
+            self.backend
+                .get_uci_opts()
+                .iter()
+                .map(UciOption::uci_set_msg),
+        );
+        cmds.extend(self.options.iter().cloned());
+        cmds.push(format!("position {}", self.position));
+        cmds.push(format!("go {}", limits));
+        cmds.push("quit".to_owned());
+
+        let lines: Vec<_> = self
+            .backend
+            .execute_uci(cmds)?
+            .into_iter()
+            .filter(|line| line.starts_with("info") || line.starts_with("bestmove"))
+            .collect();
+        if !lines.iter().any(|line| line.starts_with("bestmove")) {
+            bail!("the backend didn't return the best move");
+        }
+        Ok(lines)
+    }
+}
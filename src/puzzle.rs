@@ -0,0 +1,325 @@
+//! Tactical puzzles mined from played games
+//!
+//! Every position of the games is analysed with two lines. A position
+//! makes a puzzle when the best move keeps an advantage, is at least
+//! `min_gap` centipawns better than the second best one, and starts a
+//! forcing line: every move of the solver is a check, a capture or a
+//! promotion. The later positions of the solution where the solver moves
+//! are analysed again, and the solution stops before the first move that
+//! isn't the only good one.
+//!
+//! Only chess and Chess960 games can be replayed, the engine is told which
+//! one is played.
+//!
+//! Unlike the other analyses, the engine outputs are read as the final
+//! `SearchInfo` of every line rather than as an `AnalysisResult`, which only
+//! holds the best line: a puzzle is found by comparing the two best lines.
+
+use crate::analysis::{final_lines, Score, SearchInfo};
+use crate::backends::{UciBackend, UciInput, UciOption};
+use crate::pgn::PgnGame;
+use crate::review::EVAL_CAP;
+use crate::variant::Variant;
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use shakmaty::fen;
+use shakmaty::san::SanPlus;
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Move, Position};
+
+/// The default minimal gap between the best and the second best moves, in
+/// centipawns
+pub const MIN_GAP: i32 = 300;
+/// The default maximal number of moves of the solver
+pub const MAX_MOVES: usize = 3;
+/// The value of a mate in 0 moves, a mate in `n` is worth `MATE_VALUE - n`
+const MATE_VALUE: i32 = 10_000;
+/// The number of lines searched in every position
+const LINES: u32 = 2;
+
+/// A position with a single winning move, and how to follow it up
+#[derive(Debug, Clone, Serialize)]
+pub struct Puzzle {
+    pub fen: String,
+    /// The solution in UCI notation, with the replies of the opponent, from
+    /// the first move of the solver to the last one
+    pub moves: Vec<String>,
+    /// The solution in SAN
+    pub san: Vec<String>,
+    /// The number of the game in the PGN, starting from 1
+    pub game: usize,
+    /// The number of moves played in the game before the puzzle position
+    pub ply: usize,
+    /// The evaluation of the best move, for the solver
+    pub score: Score,
+    /// How much better the best move is than the second best one, in
+    /// centipawns, a mate counting as `10000` minus its length
+    pub gap: i32,
+}
+
+/// A puzzle whose solution hasn't been verified yet
+struct Candidate {
+    game: usize,
+    ply: usize,
+    position: Chess,
+    score: Score,
+    gap: i32,
+    moves: Vec<Move>,
+}
+
+impl Candidate {
+    fn into_puzzle(self, variant: Variant) -> Puzzle {
+        let mut pos = self.position.clone();
+        let san = self
+            .moves
+            .iter()
+            .map(|m| SanPlus::from_move_and_play_unchecked(&mut pos, m).to_string())
+            .collect();
+        Puzzle {
+            fen: fen::fen(&self.position),
+            moves: self
+                .moves
+                .iter()
+                .map(|m| variant.uci(m).to_string())
+                .collect(),
+            san,
+            game: self.game,
+            ply: self.ply,
+            score: self.score,
+            gap: self.gap,
+        }
+    }
+}
+
+/// Finds the puzzles in the games, see the module documentation
+#[derive(Debug, Clone)]
+pub struct PuzzleMiner {
+    depth: u32,
+    variant: Variant,
+    min_gap: i32,
+    max_moves: usize,
+}
+
+impl PuzzleMiner {
+    pub fn new(depth: u32) -> Self {
+        Self {
+            depth,
+            variant: Variant::Standard,
+            min_gap: MIN_GAP,
+            max_moves: MAX_MOVES,
+        }
+    }
+
+    /// The variant of the games, chess or Chess960
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// How much better than the second best move the best one must be, in
+    /// centipawns
+    pub fn min_gap(mut self, min_gap: i32) -> Self {
+        self.min_gap = min_gap;
+        self
+    }
+
+    /// The maximal number of moves of the solver, at least one
+    pub fn max_moves(mut self, max_moves: usize) -> Self {
+        self.max_moves = max_moves.max(1);
+        self
+    }
+
+    /// Finds the puzzles in the main lines of the games, in the order of
+    /// the games.
+    ///
+    /// The positions are analysed as a batch, in parallel if the backend
+    /// supports it. A puzzle starting within the solution of the previous
+    /// one is dropped, as it repeats it.
+    pub fn mine(&self, backend: &dyn UciBackend, games: &[PgnGame]) -> Result<Vec<Puzzle>> {
+        if !matches!(self.variant, Variant::Standard | Variant::Chess960) {
+            bail!("finding puzzles in {} games isn't supported", self.variant);
+        }
+        let mut positions = Vec::new();
+        for (i, game) in games.iter().enumerate() {
+            let game_positions = game
+                .positions()
+                .with_context(|| format!("replaying game {}", i + 1))?;
+            positions.extend(
+                game_positions
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(_, pos)| pos.outcome().is_none())
+                    .map(|(ply, pos)| (i + 1, ply, pos)),
+            );
+        }
+
+        let analyses = self.analyse(backend, positions.iter().map(|(_, _, pos)| pos));
+        let mut candidates = Vec::new();
+        for ((game, ply, position), lines) in positions.into_iter().zip(analyses) {
+            let lines = lines.with_context(|| format!("analysing ply {} of game {}", ply, game))?;
+            let (score, gap) = match self.best_line(&lines) {
+                Some(best) => best,
+                None => continue,
+            };
+            let moves = forcing_line(&position, &lines[0].pv, self.max_moves);
+            if value(score) > 0 && !moves.is_empty() {
+                candidates.push(Candidate {
+                    game,
+                    ply,
+                    position,
+                    score,
+                    gap,
+                    moves,
+                });
+            }
+        }
+
+        // The positions where the solver moves again, in the order of the
+        // solutions, so that the first move that isn't unique cuts them
+        let mut checks = Vec::new();
+        for (idx, candidate) in candidates.iter().enumerate() {
+            let mut pos = candidate.position.clone();
+            for (i, m) in candidate.moves.iter().enumerate() {
+                pos.play_unchecked(m);
+                if i % 2 == 1 {
+                    checks.push((idx, i + 1, pos.clone()));
+                }
+            }
+        }
+        let analyses = self.analyse(backend, checks.iter().map(|(_, _, pos)| pos));
+        for ((idx, solver_move, pos), lines) in checks.into_iter().zip(analyses) {
+            let candidate = &mut candidates[idx];
+            let lines = lines.with_context(|| {
+                format!(
+                    "verifying the puzzle at ply {} of game {}",
+                    candidate.ply, candidate.game
+                )
+            })?;
+            if solver_move >= candidate.moves.len() {
+                continue;
+            }
+            let best = lines
+                .first()
+                .and_then(|line| line.pv.first())
+                .and_then(|uci| uci.parse::<Uci>().ok())
+                .and_then(|uci| uci.to_move(&pos).ok());
+            let forced = pos.legal_moves().len() == 1;
+            let unique = best.as_ref() == Some(&candidate.moves[solver_move])
+                && (forced || self.best_line(&lines).is_some());
+            if !unique {
+                // Without the reply leading to this position
+                candidate.moves.truncate(solver_move - 1);
+            }
+        }
+
+        let mut puzzles: Vec<Puzzle> = Vec::new();
+        for candidate in candidates {
+            if let Some(last) = puzzles.last() {
+                if last.game == candidate.game && candidate.ply < last.ply + last.moves.len() {
+                    continue;
+                }
+            }
+            puzzles.push(candidate.into_puzzle(self.variant));
+        }
+        Ok(puzzles)
+    }
+
+    fn analyse<'a>(
+        &self,
+        backend: &dyn UciBackend,
+        positions: impl Iterator<Item = &'a Chess>,
+    ) -> Vec<Result<Vec<SearchInfo>>> {
+        let cmds = positions
+            .map(|pos| multipv_uci(backend, self.variant, &fen::fen(pos), self.depth))
+            .collect();
+        backend
+            .execute_batch(cmds)
+            .into_iter()
+            .map(|output| Ok(final_lines(&output.context("executing UCI")?)))
+            .collect()
+    }
+
+    /// The score of the best line and its gap over the second best one, if
+    /// it's large enough
+    fn best_line(&self, lines: &[SearchInfo]) -> Option<(Score, i32)> {
+        let best = lines.first()?.score?;
+        let second = lines.get(1)?.score?;
+        let gap = value(best) - value(second);
+        Some((best, gap)).filter(|_| gap >= self.min_gap)
+    }
+}
+
+/// The UCI session analysing `fen` with `LINES` lines
+fn multipv_uci(backend: &dyn UciBackend, variant: Variant, fen: &str, depth: u32) -> UciInput {
+    let mut cmds = backend.generate_variant_uci(variant, fen, depth);
+    let position = cmds
+        .iter()
+        .position(|cmd| cmd.starts_with("position "))
+        .unwrap_or(cmds.len());
+    cmds.insert(position, UciOption::new("MultiPV", LINES).uci_set_msg());
+    cmds
+}
+
+/// The score in centipawns, capped, with the mates above every capped score
+fn value(score: Score) -> i32 {
+    match score {
+        Score::Cp(cp) => cp.max(-EVAL_CAP).min(EVAL_CAP),
+        Score::Mate(moves) if moves > 0 => MATE_VALUE - moves,
+        Score::Mate(moves) => -MATE_VALUE - moves,
+    }
+}
+
+/// The start of `pv` where every move of the solver is forcing, ending with
+/// one of them, with at most `max_moves` of them
+fn forcing_line(position: &Chess, pv: &[String], max_moves: usize) -> Vec<Move> {
+    let mut pos = position.clone();
+    let mut line = Vec::new();
+    let mut solution_len = 0;
+    for (i, uci) in pv.iter().take(max_moves * 2 - 1).enumerate() {
+        let m = match uci
+            .parse::<Uci>()
+            .ok()
+            .and_then(|uci| uci.to_move(&pos).ok())
+        {
+            Some(m) => m,
+            None => break,
+        };
+        let capture = m.is_capture() || m.is_promotion();
+        pos.play_unchecked(&m);
+        if i % 2 == 0 {
+            if !capture && !pos.is_check() {
+                break;
+            }
+            solution_len = i + 1;
+        }
+        line.push(m);
+    }
+    line.truncate(solution_len);
+    line
+}
+
+/// The score as in the UCI protocol, e.g. `cp 250` or `mate 3`
+fn describe_score(score: Score) -> String {
+    match score {
+        Score::Cp(cp) => format!("cp {}", cp),
+        Score::Mate(moves) => format!("mate {}", moves),
+    }
+}
+
+/// The puzzles as CSV, with a header line and the solution moves in UCI
+/// notation, separated by spaces
+pub fn to_csv(puzzles: &[Puzzle]) -> String {
+    let mut csv = "fen,moves,game,ply,score,gap\n".to_owned();
+    for puzzle in puzzles {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            puzzle.fen,
+            puzzle.moves.join(" "),
+            puzzle.game,
+            puzzle.ply,
+            describe_score(puzzle.score),
+            puzzle.gap
+        ));
+    }
+    csv
+}
@@ -0,0 +1,358 @@
+//! Reviews of played games: the accuracy of the players and their errors
+//!
+//! Every position of the game is analysed by the engine. The evaluations
+//! are converted to winning chances, as lichess does, so that a drop from
+//! +8 to +5 counts less than one from +1 to -2. A move is an inaccuracy,
+//! a mistake or a blunder if the winning chances of the player drop by at
+//! least `INACCURACY`, `MISTAKE` or `BLUNDER` percentage points.
+//!
+//! A position the engine fails to analyse doesn't end the review: the
+//! failure is reported, and only the moves from and to it aren't judged.
+
+use crate::analysis::{
+    interpret_variant_uci, serialize_color, win_chance, Advantage, AnalysisResult,
+};
+use crate::backends::UciBackend;
+use crate::pgn::PgnGame;
+use crate::variant::Variant;
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use shakmaty::fen::{self, Fen};
+use shakmaty::san::San;
+use shakmaty::{Color, Outcome, Position};
+
+/// Evaluations are capped to this many centipawns, a mate counts as much
+pub const EVAL_CAP: i32 = 1000;
+pub const INACCURACY: f64 = 5.0;
+pub const MISTAKE: f64 = 10.0;
+pub const BLUNDER: f64 = 15.0;
+/// The number of critical moments reported
+pub const CRITICAL_MOMENTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    fn from_drop(drop: f64) -> Option<Self> {
+        if drop >= BLUNDER {
+            Some(Judgement::Blunder)
+        } else if drop >= MISTAKE {
+            Some(Judgement::Mistake)
+        } else if drop >= INACCURACY {
+            Some(Judgement::Inaccuracy)
+        } else {
+            None
+        }
+    }
+
+    /// The annotation symbol
+    fn symbol(self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+}
+
+/// A move of the game, judged by the engine
+#[derive(Debug, Clone, Serialize)]
+pub struct MoveReview {
+    /// The number of the move, as in the PGN
+    pub move_number: u32,
+    #[serde(serialize_with = "serialize_color")]
+    pub color: Color,
+    pub san: String,
+    /// The move preferred by the engine, in SAN
+    pub best_move: String,
+    /// The evaluations before and after the move, in centipawns for White
+    pub eval_before: i32,
+    pub eval_after: i32,
+    pub centipawn_loss: u32,
+    /// Between 0 and 100
+    pub accuracy: f64,
+    pub judgement: Option<Judgement>,
+}
+
+impl MoveReview {
+    /// The move in the PGN notation, e.g. `12... Nf6?`
+    pub fn notation(&self) -> String {
+        let dots = match self.color {
+            Color::White => ".",
+            Color::Black => "...",
+        };
+        let symbol = self.judgement.map_or("", Judgement::symbol);
+        format!("{}{} {}{}", self.move_number, dots, self.san, symbol)
+    }
+
+    /// How much the winning chances of White changed, in percentage points
+    fn swing(&self) -> f64 {
+        (win_chance(self.eval_after) - win_chance(self.eval_before)).abs()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerReview {
+    pub name: String,
+    /// The average accuracy of the moves, between 0 and 100
+    pub accuracy: f64,
+    pub average_centipawn_loss: f64,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+impl PlayerReview {
+    fn new(name: String, moves: &[&MoveReview]) -> Self {
+        let count = |judgement| {
+            moves
+                .iter()
+                .filter(|m| m.judgement == Some(judgement))
+                .count()
+        };
+        let average = |values: Vec<f64>| {
+            if values.is_empty() {
+                0.0
+            } else {
+                values.iter().sum::<f64>() / values.len() as f64
+            }
+        };
+        Self {
+            name,
+            accuracy: average(moves.iter().map(|m| m.accuracy).collect()),
+            average_centipawn_loss: average(
+                moves.iter().map(|m| f64::from(m.centipawn_loss)).collect(),
+            ),
+            inaccuracies: count(Judgement::Inaccuracy),
+            mistakes: count(Judgement::Mistake),
+            blunders: count(Judgement::Blunder),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: accuracy {:.1}%, average centipawn loss {:.0}, {} inaccuracies, {} mistakes, {} blunders",
+            self.name,
+            self.accuracy,
+            self.average_centipawn_loss,
+            self.inaccuracies,
+            self.mistakes,
+            self.blunders
+        )
+    }
+}
+
+/// A position of the game the engine failed to analyse
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisFailure {
+    /// The number of moves played before the position
+    pub ply: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GameReview {
+    pub white: PlayerReview,
+    pub black: PlayerReview,
+    pub depth: u32,
+    /// The judged moves, without the ones from or to a position which
+    /// failed to be analysed
+    pub moves: Vec<MoveReview>,
+    /// The moves after which the evaluation swung the most, in the order
+    /// of the game
+    pub critical_moments: Vec<MoveReview>,
+    pub failures: Vec<AnalysisFailure>,
+}
+
+impl GameReview {
+    pub fn describe(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "{} - {}, analysed at depth {}",
+                self.white.name, self.black.name, self.depth
+            ),
+            self.white.describe(),
+            self.black.describe(),
+        ];
+        if !self.critical_moments.is_empty() {
+            lines.push("Critical moments:".to_owned());
+        }
+        for m in &self.critical_moments {
+            lines.push(format!(
+                "  {} ({} -> {}), best was {}",
+                m.notation(),
+                describe_eval(m.eval_before),
+                describe_eval(m.eval_after),
+                m.best_move
+            ));
+        }
+        for failure in &self.failures {
+            lines.push(format!(
+                "The position after ply {} wasn't analysed: {}",
+                failure.ply, failure.error
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// The evaluation in pawns, e.g. `+1.25`
+fn describe_eval(eval: i32) -> String {
+    format!("{:+.2}", f64::from(eval) / 100.0)
+}
+
+/// The accuracy of a move lowering the winning chances of the player by
+/// `drop` percentage points, following lichess
+pub fn move_accuracy(drop: f64) -> f64 {
+    (103.1668 * (-0.04354 * drop.max(0.0)).exp() - 3.1669)
+        .max(0.0)
+        .min(100.0)
+}
+
+/// The evaluation of an analysis in centipawns for White, capped
+fn evaluation(result: &AnalysisResult) -> i32 {
+    let magnitude = match result.advantage {
+        Advantage::Centipawns(cp) => (cp as i32).min(EVAL_CAP),
+        Advantage::Mate(_) | Advantage::TablebaseWin(_) => EVAL_CAP,
+        Advantage::Equality => 0,
+    };
+    match result.advantage_side {
+        Color::White => magnitude,
+        Color::Black => -magnitude,
+    }
+}
+
+/// The evaluation of a position where the game has ended
+fn outcome_evaluation(outcome: Outcome) -> i32 {
+    match outcome {
+        Outcome::Decisive {
+            winner: Color::White,
+        } => EVAL_CAP,
+        Outcome::Decisive {
+            winner: Color::Black,
+        } => -EVAL_CAP,
+        Outcome::Draw => 0,
+    }
+}
+
+/// Analyses every position of the game to `depth` and judges the moves.
+///
+/// The positions are analysed as a batch, in parallel if the backend
+/// supports it. Only chess and Chess960 games can be replayed, so the other
+/// variants are refused. The review fails only if no position could be
+/// analysed.
+pub fn review_game(
+    backend: &dyn UciBackend,
+    pgn: &PgnGame,
+    variant: Variant,
+    depth: u32,
+) -> Result<GameReview> {
+    if !matches!(variant, Variant::Standard | Variant::Chess960) {
+        bail!("reviewing {} games isn't supported", variant);
+    }
+    let (game, positions) = pgn.replay_positions()?;
+
+    // Only the positions where the game goes on need the engine
+    let pending: Vec<_> = positions
+        .iter()
+        .enumerate()
+        .filter(|(_, pos)| pos.outcome().is_none())
+        .map(|(idx, pos)| (idx, fen::fen(pos)))
+        .collect();
+    let cmds = pending
+        .iter()
+        .map(|(_, fen)| backend.generate_variant_uci(variant, fen, depth))
+        .collect();
+    let outputs = backend.execute_batch(cmds);
+    let analysed = pending.len();
+    let mut analyses: Vec<Option<AnalysisResult>> = positions.iter().map(|_| None).collect();
+    let mut errors = Vec::new();
+    for ((idx, fen), output) in pending.into_iter().zip(outputs) {
+        let fen: Fen = fen.parse().expect("shakmaty returned an invalid FEN");
+        let result = output
+            .context("executing UCI")
+            .and_then(|output| interpret_variant_uci(variant, fen, output))
+            .with_context(|| format!("analysing the position after ply {}", idx));
+        match result {
+            Ok(result) => analyses[idx] = Some(result),
+            Err(e) => errors.push((idx, e)),
+        }
+    }
+    if analysed > 0 && errors.len() == analysed {
+        let (_, e) = errors.swap_remove(0);
+        return Err(e);
+    }
+    let failures = errors
+        .into_iter()
+        .map(|(ply, e)| AnalysisFailure {
+            ply,
+            error: format!("{:#}", e),
+        })
+        .collect();
+    let evals: Vec<Option<i32>> = positions
+        .iter()
+        .zip(&analyses)
+        .map(|(pos, analysis)| match pos.outcome() {
+            Some(outcome) => Some(outcome_evaluation(outcome)),
+            None => analysis.as_ref().map(evaluation),
+        })
+        .collect();
+
+    let mut move_number = u32::from(game.start().fullmoves);
+    let mut moves = Vec::new();
+    for (ply, san) in game.moves().iter().enumerate() {
+        let pos = &positions[ply];
+        let color = pos.turn();
+        let judged = match (&analyses[ply], evals[ply], evals[ply + 1]) {
+            (Some(analysis), Some(before), Some(after)) => Some((analysis, before, after)),
+            _ => None,
+        };
+        if let Some((analysis, before, after)) = judged {
+            // The evaluations from the point of view of the player
+            let sign = match color {
+                Color::White => 1,
+                Color::Black => -1,
+            };
+            let drop = win_chance(sign * before) - win_chance(sign * after);
+            moves.push(MoveReview {
+                move_number,
+                color,
+                san: san.to_string(),
+                best_move: San::from_move(pos, &analysis.best_move).to_string(),
+                eval_before: before,
+                eval_after: after,
+                centipawn_loss: (sign * (before - after)).max(0) as u32,
+                accuracy: move_accuracy(drop),
+                judgement: Judgement::from_drop(drop),
+            });
+        }
+        if color == Color::Black {
+            move_number += 1;
+        }
+    }
+
+    let mut critical: Vec<_> = moves.iter().filter(|m| m.swing() > 0.0).collect();
+    critical.sort_by(|a, b| b.swing().partial_cmp(&a.swing()).expect("no NaN swings"));
+    critical.truncate(CRITICAL_MOMENTS);
+    let mut critical_moments: Vec<MoveReview> = critical.into_iter().cloned().collect();
+    critical_moments.sort_by_key(|m| (m.move_number, m.color == Color::Black));
+
+    let player = |color: Color, tag: &str| {
+        let name = pgn.tag(tag).unwrap_or(tag).to_owned();
+        let own: Vec<&MoveReview> = moves.iter().filter(|m| m.color == color).collect();
+        PlayerReview::new(name, &own)
+    };
+    Ok(GameReview {
+        white: player(Color::White, "White"),
+        black: player(Color::Black, "Black"),
+        depth,
+        moves,
+        critical_moments,
+        failures,
+    })
+}
@@ -0,0 +1,535 @@
+//! An HTTP service analysing positions in the background, enabled by the
+//! `serve` feature
+//!
+//! * `POST /jobs` with `{"fen": "...", "depth": 20, "profile": "local",
+//!   "variant": "chess"}`, where only the FEN is required, queues an
+//!   analysis and returns the job,
+//! * `GET /jobs/<id>` returns the job, with its status,
+//! * `GET /jobs/<id>/result` returns the `AnalysisResult` of a finished job,
+//! * `DELETE /jobs/<id>` cancels a queued or running job, which frees its
+//!   place in the queue or stops its analysis.
+//!
+//! The errors are returned as `{"error": "..."}`.
+
+use crate::analysis::interpret_variant_uci;
+use crate::backends::AsyncUciBackend;
+use crate::cache::{AnalysisCache, CachedUci};
+use crate::config::Profile;
+#[cfg(feature = "history")]
+use crate::history::{AnalysisHistory, RecordedUci};
+use crate::variant::Variant;
+use anyhow::{anyhow, Context, Result};
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use shakmaty::fen::Fen;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+pub const DEFAULT_QUEUE_SIZE: usize = 64;
+/// The finished jobs kept for the clients to fetch, the oldest are dropped
+const MAX_FINISHED_JOBS: usize = 1024;
+/// The largest request body accepted, in bytes
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_finished(self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// Shows the status as in the JSON
+impl fmt::Display for JobStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => f.write_str(&name),
+            _ => unreachable!("a status is serialized as a string"),
+        }
+    }
+}
+
+/// The body of `POST /jobs`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobRequest {
+    fen: String,
+    depth: Option<u32>,
+    profile: Option<String>,
+    variant: Option<Variant>,
+}
+
+/// What the clients see of a job
+#[derive(Clone, Serialize)]
+struct JobInfo {
+    id: u64,
+    status: JobStatus,
+    fen: String,
+    variant: String,
+    depth: u32,
+    profile: Option<String>,
+    error: Option<String>,
+}
+
+struct Job {
+    info: JobInfo,
+    fen: Fen,
+    variant: Variant,
+    result: Option<serde_json::Value>,
+    /// Stops the running analysis
+    cancel: Option<oneshot::Sender<()>>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+/// Analyses the positions submitted over HTTP, using the profiles of the
+/// configuration
+pub struct AnalysisService {
+    default_profile: Profile,
+    profiles: BTreeMap<String, Profile>,
+    cache: Option<AnalysisCache>,
+    #[cfg(feature = "history")]
+    history: Option<AnalysisHistory>,
+    queue_size: usize,
+    workers: usize,
+}
+
+impl AnalysisService {
+    /// Analyses the jobs which don't name a profile with `default_profile`
+    pub fn new(default_profile: Profile) -> Self {
+        Self {
+            default_profile,
+            profiles: BTreeMap::new(),
+            cache: None,
+            #[cfg(feature = "history")]
+            history: None,
+            queue_size: DEFAULT_QUEUE_SIZE,
+            workers: 1,
+        }
+    }
+
+    /// Sets the profiles the jobs may select by name.
+    pub fn profiles(mut self, profiles: BTreeMap<String, Profile>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// Serves the analyses from `cache` when possible.
+    pub fn cache(mut self, cache: Option<AnalysisCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Records the analyses in `history`.
+    #[cfg(feature = "history")]
+    pub fn history(mut self, history: Option<AnalysisHistory>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Sets the number of jobs which may wait for a worker, further jobs
+    /// are refused.
+    pub fn queue_size(mut self, size: usize) -> Self {
+        self.queue_size = size.max(1);
+        self
+    }
+
+    /// Sets the number of jobs analysed at the same time.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Starts the workers and binds the HTTP server to `addr`, returning
+    /// the bound address and the server to await.
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn bind(self, addr: &SocketAddr) -> Result<(SocketAddr, impl Future<Output = Result<()>>)> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let state = Arc::new(State {
+            service: self,
+            jobs: Mutex::new(Jobs::default()),
+            queue: sender,
+            backends: Mutex::new(HashMap::new()),
+        });
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        for _ in 0..state.service.workers {
+            tokio::spawn(work(state.clone(), receiver.clone()));
+        }
+
+        let make_service = make_service_fn(move |_| {
+            let state = state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+        });
+        let server = Server::try_bind(addr)
+            .with_context(|| format!("listening on {}", addr))?
+            .serve(make_service);
+        let local_addr = server.local_addr();
+        Ok((
+            local_addr,
+            async move { server.await.context("serving HTTP") },
+        ))
+    }
+}
+
+/// The state shared by the request handlers and the workers
+struct State {
+    service: AnalysisService,
+    jobs: Mutex<Jobs>,
+    /// The jobs to run. The size of the queue is the number of the queued
+    /// jobs, the cancelled ones are skipped by the workers.
+    queue: mpsc::UnboundedSender<u64>,
+    /// The backends of the profiles, set up on the first use
+    backends: Mutex<HashMap<Option<String>, Arc<dyn AsyncUciBackend>>>,
+}
+
+impl State {
+    fn profile(&self, name: Option<&str>) -> Result<&Profile> {
+        match name {
+            Some(name) => self
+                .service
+                .profiles
+                .get(name)
+                .ok_or_else(|| anyhow!("no profile named `{}` in the configuration", name)),
+            None => Ok(&self.service.default_profile),
+        }
+    }
+
+    fn backend(&self, name: Option<&str>) -> Result<Arc<dyn AsyncUciBackend>> {
+        let mut backends = self.backends.lock().expect("the backends are poisoned");
+        let key = name.map(str::to_owned);
+        if let Some(backend) = backends.get(&key) {
+            return Ok(backend.clone());
+        }
+        let backend = self.profile(name)?.async_backend()?;
+        #[cfg(feature = "history")]
+        let backend: Box<dyn AsyncUciBackend> = match &self.service.history {
+            Some(history) => Box::new(RecordedUci::new(backend, history.clone())),
+            None => backend,
+        };
+        let backend: Arc<dyn AsyncUciBackend> = match &self.service.cache {
+            Some(cache) => Arc::new(CachedUci::new(backend, cache.clone())),
+            None => Arc::from(backend),
+        };
+        backends.insert(key, backend.clone());
+        Ok(backend)
+    }
+
+    fn submit(&self, body: &[u8]) -> Result<JobInfo, ApiError> {
+        let request: JobRequest = serde_json::from_slice(body)
+            .map_err(|e| ApiError::bad_request(format!("invalid job: {}", e)))?;
+        let profile = self
+            .profile(request.profile.as_deref())
+            .map_err(ApiError::bad_request)?;
+        let fen: Fen = request
+            .fen
+            .parse()
+            .map_err(|_| ApiError::bad_request(format!("invalid FEN `{}`", request.fen)))?;
+        let variant = request.variant.unwrap_or_else(|| profile.variant());
+        variant.validate(&fen).map_err(ApiError::bad_request)?;
+        let depth = request
+            .depth
+            .or(profile.depth)
+            .ok_or_else(|| ApiError::bad_request("no analysis depth given"))?;
+
+        let mut jobs = self.jobs.lock().expect("the jobs are poisoned");
+        if jobs.queued() >= self.service.queue_size {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "the queue is full, try again later",
+            ));
+        }
+        let id = jobs.next_id;
+        let info = JobInfo {
+            id,
+            status: JobStatus::Queued,
+            fen: fen.to_string(),
+            variant: variant.to_string(),
+            depth,
+            profile: request.profile,
+            error: None,
+        };
+        self.queue.send(id).map_err(|_| {
+            ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "the workers have stopped")
+        })?;
+        jobs.next_id += 1;
+        let job = Job {
+            info: info.clone(),
+            fen,
+            variant,
+            result: None,
+            cancel: None,
+        };
+        jobs.jobs.insert(id, job);
+        jobs.forget_finished();
+        Ok(info)
+    }
+
+    fn job(&self, id: &str) -> Result<JobInfo, ApiError> {
+        let jobs = self.jobs.lock().expect("the jobs are poisoned");
+        jobs.get(id).map(|job| job.info.clone())
+    }
+
+    fn result(&self, id: &str) -> Result<serde_json::Value, ApiError> {
+        let jobs = self.jobs.lock().expect("the jobs are poisoned");
+        let job = jobs.get(id)?;
+        match (&job.result, job.info.status) {
+            (Some(result), _) => Ok(result.clone()),
+            (None, JobStatus::Failed) => Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!(
+                    "the job has failed: {}",
+                    job.info.error.as_deref().unwrap_or("")
+                ),
+            )),
+            (None, status) => Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!("the job is {}", status),
+            )),
+        }
+    }
+
+    fn cancel(&self, id: &str) -> Result<JobInfo, ApiError> {
+        let mut jobs = self.jobs.lock().expect("the jobs are poisoned");
+        let job = jobs.get_mut(id)?;
+        if job.info.status.is_finished() {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "the job has already finished",
+            ));
+        }
+        if let Some(cancel) = job.cancel.take() {
+            let _ = cancel.send(());
+        }
+        job.info.status = JobStatus::Cancelled;
+        Ok(job.info.clone())
+    }
+
+    /// Analyses the job, unless it has been cancelled while queued
+    async fn run(&self, id: u64) {
+        let (cancelled, fen, variant, depth, profile) = {
+            let mut jobs = self.jobs.lock().expect("the jobs are poisoned");
+            let job = match jobs.jobs.get_mut(&id) {
+                Some(job) if job.info.status == JobStatus::Queued => job,
+                _ => return,
+            };
+            let (cancel, cancelled) = oneshot::channel();
+            job.cancel = Some(cancel);
+            job.info.status = JobStatus::Running;
+            let info = &job.info;
+            (
+                cancelled,
+                job.fen.clone(),
+                job.variant,
+                info.depth,
+                info.profile.clone(),
+            )
+        };
+
+        let analysis = self.analyse(profile.as_deref(), fen, variant, depth);
+        let outcome = tokio::select! {
+            result = analysis => Some(result),
+            _ = cancelled => None,
+        };
+
+        let mut jobs = self.jobs.lock().expect("the jobs are poisoned");
+        if let (Some(job), Some(outcome)) = (jobs.jobs.get_mut(&id), outcome) {
+            job.cancel = None;
+            match outcome {
+                Ok(result) => {
+                    job.result = Some(result);
+                    job.info.status = JobStatus::Done;
+                }
+                Err(e) => {
+                    job.info.error = Some(format!("{:#}", e));
+                    job.info.status = JobStatus::Failed;
+                }
+            }
+        }
+    }
+
+    async fn analyse(
+        &self,
+        profile: Option<&str>,
+        fen: Fen,
+        variant: Variant,
+        depth: u32,
+    ) -> Result<serde_json::Value> {
+        let backend = self.backend(profile)?;
+        let cmds = backend.generate_variant_uci(variant, &fen.to_string(), depth);
+        let output = backend.execute_async(cmds).await.context("executing UCI")?;
+        let mut result = interpret_variant_uci(variant, fen, output)?;
+        result.max_cost = backend.max_cost();
+        Ok(serde_json::to_value(&result)?)
+    }
+}
+
+impl Jobs {
+    fn get(&self, id: &str) -> Result<&Job, ApiError> {
+        id.parse()
+            .ok()
+            .and_then(|id| self.jobs.get(&id))
+            .ok_or_else(ApiError::no_such_job)
+    }
+
+    fn get_mut(&mut self, id: &str) -> Result<&mut Job, ApiError> {
+        id.parse()
+            .ok()
+            .and_then(move |id| self.jobs.get_mut(&id))
+            .ok_or_else(ApiError::no_such_job)
+    }
+
+    fn queued(&self) -> usize {
+        self.jobs
+            .values()
+            .filter(|job| job.info.status == JobStatus::Queued)
+            .count()
+    }
+
+    fn forget_finished(&mut self) {
+        let finished: Vec<u64> = self
+            .jobs
+            .iter()
+            .filter(|(_, job)| job.info.status.is_finished())
+            .map(|(id, _)| *id)
+            .collect();
+        let excess = finished.len().saturating_sub(MAX_FINISHED_JOBS);
+        for id in &finished[..excess] {
+            self.jobs.remove(id);
+        }
+    }
+}
+
+async fn work(state: Arc<State>, queue: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<u64>>>) {
+    loop {
+        let id = queue.lock().await.recv().await;
+        match id {
+            Some(id) => state.run(id).await,
+            None => return,
+        }
+    }
+}
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new<M: ToString>(status: StatusCode, message: M) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    fn bad_request<M: std::fmt::Display>(message: M) -> Self {
+        // Include the causes of anyhow errors
+        Self::new(StatusCode::BAD_REQUEST, format!("{:#}", message))
+    }
+
+    fn no_such_job() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "no such job")
+    }
+
+    fn too_large() -> Self {
+        Self::new(StatusCode::PAYLOAD_TOO_LARGE, "the job is too large")
+    }
+
+    fn into_response(self) -> Response<Body> {
+        #[derive(Serialize)]
+        struct Error {
+            error: String,
+        }
+        json_response(
+            self.status,
+            &Error {
+                error: self.message,
+            },
+        )
+    }
+}
+
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    let body = serde_json::to_vec(body).expect("serializing the response");
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .expect("building the response")
+}
+
+async fn handle(state: Arc<State>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = route(&state, req).await;
+    Ok(response.unwrap_or_else(ApiError::into_response))
+}
+
+async fn route(state: &State, req: Request<Body>) -> Result<Response<Body>, ApiError> {
+    let method = req.method().clone();
+    let path: Vec<String> = req
+        .uri()
+        .path()
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(str::to_owned)
+        .collect();
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    match (method, &path[..]) {
+        (Method::POST, ["jobs"]) => {
+            let length = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+            if length.map_or(false, |length| length > MAX_BODY_SIZE) {
+                return Err(ApiError::too_large());
+            }
+            let body = read_body(req.into_body()).await?;
+            let job = state.submit(&body)?;
+            Ok(json_response(StatusCode::ACCEPTED, &job))
+        }
+        (Method::GET, ["jobs", id]) => Ok(json_response(StatusCode::OK, &state.job(id)?)),
+        (Method::GET, ["jobs", id, "result"]) => {
+            Ok(json_response(StatusCode::OK, &state.result(id)?))
+        }
+        (Method::DELETE, ["jobs", id]) => Ok(json_response(StatusCode::OK, &state.cancel(id)?)),
+        (_, ["jobs"]) | (_, ["jobs", _]) | (_, ["jobs", _, "result"]) => Err(ApiError::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method not allowed",
+        )),
+        _ => Err(ApiError::new(StatusCode::NOT_FOUND, "not found")),
+    }
+}
+
+/// Reads the body up to `MAX_BODY_SIZE`, whether its length is announced or
+/// it's chunked
+async fn read_body(mut body: Body) -> Result<Vec<u8>, ApiError> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(ApiError::bad_request)?;
+        if (bytes.len() + chunk.len()) as u64 > MAX_BODY_SIZE {
+            return Err(ApiError::too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,138 @@
+//! Syzygy tablebase probing
+//!
+//! For the positions with few enough pieces the tablebase gives the exact
+//! result for free, so there's no need to run the engine at all.
+
+use crate::analysis::{interpret_uci, Advantage, AnalysisResult, AnalysisSource};
+use crate::backends::UciBackend;
+use crate::variant::Variant;
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+use shakmaty::{Chess, Color, Position, Setup};
+use shakmaty_syzygy::{Dtz, SyzygyError, Wdl};
+use std::path::Path;
+
+/// The number of plies of the principal variation read from the tablebase
+const PV_LENGTH: usize = 10;
+
+pub struct Tablebase {
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+}
+
+impl Tablebase {
+    /// Loads the tables from the given directories
+    pub fn open<P: AsRef<Path>>(dirs: &[P]) -> Result<Self> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        for dir in dirs {
+            let dir = dir.as_ref();
+            tables
+                .add_directory(dir)
+                .with_context(|| format!("loading the tablebase from {}", dir.display()))?;
+        }
+        Ok(Self { tables })
+    }
+
+    /// The maximal number of pieces of a position covered by the tables
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    fn is_covered(&self, pos: &Chess) -> bool {
+        // The tables don't cover the positions with castling rights
+        pos.castling_rights().is_empty() && pos.board().occupied().count() <= self.max_pieces()
+    }
+
+    /// Returns the exact result of the position, or `None` if it isn't
+    /// covered or its DTZ table can't be probed
+    pub fn probe(&self, fen: &Fen) -> Result<Option<AnalysisResult>> {
+        let pos: Chess = fen.position()?;
+        if !self.is_covered(&pos) {
+            return Ok(None);
+        }
+
+        let wdl = match self.tables.probe_wdl(&pos) {
+            Ok(wdl) => wdl,
+            Err(SyzygyError::MissingTable { .. }) => {
+                debug!("The table of the position is missing");
+                return Ok(None);
+            }
+            Err(e) => return Err(e).context("probing WDL"),
+        };
+        let (best_move, dtz) = match self.tables.best_move(&pos) {
+            Ok(Some(best)) => best,
+            // The game is over, the engine wouldn't find a move either
+            Ok(None) => return Ok(None),
+            // E.g. only the WDL table is there, the engine will find the move
+            Err(e) => {
+                warn!(
+                    "Cannot probe DTZ, leaving the position to the engine: {}",
+                    e
+                );
+                return Ok(None);
+            }
+        };
+
+        let (advantage, advantage_side) = tablebase_advantage(wdl, dtz, pos.turn());
+        Ok(Some(AnalysisResult {
+            advantage,
+            advantage_side,
+            depth: 0,
+            pv: self.principal_variation(pos),
+            best_move,
+            ponder: None,
+            source: AnalysisSource::Tablebase,
+            variant: Variant::Standard,
+            max_cost: None,
+        }))
+    }
+
+    /// Follows the best moves of both sides, as far as the tables allow
+    fn principal_variation(&self, mut pos: Chess) -> Vec<Uci> {
+        let mut pv = Vec::new();
+        while pv.len() < PV_LENGTH {
+            match self.tables.best_move(&pos) {
+                Ok(Some((mv, _))) => {
+                    pv.push(Uci::from_standard(&mv));
+                    pos.play_unchecked(&mv);
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    debug!("Stopping the principal variation: {}", e);
+                    break;
+                }
+            }
+        }
+        pv
+    }
+}
+
+/// The advantage following from the WDL and the DTZ of the side to move,
+/// `turn`, and the side having it
+pub fn tablebase_advantage(wdl: Wdl, dtz: Dtz, turn: Color) -> (Advantage, Color) {
+    let dtz = dtz.0.abs() as u32;
+    match wdl {
+        Wdl::Win => (Advantage::TablebaseWin(dtz), turn),
+        Wdl::Loss => (Advantage::TablebaseWin(dtz), !turn),
+        // Cursed wins and blessed losses are draws under the 50-move rule
+        Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => (Advantage::Equality, turn),
+    }
+}
+
+/// Analyses the position using the tablebase if it's covered,
+/// otherwise running the engine to `depth`.
+pub fn analyse(
+    tablebase: &Tablebase,
+    backend: &dyn UciBackend,
+    fen: Fen,
+    depth: u32,
+) -> Result<AnalysisResult> {
+    if let Some(result) = tablebase.probe(&fen)? {
+        info!("The position is in the tablebase");
+        return Ok(result);
+    }
+    let cmds = backend.generate_uci(&fen.to_string(), depth);
+    let output = backend.execute_uci(cmds).context("Executing UCI")?;
+    interpret_uci(fen, output)
+}
@@ -0,0 +1,196 @@
+//! Games with variations, browsed move by move
+//!
+//! Every move of the tree may be followed by several continuations: the
+//! first one is the main line, the others are variations branching from
+//! the move.
+
+use anyhow::{bail, Context, Result};
+use shakmaty::fen::{self, Fen};
+use shakmaty::san::SanPlus;
+use shakmaty::{Chess, Color, Move, Position, Setup};
+
+/// The index of a position of the tree
+pub type NodeId = usize;
+
+struct Node {
+    position: Chess,
+    /// The move leading to the position, none at the start
+    last_move: Option<(Move, SanPlus)>,
+    parent: Option<NodeId>,
+    /// The main line first
+    children: Vec<NodeId>,
+}
+
+/// A row of the move list: consecutive moves of a line, with their numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRow {
+    /// How deeply the line is nested, 0 for the main line
+    pub depth: usize,
+    /// The moves, e.g. `1. e4` or `1... e5` at the start of a row, and the
+    /// positions they lead to
+    pub moves: Vec<(NodeId, String)>,
+}
+
+/// A game of chess or Chess960 with variations, and a selected position
+pub struct GameTree {
+    nodes: Vec<Node>,
+    current: NodeId,
+}
+
+impl GameTree {
+    /// The start position
+    pub const ROOT: NodeId = 0;
+
+    pub fn new(start: Chess) -> Self {
+        Self {
+            nodes: vec![Node {
+                position: start,
+                last_move: None,
+                parent: None,
+                children: Vec::new(),
+            }],
+            current: Self::ROOT,
+        }
+    }
+
+    pub fn from_fen(fen: &Fen) -> Result<Self> {
+        let start = fen.position().context("illegal start position")?;
+        Ok(Self::new(start))
+    }
+
+    /// The selected position
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+
+    pub fn position(&self) -> &Chess {
+        &self.nodes[self.current].position
+    }
+
+    pub fn fen(&self) -> String {
+        fen::fen(self.position())
+    }
+
+    /// Plays a move in the selected position and selects the position it
+    /// leads to. A move which isn't in the tree yet starts a variation, or
+    /// extends the line if it's the last position.
+    pub fn play(&mut self, m: &Move) -> Result<NodeId> {
+        let node = &self.nodes[self.current];
+        if !node.position.is_legal(m) {
+            bail!("illegal move {}", m);
+        }
+        let existing = node.children.iter().copied().find(|&child| {
+            let (played, _) = self.nodes[child]
+                .last_move
+                .as_ref()
+                .expect("a child has a move");
+            played == m
+        });
+        if let Some(child) = existing {
+            self.current = child;
+            return Ok(child);
+        }
+
+        let mut position = node.position.clone();
+        let san = SanPlus::from_move_and_play_unchecked(&mut position, m);
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            position,
+            last_move: Some((m.clone(), san)),
+            parent: Some(self.current),
+            children: Vec::new(),
+        });
+        self.nodes[self.current].children.push(id);
+        self.current = id;
+        Ok(id)
+    }
+
+    pub fn select(&mut self, node: NodeId) -> Result<()> {
+        if node >= self.nodes.len() {
+            bail!("no position {} in the game", node);
+        }
+        self.current = node;
+        Ok(())
+    }
+
+    /// Selects the position before the last move, returning whether there
+    /// was one
+    pub fn back(&mut self) -> bool {
+        match self.nodes[self.current].parent {
+            Some(parent) => {
+                self.current = parent;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Selects the next position of the line, returning whether there was
+    /// one
+    pub fn forward(&mut self) -> bool {
+        match self.nodes[self.current].children.first() {
+            Some(&child) => {
+                self.current = child;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Selects the start position
+    pub fn first(&mut self) {
+        self.current = Self::ROOT;
+    }
+
+    /// Selects the last position of the line
+    pub fn last(&mut self) {
+        while self.forward() {}
+    }
+
+    /// The moves as shown in a move list: the main line is split after
+    /// every move with variations, which follow it, nested one level deeper
+    pub fn rows(&self) -> Vec<MoveRow> {
+        let mut rows = Vec::new();
+        if let Some(&first) = self.nodes[Self::ROOT].children.first() {
+            self.push_line(first, 0, &mut rows);
+        }
+        rows
+    }
+
+    fn push_line(&self, first: NodeId, depth: usize, rows: &mut Vec<MoveRow>) {
+        let mut moves = Vec::new();
+        let mut next = Some(first);
+        while let Some(id) = next {
+            moves.push((id, self.label(id, moves.is_empty())));
+            let parent = self.nodes[id].parent.expect("a move has a parent");
+            let siblings = &self.nodes[parent].children;
+            if siblings[0] == id && siblings.len() > 1 {
+                rows.push(MoveRow {
+                    depth,
+                    moves: std::mem::take(&mut moves),
+                });
+                for &variation in &siblings[1..] {
+                    self.push_line(variation, depth + 1, rows);
+                }
+            }
+            next = self.nodes[id].children.first().copied();
+        }
+        if !moves.is_empty() {
+            rows.push(MoveRow { depth, moves });
+        }
+    }
+
+    /// The SAN of the move leading to `id`, with the move number for White,
+    /// and for Black at the start of a row
+    fn label(&self, id: NodeId, row_start: bool) -> String {
+        let node = &self.nodes[id];
+        let (_, san) = node.last_move.as_ref().expect("a child has a move");
+        let before = &self.nodes[node.parent.expect("a move has a parent")].position;
+        let number = u32::from(before.fullmoves());
+        match before.turn() {
+            Color::White => format!("{}. {}", number, san),
+            Color::Black if row_start => format!("{}... {}", number, san),
+            Color::Black => san.to_string(),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Chess variants supported by shakmaty and the UCI engines
+//!
+//! Chess960 is set up with the `UCI_Chess960` option, the other variants
+//! with `UCI_Variant`, as understood by Fairy-Stockfish and the multi-variant
+//! Stockfish.
+
+use crate::backends::UciOption;
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+use shakmaty::variants::{
+    Atomic, Crazyhouse, Giveaway, Horde, KingOfTheHill, RacingKings, ThreeCheck,
+};
+use shakmaty::{Chess, Move};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Variant {
+    Standard,
+    Chess960,
+    Antichess,
+    Atomic,
+    Crazyhouse,
+    Horde,
+    KingOfTheHill,
+    RacingKings,
+    ThreeCheck,
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Variant::Standard
+    }
+}
+
+impl Variant {
+    pub const ALL: [Variant; 9] = [
+        Variant::Standard,
+        Variant::Chess960,
+        Variant::Antichess,
+        Variant::Atomic,
+        Variant::Crazyhouse,
+        Variant::Horde,
+        Variant::KingOfTheHill,
+        Variant::RacingKings,
+        Variant::ThreeCheck,
+    ];
+
+    /// The name used on the command line and by `UCI_Variant`
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::Chess960 => "chess960",
+            Variant::Antichess => "antichess",
+            Variant::Atomic => "atomic",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Horde => "horde",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::RacingKings => "racingkings",
+            Variant::ThreeCheck => "3check",
+        }
+    }
+
+    /// The options telling the engine which variant is played
+    pub fn uci_options(self) -> Vec<UciOption> {
+        match self {
+            Variant::Standard => vec![],
+            Variant::Chess960 => vec![UciOption::new("UCI_Chess960", "true")],
+            other => vec![UciOption::new("UCI_Variant", other.name())],
+        }
+    }
+
+    /// The move in UCI notation, castling written as the king taking its
+    /// rook in Chess960
+    pub fn uci(self, m: &Move) -> Uci {
+        match self {
+            Variant::Chess960 => Uci::from_chess960(m),
+            _ => Uci::from_standard(m),
+        }
+    }
+
+    /// Checks that the FEN describes a legal position of the variant
+    pub fn validate(self, fen: &Fen) -> Result<()> {
+        let res = match self {
+            Variant::Standard | Variant::Chess960 => fen.position::<Chess>().map(drop),
+            Variant::Antichess => fen.position::<Giveaway>().map(drop),
+            Variant::Atomic => fen.position::<Atomic>().map(drop),
+            Variant::Crazyhouse => fen.position::<Crazyhouse>().map(drop),
+            Variant::Horde => fen.position::<Horde>().map(drop),
+            Variant::KingOfTheHill => fen.position::<KingOfTheHill>().map(drop),
+            Variant::RacingKings => fen.position::<RacingKings>().map(drop),
+            Variant::ThreeCheck => fen.position::<ThreeCheck>().map(drop),
+        };
+        res.map_err(|e| anyhow!("illegal {} position: {}", self, e))
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Variant {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let normalized = s.to_lowercase().replace(|c| c == '-' || c == '_', "");
+        let variant = match normalized.as_str() {
+            "chess" | "standard" => Variant::Standard,
+            "chess960" | "fischerandom" | "fischerrandom" => Variant::Chess960,
+            "antichess" | "giveaway" => Variant::Antichess,
+            "koth" => Variant::KingOfTheHill,
+            "threecheck" => Variant::ThreeCheck,
+            other => *Variant::ALL
+                .iter()
+                .find(|v| v.name() == other)
+                .ok_or_else(|| anyhow!("unknown variant `{}`", s))?,
+        };
+        Ok(variant)
+    }
+}
+
+/// Written by name, as it's read
+impl Serialize for Variant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl TryFrom<String> for Variant {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
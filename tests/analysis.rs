@@ -0,0 +1,78 @@
+use golemate::analysis::{interpret_variant_uci, pv_moves, Score};
+use golemate::variant::Variant;
+use shakmaty::fen::Fen;
+use shakmaty::Color;
+
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn pv(moves: &[&str]) -> Vec<String> {
+    moves.iter().map(|m| (*m).to_owned()).collect()
+}
+
+#[test]
+fn plays_the_principal_variation() {
+    let fen: Fen = INITIAL_FEN.parse().unwrap();
+    let moves = pv_moves(Variant::Standard, &fen, &pv(&["e2e4", "e7e5", "g1f3"])).unwrap();
+    let san: Vec<_> = moves.iter().map(|m| m.san.as_str()).collect();
+    assert_eq!(san, vec!["e4", "e5", "Nf3"]);
+    assert_eq!(
+        moves[2].fen,
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    );
+}
+
+#[test]
+fn stops_at_an_illegal_move() {
+    let fen: Fen = INITIAL_FEN.parse().unwrap();
+    let moves = pv_moves(Variant::Standard, &fen, &pv(&["e2e4", "e2e4", "g1f3"])).unwrap();
+    assert_eq!(moves.len(), 1);
+    assert!(pv_moves(Variant::Standard, &fen, &pv(&["x"]))
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn follows_the_rules_of_the_variant() {
+    // Capturing is compulsory in antichess
+    let fen: Fen = "rnbqkbnr/pppp1ppp/8/4p3/3P4/8/PPP1PPPP/RNBQKBNR w - - 0 2"
+        .parse()
+        .unwrap();
+    let line = pv(&["g1f3", "e5d4"]);
+    assert_eq!(pv_moves(Variant::Standard, &fen, &line).unwrap().len(), 2);
+    assert!(pv_moves(Variant::Antichess, &fen, &line)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn scores_for_white() {
+    assert_eq!(Score::Cp(35).describe_for_white(Color::White), "+0.35");
+    assert_eq!(Score::Cp(35).describe_for_white(Color::Black), "-0.35");
+    assert_eq!(Score::Mate(-3).describe_for_white(Color::Black), "#3");
+
+    assert_eq!(Score::Cp(0).white_chance(Color::Black), 50.0);
+    assert!(Score::Cp(200).white_chance(Color::White) > 50.0);
+    assert!(Score::Cp(200).white_chance(Color::Black) < 50.0);
+    assert_eq!(Score::Mate(2).white_chance(Color::Black), 0.0);
+    assert_eq!(Score::Mate(-1).white_chance(Color::Black), 100.0);
+}
+
+#[test]
+fn writes_the_castling_of_the_variant() {
+    let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    let castle = |variant, best_move: &str, ponder: &str| {
+        let output = vec![format!("bestmove {} ponder {}", best_move, ponder)];
+        let result = interpret_variant_uci(variant, fen.parse().unwrap(), output).unwrap();
+        let json = serde_json::to_value(&result).unwrap();
+        (json["best_move"].clone(), json["ponder"].clone())
+    };
+    assert_eq!(
+        castle(Variant::Standard, "e1g1", "e8c8"),
+        ("e1g1".into(), "e8c8".into())
+    );
+    // The king takes its rook in Chess960
+    assert_eq!(
+        castle(Variant::Chess960, "e1h1", "e8a8"),
+        ("e1h1".into(), "e8a8".into())
+    );
+}
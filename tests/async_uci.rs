@@ -0,0 +1,76 @@
+#![cfg(all(feature = "async", feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::backends::{AsyncUciBackend, BlockingUci, NativeUci, ResourceLimits, UciBackend};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn backends_are_send_and_sync() {
+    assert_send_sync::<NativeUci>();
+    assert_send_sync::<golemate::backends::EnginePool>();
+    assert_send_sync::<Box<dyn UciBackend>>();
+    assert_send_sync::<Box<dyn AsyncUciBackend>>();
+    #[cfg(feature = "gwasm")]
+    assert_send_sync::<golemate::backends::GWasmUci>();
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn runs_analyses_concurrently() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 5 score cp 20 pv f1b5"], "bestmove f1b5");
+    let script = script.replace("on go\n", "on go\n    sleep 300\n");
+    let backend = Arc::new(NativeUci::new(fake_engine(dir.path(), &script)));
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                let cmds = backend.generate_uci(FEN, 5);
+                backend.execute_async(cmds).await
+            })
+        })
+        .collect();
+    for handle in handles {
+        let output = handle.await.unwrap().unwrap();
+        assert!(output.contains(&"bestmove f1b5".to_owned()));
+    }
+    // Run one after another, the analyses would take 2.4 s
+    assert!(start.elapsed() < Duration::from_millis(2000));
+}
+
+#[tokio::test]
+async fn kills_engines_over_the_wall_clock_limit() {
+    let dir = TempDir::new().unwrap();
+    let backend =
+        NativeUci::new(fake_engine(dir.path(), "on go\n    hang\n")).limits(ResourceLimits {
+            wall_clock: Some(1),
+            ..ResourceLimits::default()
+        });
+    let err = backend
+        .execute_async(backend.generate_uci(FEN, 5))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("wall-clock limit"), "{:#}", err);
+}
+
+#[tokio::test]
+async fn wraps_blocking_backends() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&[], "bestmove f1b5");
+    let engine: Box<dyn UciBackend> = Box::new(NativeUci::new(fake_engine(dir.path(), &script)));
+    let backend = BlockingUci::new(engine);
+    let output = backend
+        .execute_async(backend.generate_uci(FEN, 5))
+        .await
+        .unwrap();
+    assert!(output.contains(&"bestmove f1b5".to_owned()));
+}
@@ -0,0 +1,81 @@
+#[cfg(unix)]
+mod common;
+
+use golemate::bench::{search_stats, BENCH_POSITIONS};
+
+#[test]
+fn reads_the_search_stats() {
+    let output: Vec<String> = vec![
+        "info string nodes 999999 time 999999".to_owned(),
+        "info depth 1 seldepth 1 nodes 20 nps 20000 time 1 pv e2e4".to_owned(),
+        "info depth 12 seldepth 15 score cp 30 nodes 150000 nps 1500000 time 100 pv e2e4 nodes 5"
+            .to_owned(),
+        "info nodes 160000 time 104 hashfull 3".to_owned(),
+        "bestmove e2e4".to_owned(),
+    ];
+    assert_eq!(search_stats(&output), (160_000, 104));
+    assert_eq!(search_stats(&[]), (0, 0));
+}
+
+#[test]
+fn uses_legal_positions() {
+    for fen in BENCH_POSITIONS.iter() {
+        let fen: shakmaty::fen::Fen = fen.parse().unwrap();
+        fen.position::<shakmaty::Chess>().unwrap();
+    }
+}
+
+#[cfg(all(feature = "native", unix))]
+#[test]
+fn measures_a_native_engine() {
+    use common::{engine_script, fake_engine};
+    use golemate::backends::{IgnoreProgress, NativeUci};
+    use golemate::bench::run_bench;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(
+        &["info depth 4 nodes 50000 nps 1000000 time 50 pv e2e4"],
+        "bestmove e2e4",
+    );
+    let mut backend = NativeUci::new(fake_engine(dir.path(), &script));
+    let report = run_bench(&mut backend, Arc::new(IgnoreProgress), 4).unwrap();
+
+    assert_eq!(report.positions.len(), BENCH_POSITIONS.len());
+    assert_eq!(report.nodes, 50_000 * BENCH_POSITIONS.len() as u64);
+    assert!(report.engine_time <= report.wall_time);
+    assert!(report.positions.iter().all(|p| p.golem_time.is_none()));
+    assert!(report.describe().starts_with("Benchmark of native:"));
+}
+
+#[cfg(all(feature = "gwasm", unix))]
+#[test]
+fn measures_the_time_in_golem() {
+    use common::mock_golem::MockGolem;
+    use common::write_engine_script;
+    use golemate::backends::{GWasmUci, IgnoreProgress};
+    use golemate::bench::run_bench;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(
+        dir.path(),
+        "engine",
+        "cat > /dev/null\necho \"info depth 3 nodes 1000 time 2 pv e2e4\"\necho \"bestmove e2e4\"\n",
+    );
+    let wasm = dir.path().join("engine.wasm");
+    let js = dir.path().join("engine.js");
+    std::fs::write(&wasm, b"\0asm").unwrap();
+    std::fs::write(&js, b"// js").unwrap();
+    let mut backend = GWasmUci::with_golem(&wasm, &js, None, MockGolem::new(engine)).unwrap();
+    let report = run_bench(&mut backend, Arc::new(IgnoreProgress), 3).unwrap();
+
+    assert_eq!(report.nodes, 1000 * BENCH_POSITIONS.len() as u64);
+    assert!(report
+        .positions
+        .iter()
+        .all(|p| p.golem_time.unwrap() <= p.wall_time));
+    assert!(report.describe().contains("Time in Golem: "));
+}
@@ -0,0 +1,91 @@
+use golemate::book::{describe_book_moves, polyglot_key, Book};
+use shakmaty::fen::Fen;
+use shakmaty::{Chess, Move, Square};
+
+fn position(fen: &str) -> Chess {
+    fen.parse::<Fen>().unwrap().position().unwrap()
+}
+
+#[test]
+fn computes_polyglot_keys() {
+    // The reference values from the Polyglot specification
+    let reference = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            0x463b_9618_1691_fc9c,
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            0x823c_9b50_fd11_4196,
+        ),
+        (
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            0x0756_b944_61c5_0fb0,
+        ),
+        (
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            0x22a4_8b5a_8e47_ff78,
+        ),
+        (
+            "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+            0x00fd_d303_c946_bdd9,
+        ),
+        (
+            "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+            0x3c81_23ea_7b06_7637,
+        ),
+    ];
+    for (fen, key) in reference.iter() {
+        assert_eq!(polyglot_key(&position(fen)), *key, "{}", fen);
+    }
+}
+
+fn entry(key: u64, mv: u16, weight: u16) -> Vec<u8> {
+    let mut data = key.to_be_bytes().to_vec();
+    data.extend_from_slice(&mv.to_be_bytes());
+    data.extend_from_slice(&weight.to_be_bytes());
+    data.extend_from_slice(&[0; 4]);
+    data
+}
+
+/// Encodes a move as Polyglot does: to, from and promotion bits
+fn raw_move(from: Square, to: Square) -> u16 {
+    (u16::from(u8::from(from)) << 6) | u16::from(u8::from(to))
+}
+
+#[test]
+fn looks_up_weighted_moves() {
+    let start = position("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    let key = polyglot_key(&start);
+    let mut data = entry(key - 1, raw_move(Square::G1, Square::F3), 10);
+    data.extend(entry(key, raw_move(Square::D2, Square::D4), 1));
+    data.extend(entry(key, raw_move(Square::E2, Square::E4), 3));
+    // An illegal move from a broken book
+    data.extend(entry(key, raw_move(Square::E2, Square::E5), 7));
+    data.extend(entry(key + 1, raw_move(Square::B1, Square::C3), 10));
+    let book = Book::from_bytes(data).unwrap();
+
+    let entries = book.lookup(&start);
+    let targets: Vec<_> = entries.iter().map(|e| e.mv.to()).collect();
+    assert_eq!(targets, vec![Square::E4, Square::D4]);
+    assert_eq!(book.weighted_move(&start, 0.9).unwrap().mv.to(), Square::D4);
+    assert_eq!(
+        describe_book_moves(&start, &entries),
+        "Book moves: e4 (75%), d4 (25%).\nThe book move is e4."
+    );
+}
+
+#[test]
+fn decodes_castling() {
+    let pos = position("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+    let data = entry(polyglot_key(&pos), raw_move(Square::E1, Square::H1), 1);
+    let book = Book::from_bytes(data).unwrap();
+
+    let best = book.best_move(&pos).unwrap();
+    assert!(matches!(best.mv, Move::Castle { .. }));
+}
+
+#[test]
+fn rejects_truncated_books() {
+    assert!(Book::from_bytes(vec![0; 17]).is_err());
+}
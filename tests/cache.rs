@@ -0,0 +1,84 @@
+use golemate::cache::{AnalysisCache, CacheKey};
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+fn lines(lines: &[&str]) -> Vec<String> {
+    lines.iter().map(|line| (*line).to_owned()).collect()
+}
+
+fn request(depth: u32) -> (CacheKey, u32) {
+    let uci = lines(&[
+        "uci",
+        "setoption name Hash value 128",
+        &format!("position fen {}", FEN),
+        &format!("go depth {}", depth),
+    ]);
+    CacheKey::from_uci("native:engine".to_owned(), &uci).unwrap()
+}
+
+#[test]
+fn stores_the_interpreted_result() {
+    let dir = TempDir::new().unwrap();
+    let cache = AnalysisCache::new(dir.path().to_owned());
+    let (key, depth) = request(6);
+    let output = lines(&["info depth 6 score cp 40 pv f1b5 a7a6", "bestmove f1b5"]);
+    cache.insert(&key, depth, &output).unwrap();
+
+    assert_eq!(cache.get(&key, 5).unwrap(), Some(output));
+    let result = cache.get_result(&key, 6).unwrap().unwrap();
+    assert_eq!(result["best_move"], "f1b5");
+    assert_eq!(result["pv"], serde_json::json!(["f1b5", "a7a6"]));
+    assert_eq!(cache.get_result(&key, 7).unwrap(), None);
+}
+
+#[test]
+fn writes_concurrently() {
+    let dir = TempDir::new().unwrap();
+    let cache = Arc::new(AnalysisCache::new(dir.path().to_owned()));
+    let writers: Vec<_> = (0..8)
+        .map(|_| {
+            let cache = cache.clone();
+            thread::spawn(move || {
+                let (key, depth) = request(6);
+                cache.insert(&key, depth, &lines(&["bestmove f1b5"]))
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap().unwrap();
+    }
+
+    let (key, depth) = request(6);
+    assert!(cache.get(&key, depth).unwrap().is_some());
+    // Only the entry is left
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn keys_by_the_canonical_variant() {
+    let key = |variant: &str| {
+        let uci = lines(&[
+            &format!("setoption name UCI_Variant value {}", variant),
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "go depth 10",
+        ]);
+        CacheKey::from_uci("native:engine".to_owned(), &uci)
+            .unwrap()
+            .0
+    };
+    assert_eq!(key("kingofthehill"), key("KOTH"));
+    assert_eq!(key("koth").variant, "kingofthehill");
+    assert!(key("koth").options.is_empty());
+    assert_ne!(key("atomic"), key("koth"));
+    // Selecting standard chess is the same as selecting nothing
+    let uci = lines(&[
+        "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "go depth 10",
+    ]);
+    let (standard, _) = CacheKey::from_uci("native:engine".to_owned(), &uci).unwrap();
+    assert_eq!(standard.variant, "chess");
+    assert_eq!(key("chess"), standard);
+}
@@ -0,0 +1,402 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use std::path::Path;
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+fn golemate(engine: &Path, extra: &[&str]) -> Output {
+    golemate_in(engine.parent().unwrap())
+        .args(&["--fen", FEN, "--depth", "6", "--engine"])
+        .arg(engine)
+        .args(extra)
+        .output()
+        .expect("running golemate")
+}
+
+/// Keeps the cache, the configuration and the history in the test directory
+fn golemate_in(dir: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_golemate"));
+    cmd.env("XDG_CACHE_HOME", dir.join("cache"))
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"));
+    cmd
+}
+
+#[test]
+fn prints_the_analysis() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let output = golemate(&fake_engine(dir.path(), &script), &[]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Analysis depth: 6."), "{}", stdout);
+    assert!(
+        stdout.contains("White has 40 centipawns advantage"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn prints_raw_uci() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let output = golemate(&fake_engine(dir.path(), &script), &["--raw"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().any(|l| l == "bestmove f1b5"), "{}", stdout);
+}
+
+#[test]
+fn fails_on_engine_crash() {
+    let dir = TempDir::new().unwrap();
+    let output = golemate(&fake_engine(dir.path(), "on go\n    crash\n"), &[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn fails_on_illegal_best_move() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&[], "bestmove a1a8");
+    let output = golemate(&fake_engine(dir.path(), &script), &[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn serves_cached_analyses() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 9 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    assert!(golemate(&engine, &["--raw"]).status.success());
+
+    // The engine would crash now, but its mtime hasn't changed
+    std::fs::write(dir.path().join("engine.script"), "on go\n    crash\n").unwrap();
+    let output = golemate(&engine, &["--raw"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().any(|l| l == "bestmove f1b5"), "{}", stdout);
+
+    assert!(!golemate(&engine, &["--no-cache"]).status.success());
+}
+
+#[test]
+fn replays_a_transcript() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    let transcript = dir.path().join("transcript.jsonl");
+    assert!(golemate(
+        &engine,
+        &["--no-cache", "--record", transcript.to_str().unwrap()]
+    )
+    .status
+    .success());
+
+    // The engine would crash now, and the transcript is used instead
+    std::fs::write(dir.path().join("engine.script"), "on go\n    crash\n").unwrap();
+    let output = golemate(
+        &engine,
+        &["--no-cache", "--replay", transcript.to_str().unwrap()],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("White has 40 centipawns advantage"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn prunes_the_cache() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    assert!(golemate(&engine, &[]).status.success());
+
+    let output = golemate_in(dir.path())
+        .args(&["cache", "prune"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.trim(), "Removed 1 cached analyses");
+}
+
+#[test]
+fn takes_the_cache_dir_before_and_after_the_subcommand() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    let cache = dir.path().join("elsewhere");
+    let cache = cache.to_str().unwrap();
+    assert!(golemate(&engine, &["--cache-dir", cache]).status.success());
+
+    let prune = |args: &[&str]| {
+        let output = golemate_in(dir.path()).args(args).output().unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    };
+    // The default cache is left alone
+    assert_eq!(prune(&["cache", "prune"]), "Removed 0 cached analyses");
+    assert_eq!(
+        prune(&["cache", "prune", "--cache-dir", cache]),
+        "Removed 1 cached analyses"
+    );
+    assert!(golemate(&engine, &["--cache-dir", cache]).status.success());
+    assert_eq!(
+        prune(&["--cache-dir", cache, "cache", "prune"]),
+        "Removed 1 cached analyses"
+    );
+}
+
+#[cfg(feature = "history")]
+#[test]
+fn records_the_history() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    assert!(golemate(&engine, &[]).status.success());
+    // Served from the cache, so not recorded again
+    assert!(golemate(&engine, &[]).status.success());
+    assert!(golemate(&engine, &["--no-cache", "--no-history"])
+        .status
+        .success());
+
+    let output = golemate_in(dir.path())
+        .args(&["history", "--json", "--fen", FEN, "--engine"])
+        .arg(&engine)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let records = records.as_array().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["best_move"], "f1b5");
+    assert_eq!(records[0]["limits"], "depth 6");
+    assert_eq!(records[0]["info"][0]["score"]["cp"], 40);
+
+    let output = golemate_in(dir.path())
+        .args(&["history", "--engine", "no-such-engine"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.trim(), "No recorded analyses");
+}
+
+#[cfg(feature = "history")]
+#[test]
+fn takes_the_history_db_before_and_after_the_subcommand() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    let db = dir.path().join("elsewhere.sqlite");
+    let db = db.to_str().unwrap();
+    assert!(golemate(&engine, &["--history-db", db]).status.success());
+
+    let count = |args: &[&str]| {
+        let output = golemate_in(dir.path()).args(args).output().unwrap();
+        assert!(output.status.success());
+        let records: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        records.as_array().unwrap().len()
+    };
+    assert_eq!(count(&["--history-db", db, "history", "--json"]), 1);
+    assert_eq!(count(&["history", "--json", "--history-db", db]), 1);
+    // The default database is left alone
+    assert_eq!(count(&["history", "--json"]), 0);
+}
+
+#[test]
+fn uses_the_profile_from_the_configuration() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 7 score cp 40 pv f1b5"], "bestmove f1b5");
+    fake_engine(dir.path(), &script);
+    let config_dir = dir.path().join("config").join("golemate");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        "[profiles.fake]\nengine = \"../../engine\"\ndepth = 7\noptions = { Hash = 16 }\n",
+    )
+    .unwrap();
+
+    let output = golemate_in(dir.path())
+        .args(&["--fen", FEN, "--profile", "fake", "--no-cache"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Analysis depth: 7."), "{}", stdout);
+
+    // The flags take precedence over the profile
+    let output = golemate_in(dir.path())
+        .args(&["--fen", FEN, "--profile", "fake", "--no-cache"])
+        .args(&["--engine", "/nonexistent"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let output = golemate_in(dir.path())
+        .args(&["--fen", FEN, "--profile", "missing"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("no profile named `missing`"), "{}", stderr);
+}
+
+#[test]
+fn analyses_with_the_subcommand() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    let output = golemate_in(dir.path())
+        .args(&["analyse", "--fen", FEN, "--depth", "6", "--engine"])
+        .arg(&engine)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("The best move is"), "{}", stdout);
+}
+
+#[test]
+fn analyses_a_batch() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    let input = dir.path().join("positions.txt");
+    std::fs::write(
+        &input,
+        format!("# positions\n{}\n\n8/8/8/8/8/8/8/K1k5 w - - bm Ka2;\n", FEN),
+    )
+    .unwrap();
+
+    let output = golemate_in(dir.path())
+        .args(&["--engine"])
+        .arg(&engine)
+        .args(&["batch", "--depth", "6", "--json"])
+        .arg(&input)
+        .output()
+        .unwrap();
+    // f1b5 is illegal in the second position
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["best_move"], "f1b5");
+    assert_eq!(lines[0]["depth"], 6);
+    assert!(lines[1]["error"].is_string());
+}
+
+#[test]
+fn analyses_a_batch_with_several_engines() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let engine = fake_engine(dir.path(), &script);
+    let input = dir.path().join("positions.txt");
+    std::fs::write(
+        &input,
+        format!(
+            "{}
+",
+            FEN
+        )
+        .repeat(5),
+    )
+    .unwrap();
+
+    let output = golemate_in(dir.path())
+        .args(&[
+            "batch",
+            "--no-cache",
+            "--depth",
+            "6",
+            "--json",
+            "--sessions",
+            "2",
+            "--engine",
+        ])
+        .arg(&engine)
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 5);
+    assert!(stdout
+        .lines()
+        .all(|line| line.contains("\"best_move\":\"f1b5\"")));
+}
+
+/// The flags of both backends are in the same command line, they must
+/// not clash
+#[cfg(feature = "gwasm")]
+#[test]
+fn takes_the_sessions_with_the_gwasm_flags() {
+    let dir = TempDir::new().unwrap();
+    let output = golemate_in(dir.path())
+        .args(&["batch", "--sessions", "2", "--help"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("--sessions"), "{}", stdout);
+    assert!(stdout.contains("-j, --js"), "{}", stdout);
+}
+
+#[test]
+fn plays_a_match_between_profiles() {
+    let dir = TempDir::new().unwrap();
+    fake_engine(dir.path(), &engine_script(&[], "bestmove h1h8"));
+    let config = dir.path().join("golemate.toml");
+    std::fs::write(
+        &config,
+        "[profiles.a]\nengine = \"engine\"\ndepth = 1\n[profiles.b]\nengine = \"engine\"\ndepth = 2\n",
+    )
+    .unwrap();
+    let openings = dir.path().join("openings.epd");
+    std::fs::write(&openings, "k7/8/1K6/8/8/8/8/7Q w - -\n").unwrap();
+
+    let output = golemate_in(dir.path())
+        .args(&["match", "a", "b", "--no-cache", "--config"])
+        .arg(&config)
+        .arg("--openings")
+        .arg(&openings)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("Game 1: a - b 1-0 (checkmate)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("Score: a 1 - 1 b\n"), "{}", stdout);
+}
+
+#[test]
+fn shows_the_engine_info() {
+    let dir = TempDir::new().unwrap();
+    let script = "on uci\n    send id name Fake\n    send option name Ponder type check default false\n    send uciok\n";
+    let engine = fake_engine(dir.path(), script);
+    let output = golemate_in(dir.path())
+        .args(&["engine", "info", "--json", "--engine"])
+        .arg(&engine)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["name"], "Fake");
+    assert_eq!(info["capabilities"]["ponder"], true);
+    assert_eq!(info["options"][0]["type"], "check");
+}
@@ -0,0 +1,122 @@
+//! A stand-in for a Golem node, computing gWASM tasks on the local machine.
+//!
+//! Instead of running the WASM binary, every subtask input is fed to the
+//! standard input of a local engine and its standard output is stored in the
+//! workspace, exactly where a Golem node would have put the subtask result.
+
+use anyhow::{anyhow, bail, Context, Result};
+use golemate::backends::{Cancellation, GolemCompute, ProgressEvent, ProgressObserver, Workspace};
+use gwasm_api::task::{ComputedTask, Task};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct MockGolem {
+    engine: PathBuf,
+    timeout: Option<Duration>,
+    fail: bool,
+    corrupt_output: bool,
+}
+
+impl MockGolem {
+    pub fn new(engine: PathBuf) -> Self {
+        Self {
+            engine,
+            timeout: None,
+            fail: false,
+            corrupt_output: false,
+        }
+    }
+
+    /// Kill the engine and fail the task if a subtask takes longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Report the task as failed, as if no provider managed to compute it.
+    pub fn fail(mut self) -> Self {
+        self.fail = true;
+        self
+    }
+
+    /// Replace the subtask outputs with bytes which aren't valid UTF-8.
+    pub fn corrupt_output(mut self) -> Self {
+        self.corrupt_output = true;
+        self
+    }
+
+    fn run_engine(&self, input: PathBuf, output: PathBuf, cancel: &Cancellation) -> Result<()> {
+        let mut child = Command::new(&self.engine)
+            .stdin(File::open(&input).context("opening the subtask input")?)
+            .stdout(File::create(&output).context("creating the subtask output")?)
+            .stderr(Stdio::null())
+            .spawn()
+            .context("running the engine")?;
+
+        let start = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                if !status.success() {
+                    bail!("the engine exited with {}", status);
+                }
+                break;
+            }
+            if cancel.is_cancelled() {
+                child.kill()?;
+                child.wait()?;
+                bail!("the task was cancelled");
+            }
+            if let Some(timeout) = self.timeout {
+                if start.elapsed() > timeout {
+                    child.kill()?;
+                    child.wait()?;
+                    bail!("subtask timed out");
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        if self.corrupt_output {
+            fs::write(&output, b"\xff\xfe\xfd\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl GolemCompute for MockGolem {
+    fn compute(
+        &self,
+        task: Task,
+        _workspace: Arc<Workspace>,
+        progress: Arc<dyn ProgressObserver>,
+        cancel: &Cancellation,
+    ) -> Result<ComputedTask> {
+        progress.notify(ProgressEvent::TaskSubmitted);
+        if self.fail {
+            bail!("task failed: no provider computed the subtask");
+        }
+
+        let options = task.options();
+        let total = options.subtasks().count();
+        for (done, (name, subtask)) in options.subtasks().enumerate() {
+            let mut args = subtask.exec_args();
+            let input = args.next().context("missing subtask input argument")?;
+            let output = args.next().context("missing subtask output argument")?;
+            self.run_engine(
+                options.input_dir_path().join(name).join(input),
+                options.output_dir_path().join(name).join(output),
+                cancel,
+            )
+            .with_context(|| format!("computing subtask {}", name))?;
+            progress.notify(ProgressEvent::Progress((done + 1) as f64 / total as f64));
+        }
+        progress.notify(ProgressEvent::TaskFinished);
+
+        ComputedTask::try_from(task).map_err(|e| anyhow!("collecting the results: {}", e))
+    }
+}
@@ -0,0 +1,53 @@
+#![allow(dead_code)]
+
+#[cfg(feature = "gwasm")]
+pub mod mock_golem;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Writes an executable shell script standing in for a chess engine.
+pub fn write_engine_script(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, format!("#!/bin/sh\n{}", body)).expect("writing the engine script");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+        .expect("making the engine script executable");
+    path
+}
+
+/// Sets up the fake UCI engine from `examples/fake_uci_engine.rs` in `dir`,
+/// driven by `script`, and returns the path to launch it.
+pub fn fake_engine(dir: &Path, script: &str) -> PathBuf {
+    // The test executable lives in target/<profile>/deps
+    let exe = env::current_exe().expect("locating the test executable");
+    let profile_dir = exe
+        .parent()
+        .and_then(Path::parent)
+        .expect("unexpected test executable location");
+    let fake = profile_dir.join("examples").join("fake_uci_engine");
+    assert!(
+        fake.exists(),
+        "the fake engine is missing, build it with `cargo build --examples`"
+    );
+
+    let engine = dir.join("engine");
+    symlink(&fake, &engine).expect("linking the fake engine");
+    fs::write(dir.join("engine.script"), script).expect("writing the fake engine script");
+    engine
+}
+
+/// A script of a well-behaved engine, finding `bestmove` after `info` lines.
+pub fn engine_script(info: &[&str], bestmove: &str) -> String {
+    let mut script = String::from(
+        "on uci\n    send id name Fake\n    send id author Golemate\n    send uciok\n\
+         on isready\n    send readyok\n\
+         on go\n",
+    );
+    for line in info {
+        script.push_str(&format!("    send {}\n", line));
+    }
+    script.push_str(&format!("    send {}\n", bestmove));
+    script
+}
@@ -0,0 +1,163 @@
+use golemate::config::{BackendKind, Config};
+use golemate::variant::Variant;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+const CONFIG: &str = r#"
+default_profile = "local"
+
+[profiles.local]
+engine = "/usr/bin/stockfish"
+depth = 20
+variant = "chess960"
+options = { Threads = 8, Ponder = false, "Syzygy Path" = "/tb" }
+
+[profiles.golem]
+wasm = "stockfish.wasm"
+js = "stockfish.js"
+datadir = "datadir"
+max_price = 0.5
+replicas = 2
+golem = { address = "10.0.0.1", port = 61000, mainnet = true }
+"#;
+
+#[test]
+fn reads_profiles() {
+    let config = Config::from_toml(CONFIG).unwrap();
+    let local = config.profile(None).unwrap();
+    assert_eq!(local.backend_kind(), Some(BackendKind::Native));
+    assert_eq!(local.engine, Some(PathBuf::from("/usr/bin/stockfish")));
+    assert_eq!(local.depth, Some(20));
+    assert_eq!(local.variant(), Variant::Chess960);
+    let options: Vec<_> = local
+        .uci_options()
+        .iter()
+        .map(|o| o.name().to_owned())
+        .collect();
+    assert_eq!(options, ["Ponder", "Syzygy Path", "Threads"]);
+
+    let golem = config.profile(Some("golem")).unwrap();
+    assert_eq!(golem.backend_kind(), Some(BackendKind::Gwasm));
+    assert_eq!(golem.replicas, Some(2));
+    assert_eq!(golem.golem.port, Some(61000));
+    assert!(golem.golem.mainnet);
+}
+
+#[test]
+fn rejects_unknown_settings_and_profiles() {
+    assert!(Config::from_toml("[profiles.local]\nengien = \"stockfish\"\n").is_err());
+    assert!(Config::from_toml("[profiles.local]\nvariant = \"bughouse\"\n").is_err());
+
+    let config = Config::from_toml(CONFIG).unwrap();
+    assert!(config.profile(Some("missing")).is_err());
+    let empty = Config::from_toml("").unwrap();
+    assert_eq!(empty.profile(None).unwrap().backend_kind(), None);
+}
+
+#[test]
+fn resolves_paths_relative_to_the_file() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, CONFIG).unwrap();
+    let config = Config::load(&path).unwrap();
+    let golem = config.profile(Some("golem")).unwrap();
+    assert_eq!(golem.wasm, Some(dir.path().join("stockfish.wasm")));
+    assert_eq!(golem.datadir, Some(dir.path().join("datadir")));
+    let local = config.profile(Some("local")).unwrap();
+    assert_eq!(local.engine, Some(PathBuf::from("/usr/bin/stockfish")));
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn overrides_the_default_engine_options() {
+    use golemate::backends::UciBackend;
+
+    let config = Config::from_toml("[profiles.p]\nengine = \"e\"\noptions = { hash = 16 }\n");
+    let backend = config
+        .unwrap()
+        .profile(Some("p"))
+        .unwrap()
+        .backend()
+        .unwrap();
+    let cmds = backend.generate_uci("8/8/8/8/8/8/8/K1k5 w - - 0 1", 1);
+    assert!(cmds.contains(&"setoption name hash value 16".to_owned()));
+    assert!(!cmds.iter().any(|cmd| cmd.contains("name Hash")));
+    assert!(cmds.iter().any(|cmd| cmd.contains("name Threads")));
+}
+
+#[test]
+fn reads_resource_limits() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    let text = "[profiles.p]\nengine = \"e\"\n\
+                [profiles.p.limits]\nmemory = 512\ncpus = [0, 2]\nwall_clock = 60\n\
+                sandbox = { read = [\"nets\"], write = [\"/tmp\"] }\n";
+    std::fs::write(&path, text).unwrap();
+    let limits = Config::load(&path)
+        .unwrap()
+        .profile(Some("p"))
+        .unwrap()
+        .limits;
+    assert_eq!(limits.memory, Some(512));
+    assert_eq!(limits.cpus, [0, 2]);
+    assert_eq!(limits.wall_clock, Some(60));
+    let sandbox = limits.sandbox.unwrap();
+    assert_eq!(sandbox.read, [dir.path().join("nets")]);
+    assert_eq!(sandbox.write, [PathBuf::from("/tmp")]);
+    assert!(!sandbox.network);
+
+    assert!(Config::from_toml("[profiles.p.limits]\nmemroy = 512\n").is_err());
+}
+
+#[test]
+fn looks_up_bare_engine_commands_in_the_path() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("config.toml");
+    let text = "[profiles.bare]\nengine = \"stockfish\"\n\
+                [profiles.relative]\nengine = \"engines/stockfish\"\n";
+    std::fs::write(&path, text).unwrap();
+    let config = Config::load(&path).unwrap();
+    let bare = config.profile(Some("bare")).unwrap();
+    assert_eq!(bare.engine, Some(PathBuf::from("stockfish")));
+    let relative = config.profile(Some("relative")).unwrap();
+    assert_eq!(
+        relative.engine,
+        Some(dir.path().join("engines").join("stockfish"))
+    );
+}
+
+#[test]
+fn saves_the_profiles() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("golemate").join("config.toml");
+    let mut config = Config::from_toml(CONFIG).unwrap();
+    let mut edited = config.profile(Some("local")).unwrap();
+    edited.depth = Some(12);
+    edited.variant = Some(Variant::ThreeCheck);
+    config.profiles.insert("edited".to_owned(), edited);
+    config.save(&path).unwrap();
+
+    let saved = Config::load(&path).unwrap();
+    assert_eq!(saved.default_profile.as_deref(), Some("local"));
+    assert_eq!(saved.profiles.len(), 3);
+    let edited = saved.profile(Some("edited")).unwrap();
+    assert_eq!(edited.engine, Some(PathBuf::from("/usr/bin/stockfish")));
+    assert_eq!(edited.depth, Some(12));
+    assert_eq!(edited.variant(), Variant::ThreeCheck);
+    assert_eq!(edited.options.len(), 3);
+    let golem = saved.profile(Some("golem")).unwrap();
+    assert_eq!(golem.replicas, Some(2));
+    assert!(golem.golem.mainnet);
+}
+
+#[test]
+fn reads_the_gwasm_keys_in_every_build() {
+    use golemate::backends::KeepWorkspace;
+
+    let config = Config::from_toml(
+        "[profiles.golem]\nwasm = \"e.wasm\"\njs = \"e.js\"\nkeep_workspace = \"on-failure\"\n",
+    )
+    .unwrap();
+    let profile = config.profile(Some("golem")).unwrap();
+    assert_eq!(profile.keep_workspace, Some(KeepWorkspace::OnFailure));
+}
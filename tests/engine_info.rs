@@ -0,0 +1,76 @@
+use golemate::engine_info::{EngineInfo, EngineOption};
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(str::to_owned).collect()
+}
+
+const STOCKFISH: &str = "Stockfish 11 64 BMI2 by T. Romstad, M. Costalba, J. Kiiski, G. Linscott
+id name Stockfish 11 64 BMI2
+id author T. Romstad, M. Costalba, J. Kiiski, G. Linscott
+
+option name Debug Log File type string default
+option name Threads type spin default 1 min 1 max 512
+option name Hash type spin default 16 min 1 max 131072
+option name Ponder type check default false
+option name MultiPV type spin default 1 min 1 max 500
+option name Analysis Contempt type combo default Both var Off var White var Black var Both
+option name Clear Hash type button
+option name UCI_Chess960 type check default false
+option name Syzygy Path type string default <empty>
+uciok";
+
+#[test]
+fn reads_the_handshake() {
+    let info = EngineInfo::parse(&lines(STOCKFISH)).unwrap();
+    assert_eq!(info.name.as_deref(), Some("Stockfish 11 64 BMI2"));
+    assert!(info.author.unwrap().starts_with("T. Romstad"));
+    assert_eq!(info.options.len(), 9);
+    assert!(info.capabilities.chess960);
+    assert!(info.capabilities.ponder);
+    assert!(!info.capabilities.wdl);
+    assert_eq!(info.capabilities.multipv, Some((1, 500)));
+    assert!(info.capabilities.variants.is_empty());
+
+    let debug = &info.options[0];
+    assert_eq!(debug.name, "Debug Log File");
+    assert_eq!(debug.default.as_deref(), Some(""));
+    let contempt = &info.options[5];
+    assert_eq!(contempt.vars, ["Off", "White", "Black", "Both"]);
+    assert_eq!(
+        contempt.describe(),
+        "Analysis Contempt (combo, default Both, Off/White/Black/Both)"
+    );
+    assert_eq!(info.options[6].default, None);
+}
+
+#[test]
+fn reads_option_names_with_keywords() {
+    let option =
+        EngineOption::parse("name Move min Time type spin default 0 min 0 max 5000").unwrap();
+    assert_eq!(option.name, "Move min Time");
+    assert_eq!((option.min, option.max), (Some(0), Some(5000)));
+    assert!(EngineOption::parse("name Hash type spin min x").is_err());
+    assert!(EngineOption::parse("type spin").is_err());
+}
+
+#[test]
+fn lists_the_variants() {
+    let output = lines(
+        "id name Fairy-Stockfish\n\
+         option name UCI_Variant type combo default chess var chess var atomic var crazyhouse\n\
+         uciok",
+    );
+    let info = EngineInfo::parse(&output).unwrap();
+    assert_eq!(
+        info.capabilities.variants,
+        ["chess", "atomic", "crazyhouse"]
+    );
+    assert!(info
+        .describe()
+        .contains("Variants: chess, atomic, crazyhouse"));
+}
+
+#[test]
+fn requires_uciok() {
+    assert!(EngineInfo::parse(&lines("id name Broken")).is_err());
+}
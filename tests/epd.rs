@@ -0,0 +1,62 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::backends::NativeUci;
+use golemate::epd::{run_suite, EpdRecord};
+use shakmaty::Square;
+use tempfile::TempDir;
+
+const SUITE: &str = r#"
+# Both positions allow 1. e4
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d4; id "open.001";
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - am e4; id "open.002"; c0 "1. e4 isn't bad";
+"#;
+
+#[test]
+fn parses_the_opcodes() {
+    let records = EpdRecord::parse_suite(SUITE).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].id.as_deref(), Some("open.001"));
+    assert_eq!(records[0].best_moves.len(), 2);
+    assert_eq!(records[0].best_moves[0].to(), Square::E4);
+    assert_eq!(records[1].avoid_moves.len(), 1);
+    assert_eq!(records[1].describe_expected(), "am e4");
+
+    let mate = EpdRecord::parse("6k1/5ppp/8/8/8/8/8/R5K1 w - - dm 1; bm Ra8#;").unwrap();
+    assert_eq!(mate.direct_mate, Some(1));
+    assert_eq!(mate.best_moves[0].to(), Square::A8);
+
+    // Any whitespace separates the fields
+    let spaced =
+        EpdRecord::parse("6k1/5ppp/8/8/8/8/8/R5K1\tw  -   -\tbm Ra8#; id \"a  b\";").unwrap();
+    assert_eq!(spaced.best_moves[0].to(), Square::A8);
+    assert_eq!(spaced.id.as_deref(), Some("a  b"));
+}
+
+#[test]
+fn rejects_invalid_records() {
+    assert!(EpdRecord::parse("8/8/8 w - -").is_err());
+    assert!(
+        EpdRecord::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e5;").is_err()
+    );
+    assert!(
+        EpdRecord::parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4").is_err()
+    );
+}
+
+#[test]
+fn scores_the_suite() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 5 score cp 30 pv e2e4"], "bestmove e2e4");
+    let backend = NativeUci::new(fake_engine(dir.path(), &script));
+    let records = EpdRecord::parse_suite(SUITE).unwrap();
+
+    let report = run_suite(&backend, &records, 5);
+    assert_eq!(report.total, 2);
+    assert_eq!(report.solved, 1);
+    assert!(report.positions[0].solved);
+    assert_eq!(report.positions[1].found.as_deref(), Some("e4"));
+    assert!(report.describe().ends_with("Score: 1/2 at depth 5"));
+}
@@ -0,0 +1,86 @@
+#[cfg(all(feature = "native", unix))]
+mod common;
+
+use golemate::game::{Game, GameResult};
+use shakmaty::fen::Fen;
+use shakmaty::uci::Uci;
+
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn play(game: &mut Game, moves: &[&str]) {
+    for m in moves {
+        let uci: Uci = m.parse().unwrap();
+        let m = uci.to_move(game.position()).unwrap();
+        game.play(&m).unwrap();
+    }
+}
+
+#[test]
+fn detects_checkmate() {
+    let mut game = Game::new(INITIAL_FEN.parse().unwrap()).unwrap();
+    play(&mut game, &["f2f3", "e7e5", "g2g4"]);
+    assert_eq!(game.result(), None);
+    play(&mut game, &["d8h4"]);
+    assert_eq!(game.result(), Some((GameResult::BlackWins, "checkmate")));
+    assert_eq!(game.movetext(), "1. f3 e5 2. g4 Qh4#");
+}
+
+#[test]
+fn detects_threefold_repetition() {
+    let mut game = Game::new(INITIAL_FEN.parse().unwrap()).unwrap();
+    let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    play(&mut game, &shuffle);
+    assert_eq!(game.result(), None);
+    play(&mut game, &shuffle);
+    assert_eq!(
+        game.result(),
+        Some((GameResult::Draw, "threefold repetition"))
+    );
+}
+
+#[test]
+fn numbers_the_moves_from_the_start_position() {
+    let fen: Fen = "k7/8/1K6/8/8/8/8/7Q b - - 0 40".parse().unwrap();
+    let mut game = Game::new(fen).unwrap();
+    play(&mut game, &["a8b8", "h1h7"]);
+    assert_eq!(game.movetext(), "40... Kb8 41. Qh7");
+    let illegal = "h7h1".parse::<Uci>().unwrap();
+    assert!(illegal.to_move(game.position()).is_err());
+}
+
+#[cfg(all(feature = "native", unix))]
+#[test]
+fn plays_a_match() {
+    use common::{engine_script, fake_engine};
+    use golemate::backends::NativeUci;
+    use golemate::game::{play_match, Player};
+    use golemate::variant::Variant;
+    use tempfile::TempDir;
+
+    let dir = TempDir::new().unwrap();
+    let engine = fake_engine(dir.path(), &engine_script(&[], "bestmove h1h8"));
+    let backend = NativeUci::new(engine);
+    let player = |name| Player {
+        name,
+        backend: &backend,
+        depth: 1,
+        pondering: None,
+    };
+    let opening: Fen = "k7/8/1K6/8/8/8/8/7Q w - - 0 1".parse().unwrap();
+    let report = play_match(
+        player("first"),
+        player("second"),
+        Variant::Standard,
+        &[opening],
+        10,
+    )
+    .unwrap();
+    assert_eq!(report.games.len(), 2);
+    assert_eq!(report.games[0].white, "first");
+    assert_eq!(report.games[1].white, "second");
+    assert!(report
+        .games
+        .iter()
+        .all(|game| game.result == GameResult::WhiteWins && game.moves == "1. Qh8#"));
+    assert_eq!(report.score, 1.0);
+}
@@ -0,0 +1,281 @@
+#![cfg(all(feature = "gwasm", unix))]
+
+mod common;
+
+use common::mock_golem::MockGolem;
+use common::write_engine_script;
+use golemate::analysis::interpret_uci;
+use golemate::backends::{Cancellation, GWasmUci, GolemCompute, KeepWorkspace};
+use golemate::backends::{ProgressEvent, ProgressObserver, TaskCost, UciBackend, Workspace};
+use gwasm_api::task::{ComputedTask, Task};
+use shakmaty::{fen::Fen, Square};
+use std::fs;
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+const ENGINE: &str = r#"cat > /dev/null
+echo "id name MockEngine"
+echo "uciok"
+echo "info depth 7 score cp 31 pv f1b5 a7a6"
+echo "bestmove f1b5"
+"#;
+
+fn backend(dir: &Path, golem: impl GolemCompute + 'static) -> GWasmUci {
+    let wasm = dir.join("engine.wasm");
+    let js = dir.join("engine.js");
+    fs::write(&wasm, b"\0asm").unwrap();
+    fs::write(&js, b"// js").unwrap();
+    GWasmUci::with_golem(&wasm, &js, Some(dir.join("workspaces")), golem).unwrap()
+}
+
+fn run(backend: &GWasmUci) -> anyhow::Result<Vec<String>> {
+    backend.execute_uci(backend.generate_uci(FEN, 7))
+}
+
+#[test]
+fn computes_the_analysis() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine));
+
+    let output = run(&backend).unwrap();
+    assert_eq!(output[0], "id name MockEngine");
+
+    let fen: Fen = FEN.parse().unwrap();
+    let result = interpret_uci(fen, output).unwrap();
+    assert_eq!(result.depth, 7);
+    assert_eq!(result.best_move.to(), Square::B5);
+}
+
+#[test]
+fn sends_the_uci_commands_to_the_engine() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", "cat\n");
+    let backend = backend(dir.path(), MockGolem::new(engine));
+
+    let output = run(&backend).unwrap();
+    assert_eq!(output, backend.generate_uci(FEN, 7));
+}
+
+#[test]
+fn reports_task_failure() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine).fail());
+
+    let err = run(&backend).unwrap_err();
+    assert!(format!("{:?}", err).contains("task failed"));
+}
+
+#[test]
+fn reports_engine_failure() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", "exit 3\n");
+    let backend = backend(dir.path(), MockGolem::new(engine));
+
+    let err = run(&backend).unwrap_err();
+    assert!(format!("{:?}", err).contains("exited with"));
+}
+
+#[test]
+fn reports_timeout() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", "sleep 10\n");
+    let golem = MockGolem::new(engine).timeout(Duration::from_millis(200));
+    let backend = backend(dir.path(), golem);
+
+    let err = run(&backend).unwrap_err();
+    assert!(format!("{:?}", err).contains("timed out"));
+}
+
+#[test]
+fn rejects_malformed_output() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine).corrupt_output());
+
+    let err = run(&backend).unwrap_err();
+    assert!(format!("{:?}", err).contains("reading the subtask output"));
+}
+
+fn workspaces(dir: &Path) -> usize {
+    fs::read_dir(dir.join("workspaces")).unwrap().count()
+}
+
+#[test]
+fn runs_repeatedly_in_fresh_workspaces() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine));
+
+    run(&backend).unwrap();
+    run(&backend).unwrap();
+    assert_eq!(workspaces(dir.path()), 0);
+}
+
+#[test]
+fn keeps_the_workspace_on_failure() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", "exit 1\n");
+    let backend =
+        backend(dir.path(), MockGolem::new(engine)).keep_workspace(KeepWorkspace::OnFailure);
+
+    run(&backend).unwrap_err();
+    run(&backend).unwrap_err();
+    assert_eq!(workspaces(dir.path()), 2);
+}
+
+#[test]
+fn keeps_the_workspace_on_request() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine)).keep_workspace(KeepWorkspace::Always);
+
+    run(&backend).unwrap();
+    assert_eq!(workspaces(dir.path()), 1);
+}
+
+/// Goes on computing a cancelled task until it's released, as a Golem
+/// client does, and then tells whether its input was still there.
+struct DetachedGolem {
+    release: Mutex<Option<mpsc::Receiver<()>>>,
+    finished: Mutex<mpsc::Sender<bool>>,
+}
+
+impl GolemCompute for DetachedGolem {
+    fn compute(
+        &self,
+        task: Task,
+        workspace: Arc<Workspace>,
+        _progress: Arc<dyn ProgressObserver>,
+        cancel: &Cancellation,
+    ) -> anyhow::Result<ComputedTask> {
+        let release = self.release.lock().unwrap().take().unwrap();
+        let finished = self.finished.lock().unwrap().clone();
+        let input = task.options().input_dir_path().to_owned();
+        thread::spawn(move || {
+            let _workspace = workspace;
+            release.recv().unwrap();
+            finished.send(input.exists()).unwrap();
+        });
+        while !cancel.is_cancelled() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        anyhow::bail!("the task was cancelled")
+    }
+}
+
+#[test]
+fn keeps_the_workspace_of_cancelled_tasks_until_computed() {
+    let dir = TempDir::new().unwrap();
+    let (release, released) = mpsc::channel();
+    let (finished, computed) = mpsc::channel();
+    let golem = DetachedGolem {
+        release: Mutex::new(Some(released)),
+        finished: Mutex::new(finished),
+    };
+    let backend = backend(dir.path(), golem);
+
+    let cancel = Cancellation::new();
+    cancel.cancel();
+    backend
+        .execute_cancellable(backend.generate_uci(FEN, 7), &cancel)
+        .unwrap_err();
+    assert_eq!(workspaces(dir.path()), 1);
+
+    release.send(()).unwrap();
+    assert!(computed.recv().unwrap());
+    for _ in 0..100 {
+        if workspaces(dir.path()) == 0 {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("the workspace was left behind");
+}
+
+#[test]
+fn reports_progress_to_the_observer() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let mut backend = backend(dir.path(), MockGolem::new(engine));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    backend.set_progress_observer(Arc::new(move |event: ProgressEvent| {
+        sink.lock().unwrap().push(event)
+    }));
+
+    run(&backend).unwrap();
+    let events = events.lock().unwrap();
+    assert_eq!(events.first(), Some(&ProgressEvent::TaskSubmitted));
+    assert!(events.contains(&ProgressEvent::Progress(1.0)));
+    assert_eq!(events.last(), Some(&ProgressEvent::TaskFinished));
+}
+
+#[test]
+fn refuses_tasks_over_the_budget() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine))
+        .max_price(2.0)
+        .subtask_timeout(Duration::from_secs(30 * 60))
+        .replicas(2)
+        .budget(Some(1.5));
+
+    assert_eq!(backend.max_cost(), Some(2.0));
+    let err = run(&backend).unwrap_err();
+    assert!(format!("{:?}", err).contains("exceeds the budget"));
+}
+
+#[test]
+fn reports_the_maximal_cost() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let mut backend = backend(dir.path(), MockGolem::new(engine))
+        .max_price(0.25)
+        .subtask_timeout(Duration::from_secs(30 * 60))
+        .budget(Some(0.2));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    backend.set_progress_observer(Arc::new(move |event: ProgressEvent| {
+        sink.lock().unwrap().push(event)
+    }));
+
+    run(&backend).unwrap();
+    let expected = ProgressEvent::Cost(TaskCost { max: 0.125 });
+    assert!(events.lock().unwrap().contains(&expected));
+}
+
+#[test]
+fn uses_the_result_of_any_replica() {
+    let dir = TempDir::new().unwrap();
+    let engine = write_engine_script(dir.path(), "engine", ENGINE);
+    let backend = backend(dir.path(), MockGolem::new(engine)).replicas(3);
+
+    let output = run(&backend).unwrap();
+    assert!(output.iter().any(|l| l == "bestmove f1b5"));
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn stops_dropped_analyses() {
+    use golemate::backends::{AsyncUciBackend, BlockingUci};
+
+    let dir = TempDir::new().unwrap();
+    let finished = dir.path().join("finished");
+    let script = format!("sleep 1\ntouch {}\n", finished.display());
+    let engine = write_engine_script(dir.path(), "engine", &script);
+    let backend = BlockingUci::new(backend(dir.path(), MockGolem::new(engine)));
+
+    let analysis = backend.execute_async(backend.generate_uci(FEN, 7));
+    assert!(tokio::time::timeout(Duration::from_millis(200), analysis)
+        .await
+        .is_err());
+    std::thread::sleep(Duration::from_millis(1500));
+    assert!(!finished.exists());
+}
@@ -0,0 +1,205 @@
+#![cfg(all(feature = "history", feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::analysis::{Score, SearchInfo};
+use golemate::backends::{NativeUci, UciBackend, UciInput, UciOption, UciOutput};
+use golemate::history::{AnalysisHistory, HistoryQuery, RecordedUci};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+const OTHER_FEN: &str = "8/8/8/8/8/8/8/K1k5 w - - 0 1";
+
+fn recorded(dir: &Path, script: &str, history: &AnalysisHistory) -> RecordedUci<NativeUci> {
+    RecordedUci::new(NativeUci::new(fake_engine(dir, script)), history.clone())
+}
+
+fn open_history(dir: &Path) -> AnalysisHistory {
+    AnalysisHistory::open(&dir.join("history").join("history.sqlite")).unwrap()
+}
+
+#[test]
+fn records_the_analysis() {
+    let dir = TempDir::new().unwrap();
+    let history = open_history(dir.path());
+    let script = engine_script(
+        &[
+            "info depth 5 score cp 20 nodes 100 pv e1g1",
+            "info string thinking",
+            "info depth 6 seldepth 8 score mate 3 nodes 200 nps 1000 time 200 pv f1b5 a7a6",
+        ],
+        "bestmove f1b5 ponder a7a6",
+    );
+    let backend = recorded(dir.path(), &script, &history);
+    backend.execute_uci(backend.generate_uci(FEN, 6)).unwrap();
+
+    let records = history.query(&HistoryQuery::new()).unwrap();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.backend, "native");
+    assert_eq!(record.engine, backend.engine_id());
+    assert_eq!(record.limits, "depth 6");
+    assert!(record.options.iter().any(|o| o.contains("name Hash")));
+    assert_eq!(record.best_move.as_deref(), Some("f1b5"));
+    assert_eq!(record.ponder.as_deref(), Some("a7a6"));
+    assert_eq!(record.error, None);
+    assert_eq!(record.max_cost, None);
+    assert_eq!(record.info.len(), 2);
+    assert_eq!(record.info[1].score, Some(Score::Mate(3)));
+    assert_eq!(record.info[1].pv, vec!["f1b5", "a7a6"]);
+}
+
+#[test]
+fn queries_by_position_and_engine() {
+    let dir = TempDir::new().unwrap();
+    let history = open_history(dir.path());
+    let first_dir = dir.path().join("first");
+    let second_dir = dir.path().join("second");
+    std::fs::create_dir(&first_dir).unwrap();
+    std::fs::create_dir(&second_dir).unwrap();
+    let script = engine_script(&["info depth 1 score cp 0"], "bestmove a1a2");
+    let first = recorded(&first_dir, &script, &history);
+    let second = recorded(&second_dir, &script, &history);
+    first.execute_uci(first.generate_uci(FEN, 1)).unwrap();
+    first.execute_uci(first.generate_uci(OTHER_FEN, 1)).unwrap();
+    second.execute_uci(second.generate_uci(FEN, 1)).unwrap();
+
+    // The move counters don't matter
+    let same_position = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1";
+    let by_fen = history
+        .query(&HistoryQuery::new().fen(same_position))
+        .unwrap();
+    assert_eq!(by_fen.len(), 2);
+    // The most recent first
+    assert!(by_fen[0].id > by_fen[1].id);
+
+    let query = HistoryQuery::new()
+        .fen(FEN)
+        .engine(&first_dir.display().to_string());
+    let records = history.query(&query).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].engine, first.engine_id());
+
+    let latest = history.query(&HistoryQuery::new().limit(1)).unwrap();
+    assert_eq!(latest.len(), 1);
+    assert_eq!(latest[0].engine, second.engine_id());
+}
+
+#[test]
+fn records_failures() {
+    let dir = TempDir::new().unwrap();
+    let history = open_history(dir.path());
+    let backend = recorded(dir.path(), "on go\n    crash\n", &history);
+    assert!(backend.execute_uci(backend.generate_uci(FEN, 6)).is_err());
+
+    let records = history.query(&HistoryQuery::new()).unwrap();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].error.is_some());
+    assert_eq!(records[0].best_move, None);
+}
+
+#[test]
+fn skips_sessions_without_a_search() {
+    let dir = TempDir::new().unwrap();
+    let history = open_history(dir.path());
+    let backend = recorded(dir.path(), &engine_script(&[], "bestmove a1a2"), &history);
+    backend
+        .execute_uci(vec!["uci".to_owned(), "quit".to_owned()])
+        .unwrap();
+    assert!(history.query(&HistoryQuery::new()).unwrap().is_empty());
+}
+
+#[test]
+fn records_every_session_of_a_batch() {
+    let dir = TempDir::new().unwrap();
+    let history = open_history(dir.path());
+    let backend = recorded(dir.path(), &engine_script(&[], "bestmove a1a2"), &history);
+    let inputs = vec![
+        backend.generate_uci(FEN, 2),
+        backend.generate_uci(OTHER_FEN, 2),
+    ];
+    let outputs = backend.execute_batch(inputs);
+    assert!(outputs.iter().all(Result::is_ok));
+    assert_eq!(history.query(&HistoryQuery::new()).unwrap().len(), 2);
+}
+
+#[test]
+fn keeps_the_history_between_runs() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&[], "bestmove a1a2");
+    {
+        let history = open_history(dir.path());
+        let backend = recorded(dir.path(), &script, &history);
+        backend.execute_uci(backend.generate_uci(FEN, 2)).unwrap();
+    }
+    let history = open_history(dir.path());
+    assert_eq!(history.query(&HistoryQuery::new()).unwrap().len(), 1);
+}
+
+#[test]
+fn parses_info_lines() {
+    let info = SearchInfo::parse(
+        "info depth 12 seldepth 18 multipv 2 score cp -35 upperbound nodes 5000 nps 250000 time 20 pv e2e4 e7e5",
+    )
+    .unwrap();
+    assert_eq!(info.depth, Some(12));
+    assert_eq!(info.seldepth, Some(18));
+    assert_eq!(info.multipv, Some(2));
+    assert_eq!(info.score, Some(Score::Cp(-35)));
+    assert_eq!(info.nodes, Some(5000));
+    assert_eq!(info.nps, Some(250_000));
+    assert_eq!(info.time, Some(20));
+    assert_eq!(info.pv, vec!["e2e4", "e7e5"]);
+
+    assert_eq!(SearchInfo::parse("info string depth 3"), None);
+    assert_eq!(SearchInfo::parse("bestmove e2e4"), None);
+}
+
+/// A native engine billed like a Golem task
+struct PaidUci(NativeUci);
+
+impl UciBackend for PaidUci {
+    fn execute_uci(&self, uci: UciInput) -> anyhow::Result<UciOutput> {
+        self.0.execute_uci(uci)
+    }
+
+    fn get_uci_opts(&self) -> Vec<UciOption> {
+        self.0.get_uci_opts()
+    }
+
+    fn engine_id(&self) -> String {
+        self.0.engine_id()
+    }
+
+    fn max_cost(&self) -> Option<f64> {
+        Some(0.25)
+    }
+}
+
+#[test]
+fn records_the_maximal_cost_of_concurrent_sessions() {
+    let dir = TempDir::new().unwrap();
+    let history = open_history(dir.path());
+    let engine = NativeUci::new(fake_engine(
+        dir.path(),
+        &engine_script(&[], "bestmove a1a2"),
+    ));
+    let backend = Arc::new(RecordedUci::new(PaidUci(engine), history.clone()));
+    let analyses: Vec<_> = (0..4)
+        .map(|_| {
+            let backend = backend.clone();
+            thread::spawn(move || backend.execute_uci(backend.generate_uci(FEN, 2)))
+        })
+        .collect();
+    for analysis in analyses {
+        analysis.join().unwrap().unwrap();
+    }
+
+    let records = history.query(&HistoryQuery::new()).unwrap();
+    assert_eq!(records.len(), 4);
+    assert!(records.iter().all(|record| record.max_cost == Some(0.25)));
+}
@@ -0,0 +1,173 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine, write_engine_script};
+use golemate::backends::{NativeUci, ResourceLimits, Sandbox, UciBackend};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// An engine reporting the output of `command` as an info string
+fn reporting_engine(dir: &Path, command: &str) -> std::path::PathBuf {
+    let body = format!(
+        "while read line; do\n\
+         case $line in\n\
+         go*) echo \"info string $({})\"; echo bestmove e2e4;;\n\
+         quit) exit;;\n\
+         esac\n\
+         done\n",
+        command
+    );
+    write_engine_script(dir, "engine", &body)
+}
+
+fn run(engine: &Path, limits: ResourceLimits) -> anyhow::Result<Vec<String>> {
+    let backend = NativeUci::new(engine.to_owned()).limits(limits);
+    backend.execute_uci(backend.generate_uci(FEN, 1))
+}
+
+fn info_string(output: &[String]) -> &str {
+    output
+        .iter()
+        .find_map(|line| line.strip_prefix("info string "))
+        .expect("the engine should report")
+}
+
+/// Whether the sandbox failed because of an old kernel
+fn landlock_unsupported(result: &anyhow::Result<Vec<String>>) -> bool {
+    match result {
+        Err(e) => format!("{:#}", e).contains("doesn't support Landlock"),
+        Ok(_) => false,
+    }
+}
+
+#[test]
+fn kills_engines_over_the_wall_clock_limit() {
+    let dir = TempDir::new().unwrap();
+    let engine = fake_engine(dir.path(), "on go\n    hang\n");
+    let limits = ResourceLimits {
+        wall_clock: Some(1),
+        ..ResourceLimits::default()
+    };
+
+    let start = Instant::now();
+    let err = run(&engine, limits).unwrap_err();
+    assert!(err.to_string().contains("wall-clock limit"), "{:#}", err);
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn finishes_within_the_wall_clock_limit() {
+    let dir = TempDir::new().unwrap();
+    let engine = fake_engine(dir.path(), &engine_script(&[], "bestmove e2e4"));
+    let limits = ResourceLimits {
+        wall_clock: Some(10),
+        ..ResourceLimits::default()
+    };
+    let output = run(&engine, limits).unwrap();
+    assert!(output.contains(&"bestmove e2e4".to_owned()));
+}
+
+#[test]
+fn threads_follow_the_cpu_count() {
+    let backend = NativeUci::new("engine".into()).limits(ResourceLimits {
+        cpus: vec![0, 1],
+        ..ResourceLimits::default()
+    });
+    let uci = backend.generate_uci(FEN, 1);
+    assert!(uci.contains(&"setoption name Threads value 2".to_owned()));
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+
+    #[test]
+    fn limits_the_memory() {
+        let dir = TempDir::new().unwrap();
+        let engine = reporting_engine(dir.path(), "ulimit -v");
+        let limits = ResourceLimits {
+            memory: Some(256),
+            ..ResourceLimits::default()
+        };
+        let output = run(&engine, limits).unwrap();
+        assert_eq!(info_string(&output), "262144");
+    }
+
+    #[test]
+    fn rejects_too_large_memory_limits() {
+        let dir = TempDir::new().unwrap();
+        let engine = reporting_engine(dir.path(), "ulimit -v");
+        let limits = ResourceLimits {
+            memory: Some(u64::MAX / 1024),
+            ..ResourceLimits::default()
+        };
+        let err = run(&engine, limits).unwrap_err();
+        assert!(format!("{:#}", err).contains("too large"), "{:#}", err);
+    }
+
+    #[test]
+    fn pins_the_cpus() {
+        let dir = TempDir::new().unwrap();
+        let engine = reporting_engine(dir.path(), "grep Cpus_allowed_list /proc/self/status");
+        let limits = ResourceLimits {
+            cpus: vec![0],
+            ..ResourceLimits::default()
+        };
+        let output = run(&engine, limits).unwrap();
+        assert_eq!(info_string(&output), "Cpus_allowed_list:\t0");
+    }
+
+    #[test]
+    fn sandboxes_the_filesystem() {
+        let dir = TempDir::new().unwrap();
+        let secrets = TempDir::new().unwrap();
+        let secret = secrets.path().join("secret");
+        std::fs::write(&secret, "secret").unwrap();
+        let engine = reporting_engine(
+            dir.path(),
+            &format!("cat {} || echo denied", secret.display()),
+        );
+
+        let sandboxed = ResourceLimits {
+            sandbox: Some(Sandbox::default()),
+            ..ResourceLimits::default()
+        };
+        let result = run(&engine, sandboxed);
+        if landlock_unsupported(&result) {
+            return;
+        }
+        assert_eq!(info_string(&result.unwrap()), "denied");
+
+        let allowed = ResourceLimits {
+            sandbox: Some(Sandbox {
+                read: vec![secrets.path().to_owned()],
+                ..Sandbox::default()
+            }),
+            ..ResourceLimits::default()
+        };
+        assert_eq!(info_string(&run(&engine, allowed).unwrap()), "secret");
+    }
+
+    #[test]
+    fn rejects_missing_sandbox_paths() {
+        let dir = TempDir::new().unwrap();
+        let engine = reporting_engine(dir.path(), "true");
+        let limits = ResourceLimits {
+            sandbox: Some(Sandbox {
+                write: vec![dir.path().join("missing")],
+                ..Sandbox::default()
+            }),
+            ..ResourceLimits::default()
+        };
+        let result = run(&engine, limits);
+        if landlock_unsupported(&result) {
+            return;
+        }
+        let err = result.unwrap_err();
+        assert!(format!("{:#}", err).contains("missing"), "{:#}", err);
+    }
+}
@@ -0,0 +1,156 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::write_engine_script;
+use golemate::analysis::interpret_uci;
+use golemate::backends::{LiveEngine, LiveEvent, ResourceLimits, SearchState};
+use golemate::game::{Game, PonderingEngine};
+use golemate::variant::Variant;
+use shakmaty::uci::Uci;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An engine logging the commands it receives. The infinite searches and
+/// the pondering only end with `stop` or `ponderhit`.
+fn engine(dir: &Path) -> PathBuf {
+    write_engine_script(
+        dir,
+        "engine",
+        "log=\"$0.log\"\n\
+         reply() {\n\
+         case $pos in\n\
+         *\" rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR \"*) best=\"e2e4 ponder e7e5\";;\n\
+         *\" rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR \"*|*\" moves e7e5 \"*) best=\"g1f3 ponder b8c6\";;\n\
+         *) best=f1c4;;\n\
+         esac\n\
+         echo \"info depth 1 score cp 20 nodes 100 nps 1000 pv ${best%% *}\"\n\
+         }\n\
+         while read line; do\n\
+         echo \"$line\" >> \"$log\"\n\
+         case $line in\n\
+         uci) echo id name Shell; echo uciok;;\n\
+         isready) echo readyok;;\n\
+         position*) pos=\"$line \";;\n\
+         \"go infinite\"|\"go ponder\"*) reply; waiting=1;;\n\
+         go*) reply; echo \"bestmove $best\";;\n\
+         stop|ponderhit) if [ -n \"$waiting\" ]; then waiting=; echo \"bestmove $best\"; fi;;\n\
+         quit) exit;;\n\
+         esac\n\
+         done\n",
+    )
+}
+
+fn start(dir: &Path) -> LiveEngine {
+    LiveEngine::start(&engine(dir), &[], &ResourceLimits::default()).unwrap()
+}
+
+fn commands(dir: &Path) -> Vec<String> {
+    let log = fs::read_to_string(dir.join("engine.log")).unwrap_or_default();
+    log.lines().map(str::to_owned).collect()
+}
+
+fn play(game: &mut Game, moves: &[&str]) {
+    for m in moves {
+        let uci: Uci = m.parse().unwrap();
+        let m = uci.to_move(game.position()).unwrap();
+        game.play(&m).unwrap();
+    }
+}
+
+#[test]
+fn analyses_until_stopped() {
+    let dir = TempDir::new().unwrap();
+    let mut engine = start(dir.path());
+    assert_eq!(engine.state(), SearchState::Idle);
+    engine.go_infinite(&format!("fen {}", INITIAL_FEN)).unwrap();
+    assert_eq!(engine.state(), SearchState::Searching);
+
+    match engine.next_event(TIMEOUT).unwrap() {
+        Some(LiveEvent::Info(info)) => assert_eq!(info.pv, vec!["e2e4"]),
+        event => panic!("unexpected event {:?}", event),
+    }
+    // Nothing more until the search is stopped
+    assert_eq!(engine.next_event(Duration::from_millis(100)).unwrap(), None);
+
+    let output = engine.stop().unwrap();
+    assert_eq!(engine.state(), SearchState::Idle);
+    assert_eq!(output.len(), 2);
+    let result = interpret_uci(INITIAL_FEN.parse().unwrap(), output).unwrap();
+    assert_eq!(Uci::from_standard(&result.best_move).to_string(), "e2e4");
+    let ponder = result.ponder.expect("the expected reply");
+    assert_eq!(Uci::from_standard(&ponder).to_string(), "e7e5");
+    assert!(commands(dir.path()).contains(&"go infinite".to_owned()));
+}
+
+#[test]
+fn ponders_until_the_ponderhit() {
+    let dir = TempDir::new().unwrap();
+    let mut engine = start(dir.path());
+    engine.go_ponder("startpos", "depth 5").unwrap();
+    assert_eq!(engine.state(), SearchState::Pondering);
+    assert!(engine.wait().is_err());
+
+    engine.ponderhit().unwrap();
+    assert_eq!(engine.state(), SearchState::Searching);
+    let output = engine.wait().unwrap();
+    assert_eq!(output.last().unwrap(), "bestmove f1c4");
+    assert!(engine.ponderhit().is_err());
+    assert!(commands(dir.path()).contains(&"go ponder depth 5".to_owned()));
+}
+
+#[test]
+fn limits_the_searches_to_the_wall_clock() {
+    let dir = TempDir::new().unwrap();
+    let limits = ResourceLimits {
+        wall_clock: Some(1),
+        ..ResourceLimits::default()
+    };
+    let mut engine = LiveEngine::start(&engine(dir.path()), &[], &limits).unwrap();
+    // The search never ends by itself
+    let error = engine.search("startpos", "infinite").unwrap_err();
+    assert!(error.to_string().contains("wall-clock limit"));
+}
+
+#[test]
+fn follows_the_expected_reply() {
+    let dir = TempDir::new().unwrap();
+    let mut player = PonderingEngine::new(start(dir.path()), Variant::Standard, 5);
+    let mut game = Game::new(INITIAL_FEN.parse().unwrap()).unwrap();
+
+    let analysis = player.engine_move(&game).unwrap();
+    game.play(&analysis.best_move).unwrap();
+    player.ponder(&game, &analysis).unwrap();
+    play(&mut game, &["e7e5"]);
+    let analysis = player.engine_move(&game).unwrap();
+    assert_eq!(Uci::from_standard(&analysis.best_move).to_string(), "g1f3");
+
+    let commands = commands(dir.path());
+    let after_e4 = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+    assert!(commands.contains(&format!("position fen {} moves e7e5", after_e4)));
+    assert!(commands.contains(&"ponderhit".to_owned()));
+    assert!(!commands.contains(&"stop".to_owned()));
+}
+
+#[test]
+fn searches_again_after_another_reply() {
+    let dir = TempDir::new().unwrap();
+    let mut player = PonderingEngine::new(start(dir.path()), Variant::Standard, 5);
+    let mut game = Game::new(INITIAL_FEN.parse().unwrap()).unwrap();
+
+    let analysis = player.engine_move(&game).unwrap();
+    game.play(&analysis.best_move).unwrap();
+    player.ponder(&game, &analysis).unwrap();
+    play(&mut game, &["d7d6"]);
+    let analysis = player.engine_move(&game).unwrap();
+    assert_eq!(Uci::from_standard(&analysis.best_move).to_string(), "f1c4");
+
+    let commands = commands(dir.path());
+    assert!(commands.contains(&"stop".to_owned()));
+    assert!(!commands.contains(&"ponderhit".to_owned()));
+}
@@ -0,0 +1,143 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::analysis::{interpret_uci, Advantage, Score};
+use golemate::backends::{NativeUci, ProgressEvent, UciBackend};
+use shakmaty::{fen::Fen, Color, Square};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+fn run(script: &str) -> anyhow::Result<Vec<String>> {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(fake_engine(dir.path(), script));
+    backend.execute_uci(backend.generate_uci(FEN, 8))
+}
+
+fn analyse(script: &str) -> anyhow::Result<golemate::analysis::AnalysisResult> {
+    let fen: Fen = FEN.parse().unwrap();
+    interpret_uci(fen, run(script)?)
+}
+
+#[test]
+fn analyses_a_position() {
+    let script = engine_script(
+        &[
+            "info depth 1 score cp 12 pv e1g1",
+            "info depth 8 seldepth 12 nodes 1000 score cp 35 pv f1b5 a7a6 b5a4",
+        ],
+        "bestmove f1b5 ponder a7a6",
+    );
+    let result = analyse(&script).unwrap();
+    assert_eq!(result.depth, 8);
+    assert_eq!(result.best_move.to(), Square::B5);
+    assert_eq!(result.pv.len(), 3);
+    assert_eq!(result.advantage_side, Color::White);
+    assert!(matches!(result.advantage, Advantage::Centipawns(35)));
+}
+
+#[test]
+fn reads_negative_and_mate_scores() {
+    let script = engine_script(&["info depth 3 score mate -2 pv f3e5"], "bestmove f3e5");
+    let result = analyse(&script).unwrap();
+    assert_eq!(result.advantage_side, Color::Black);
+    assert!(matches!(result.advantage, Advantage::Mate(2)));
+}
+
+#[test]
+fn ignores_strings_and_lines_without_pv() {
+    let script = engine_script(
+        &[
+            "info string NNUE evaluation using nn.nnue enabled",
+            "info depth 4 score cp 0 lowerbound nodes 210",
+            "info nodes 300 nps 30000 hashfull 1",
+        ],
+        "bestmove b1c3",
+    );
+    let result = analyse(&script).unwrap();
+    assert_eq!(result.depth, 4);
+    assert!(matches!(result.advantage, Advantage::Equality));
+    assert!(result.pv.is_empty());
+}
+
+#[test]
+fn reports_the_search_as_it_goes() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(
+        &[
+            "info string NNUE evaluation using nn.nnue enabled",
+            "info depth 8 score cp 35 pv f1b5 a7a6",
+        ],
+        "bestmove f1b5",
+    );
+    let mut backend = NativeUci::new(fake_engine(dir.path(), &script));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    backend.set_progress_observer(Arc::new(move |event: ProgressEvent| {
+        sink.lock().unwrap().push(event)
+    }));
+
+    backend.execute_uci(backend.generate_uci(FEN, 8)).unwrap();
+    let events = events.lock().unwrap();
+    let infos: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::Info(info) => Some(info),
+            _ => None,
+        })
+        .collect();
+    // The strings carry no search information
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].score, Some(Score::Cp(35)));
+    assert_eq!(infos[0].pv, vec!["f1b5", "a7a6"]);
+    assert_eq!(events.last(), Some(&ProgressEvent::TaskFinished));
+}
+
+#[test]
+fn sends_the_position_to_the_engine() {
+    let script = format!(
+        "{}on position\n    send info string {}\n",
+        engine_script(&[], "bestmove f1c4"),
+        "got position"
+    );
+    let output = run(&script).unwrap();
+    assert!(output.iter().any(|l| l == "info string got position"));
+    assert!(output.iter().any(|l| l == "uciok"));
+}
+
+#[test]
+fn waits_for_a_slow_engine() {
+    let script = "on go\n    sleep 300\n    send bestmove f1c4\n";
+    let result = analyse(script).unwrap();
+    assert_eq!(result.best_move.to(), Square::C4);
+}
+
+#[test]
+fn reports_a_crash() {
+    assert!(run("on go\n    crash\n").is_err());
+}
+
+#[test]
+fn reports_a_failure_exit_code() {
+    assert!(run("on position\n    exit 2\n").is_err());
+}
+
+#[test]
+fn rejects_protocol_violations() {
+    let violations = [
+        engine_script(&[], "bestmove"),
+        engine_script(&[], "bestmove e2e5"),
+        engine_script(&[], "bestmove xyz"),
+        engine_script(&["info depth many"], "bestmove f1c4"),
+        engine_script(&["info depth 3 score cp"], "bestmove f1c4"),
+        engine_script(&["info depth 3 score wdl 20 pv f1c4"], "bestmove f1c4"),
+        engine_script(&["info depth 3 pv f1c4 z9z9"], "bestmove f1c4"),
+        engine_script(&["info depth 3 score cp 20 pv f1c4"], "readyok"),
+    ];
+    for script in violations.iter() {
+        assert!(analyse(script).is_err(), "accepted:\n{}", script);
+    }
+}
@@ -0,0 +1,58 @@
+use golemate::pgn::parse_pgn;
+use shakmaty::fen;
+
+const PGN: &str = r#"[Event "Casual \"blitz\""]
+[White "Alice"]
+[Black "Bob"]
+
+1. e4 {best by test (really)} e5 2.Nf3 (2. f4 exf4 (2...d5)) 2... Nc6 $1
+3. Bb5!? ; the Spanish
+a6 1-0
+
+[White "Carol"]
+1. d4 d5 *
+"#;
+
+#[test]
+fn reads_the_games() {
+    let games = parse_pgn(PGN).unwrap();
+    assert_eq!(games.len(), 2);
+    assert_eq!(games[0].tag("Event"), Some("Casual \"blitz\""));
+    assert_eq!(games[0].tag("White"), Some("Alice"));
+    assert_eq!(
+        games[0].moves,
+        vec!["e4", "e5", "Nf3", "Nc6", "Bb5!?", "a6"]
+    );
+    assert_eq!(games[0].result.as_deref(), Some("1-0"));
+    assert_eq!(games[1].tag("White"), Some("Carol"));
+    assert_eq!(games[1].moves, vec!["d4", "d5"]);
+}
+
+#[test]
+fn replays_the_game() {
+    let games = parse_pgn(PGN).unwrap();
+    let game = games[0].replay().unwrap();
+    assert_eq!(game.movetext(), "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6");
+
+    let (game, positions) = games[0].replay_positions().unwrap();
+    assert_eq!(positions.len(), game.moves().len() + 1);
+    assert_eq!(fen::fen(&positions[positions.len() - 1]), game.fen());
+}
+
+#[test]
+fn starts_from_the_fen_tag() {
+    let pgn = "[FEN \"8/8/8/8/8/8/8/K1k5 b - - 0 40\"]\n40... Kc2 41. Ka2 *";
+    let game = parse_pgn(pgn).unwrap()[0].replay().unwrap();
+    assert_eq!(game.movetext(), "40... Kc2 41. Ka2");
+}
+
+#[test]
+fn rejects_broken_games() {
+    assert!(parse_pgn("1. e4 (1. d4").is_err());
+    assert!(parse_pgn("1. e4 {unterminated").is_err());
+    assert!(parse_pgn("[White \"Alice\"").is_err());
+    let illegal = parse_pgn("1. e4 e5 2. Ke3 *").unwrap();
+    assert!(illegal[0].replay().is_err());
+    let variant = parse_pgn("[Variant \"Atomic\"]\n1. e4 *").unwrap();
+    assert!(variant[0].replay().is_err());
+}
@@ -0,0 +1,129 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::write_engine_script;
+use golemate::backends::{EnginePool, UciBackend, UciOption};
+use std::collections::HashSet;
+use std::path::Path;
+use tempfile::TempDir;
+
+const SLOW_FEN: &str = "8/8/8/8/8/8/8/K1k5 w - - 0 1";
+const CRASH_FEN: &str = "8/8/8/8/8/8/8/K2k4 w - - 0 1";
+
+/// An engine reporting its PID and the position it was given, sleeping on
+/// `SLOW_FEN` and exiting on `CRASH_FEN`
+fn engine(dir: &Path) -> std::path::PathBuf {
+    let body = format!(
+        "while read line; do\n\
+         case $line in\n\
+         uci) echo id name Shell; echo uciok;;\n\
+         isready) echo readyok;;\n\
+         \"position fen {slow}\") pos=$line; sleep 0.3;;\n\
+         \"position fen {crash}\") exit 1;;\n\
+         position*) pos=$line;;\n\
+         go*) echo \"info string $$ $pos\"; echo bestmove a1a2;;\n\
+         quit) exit;;\n\
+         esac\n\
+         done\n",
+        slow = SLOW_FEN,
+        crash = CRASH_FEN,
+    );
+    write_engine_script(dir, "engine", &body)
+}
+
+fn fens(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|idx| format!("8/8/8/8/8/8/{}/K1k5 w - - 0 1", idx % 8 + 1))
+        .collect()
+}
+
+/// The PID and the position reported by the engine
+fn report(output: &[String]) -> (String, String) {
+    let info = output
+        .iter()
+        .find_map(|line| line.strip_prefix("info string "))
+        .expect("the engine should report");
+    let mut parts = info.splitn(2, ' ');
+    let pid = parts.next().unwrap().to_owned();
+    let position = parts.next().unwrap_or("").to_owned();
+    (pid, position)
+}
+
+#[test]
+fn returns_the_results_in_order() {
+    let dir = TempDir::new().unwrap();
+    let pool = EnginePool::new(engine(dir.path()), 3);
+    let mut positions = fens(12);
+    positions[0] = SLOW_FEN.to_owned();
+    let cmds = positions
+        .iter()
+        .map(|fen| pool.generate_uci(fen, 5))
+        .collect();
+
+    let outputs = pool.execute_batch(cmds);
+    assert_eq!(outputs.len(), positions.len());
+    for (fen, output) in positions.iter().zip(outputs) {
+        let output = output.unwrap();
+        assert_eq!(report(&output).1, format!("position fen {}", fen));
+        assert!(output.contains(&"bestmove a1a2".to_owned()));
+    }
+}
+
+#[test]
+fn keeps_the_engines_running() {
+    let dir = TempDir::new().unwrap();
+    let pool = EnginePool::new(engine(dir.path()), 2);
+    let mut pids = HashSet::new();
+    for _ in 0..3 {
+        let cmds = fens(4)
+            .iter()
+            .map(|fen| pool.generate_uci(fen, 5))
+            .collect();
+        for output in pool.execute_batch(cmds) {
+            pids.insert(report(&output.unwrap()).0);
+        }
+    }
+    assert!(pids.len() <= 2, "{:?}", pids);
+}
+
+#[test]
+fn replays_the_handshake() {
+    let dir = TempDir::new().unwrap();
+    let pool = EnginePool::new(engine(dir.path()), 1);
+    let output = pool
+        .execute_uci(vec!["uci".to_owned(), "quit".to_owned()])
+        .unwrap();
+    assert_eq!(output, ["id name Shell", "uciok"]);
+}
+
+#[test]
+fn restarts_crashed_engines() {
+    let dir = TempDir::new().unwrap();
+    let pool = EnginePool::new(engine(dir.path()), 1);
+    let positions = [fens(1)[0].clone(), CRASH_FEN.to_owned(), fens(2)[1].clone()];
+    let cmds = positions
+        .iter()
+        .map(|fen| pool.generate_uci(fen, 5))
+        .collect();
+
+    let outputs = pool.execute_batch(cmds);
+    assert!(outputs[0].is_ok());
+    assert!(outputs[1].is_err());
+    let (_, position) = report(outputs[2].as_ref().unwrap());
+    assert_eq!(position, format!("position fen {}", positions[2]));
+}
+
+#[test]
+fn splits_the_cpus_between_the_engines() {
+    let cpus = num_cpus::get();
+    let pool = EnginePool::new("engine".into(), 2);
+    let cmds = pool.generate_uci(SLOW_FEN, 5);
+    let threads = format!("setoption name Threads value {}", (cpus / 2).max(1));
+    assert!(cmds.contains(&threads));
+    assert!(cmds.contains(&"setoption name Hash value 512".to_owned()));
+
+    let pool = EnginePool::new("engine".into(), 2).uci_options(vec![UciOption::new("Threads", 4)]);
+    let cmds = pool.generate_uci(SLOW_FEN, 5);
+    assert!(cmds.contains(&"setoption name Threads value 4".to_owned()));
+}
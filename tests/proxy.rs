@@ -0,0 +1,65 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::backends::NativeUci;
+use golemate::proxy::UciProxy;
+use tempfile::TempDir;
+
+/// The output of the proxy, and how the session ended
+fn session(script: &str, input: &str) -> (Vec<String>, anyhow::Result<()>) {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(fake_engine(dir.path(), script));
+    let mut output = Vec::new();
+    let result = UciProxy::new(&backend, 5).serve(input.as_bytes(), &mut output);
+    let lines = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    (lines, result)
+}
+
+fn talk(script: &str, input: &str) -> Vec<String> {
+    let (output, result) = session(script, input);
+    result.unwrap();
+    output
+}
+
+#[test]
+fn answers_the_handshake() {
+    let output = talk(&engine_script(&[], "bestmove e2e4"), "uci\nisready\nquit\n");
+    assert!(output[0].starts_with("id name Golemate (native:"));
+    assert_eq!(output[2], "uciok");
+    assert_eq!(output[3], "readyok");
+}
+
+#[test]
+fn forwards_the_searches() {
+    let script = engine_script(&["info depth 5 score cp 20 pv e2e4"], "bestmove e2e4");
+    let input = "uci\nposition startpos moves d2d4\ngo depth 5\ngo infinite\nquit\n";
+    let output = talk(&script, input);
+    let searches: Vec<_> = output
+        .iter()
+        .filter(|l| l.starts_with("bestmove"))
+        .collect();
+    assert_eq!(searches, ["bestmove e2e4", "bestmove e2e4"]);
+    assert!(output.contains(&"info depth 5 score cp 20 pv e2e4".to_owned()));
+}
+
+#[test]
+fn reports_backend_failures() {
+    let (output, result) = session("on go\n    crash\n", "go depth 3\nisready\n");
+    assert!(output[0].starts_with("info string "), "{:?}", output);
+    // No made-up move, and the session is over
+    assert_eq!(output.len(), 1);
+    assert!(result.is_err());
+
+    let (output, result) = session(&engine_script(&[], "info nodes 1"), "go depth 3\n");
+    assert_eq!(
+        output,
+        ["info string the backend didn't return the best move"]
+    );
+    assert!(result.is_err());
+}
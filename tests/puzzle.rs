@@ -0,0 +1,147 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::write_engine_script;
+use golemate::analysis::Score;
+use golemate::backends::NativeUci;
+use golemate::pgn::parse_pgn;
+use golemate::puzzle::{to_csv, PuzzleMiner};
+use golemate::variant::Variant;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const SCHOLARS_MATE: &str = "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0";
+
+/// The two best lines of some positions, by their boards, from the point of
+/// view of the side to move. The other positions have no clear best move.
+const LINES: &[(&str, &str, &str)] = &[
+    // A large gap, but a quiet move
+    (
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR",
+        "cp 400 pv g8f6",
+        "cp 0 pv e7e5",
+    ),
+    (
+        "r1bqkbnr/pppp1ppp/2n5/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR",
+        "cp 500 pv h5f7 e8f7 f1c4",
+        "cp 0 pv b1c3",
+    ),
+    // After 3. Qxf7+ Kxf7, Bc4+ isn't the only good move
+    (
+        "r1bq1bnr/pppp1kpp/2n5/4p3/4P3/8/PPPP1PPP/RNB1KBNR",
+        "cp -500 pv f1c4",
+        "cp -510 pv d2d4",
+    ),
+    (
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR",
+        "mate 1 pv h5f7",
+        "cp 50 pv b1c3",
+    ),
+];
+
+fn engine(dir: &Path) -> PathBuf {
+    let mut cases = String::new();
+    for (board, first, second) in LINES {
+        cases.push_str(&format!(
+            "*\" {} \"*) echo \"info depth 8 multipv 1 score {}\"; echo \"info depth 8 multipv 2 score {}\";;\n",
+            board, first, second
+        ));
+    }
+    cases.push_str(
+        "*) echo \"info depth 8 multipv 1 score cp 10 pv a2a3\"; echo \"info depth 8 multipv 2 score cp 0 pv a2a4\";;\n",
+    );
+    let body = format!(
+        "while read line; do\n\
+         case $line in\n\
+         uci) echo id name Shell; echo uciok;;\n\
+         isready) echo readyok;;\n\
+         setoption*) echo \"$line\" >> \"$0.log\";;\n\
+         position*) pos=$line;;\n\
+         go*) case $pos in\n{}esac; echo bestmove 0000;;\n\
+         quit) exit;;\n\
+         esac\n\
+         done\n",
+        cases
+    );
+    write_engine_script(dir, "engine", &body)
+}
+
+#[test]
+fn finds_the_puzzles() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    let games = parse_pgn(SCHOLARS_MATE).unwrap();
+    let puzzles = PuzzleMiner::new(8).mine(&backend, &games).unwrap();
+
+    assert_eq!(puzzles.len(), 2);
+    // The solution stops before the move that isn't unique
+    assert_eq!(puzzles[0].ply, 4);
+    assert_eq!(puzzles[0].moves, vec!["h5f7"]);
+    assert_eq!(puzzles[0].san, vec!["Qxf7+"]);
+    assert_eq!(puzzles[0].score, Score::Cp(500));
+    assert_eq!(puzzles[0].gap, 500);
+
+    assert_eq!(puzzles[1].game, 1);
+    assert_eq!(puzzles[1].ply, 6);
+    assert_eq!(puzzles[1].san, vec!["Qxf7#"]);
+    assert_eq!(puzzles[1].score, Score::Mate(1));
+
+    let csv = to_csv(&puzzles);
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines[0], "fen,moves,game,ply,score,gap");
+    assert_eq!(
+        lines[2],
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4,h5f7,1,6,mate 1,9949"
+    );
+}
+
+#[test]
+fn follows_the_forcing_lines() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    let games = parse_pgn(SCHOLARS_MATE).unwrap();
+    // Bc4+ is good enough now, and the mate is part of the first puzzle
+    let puzzles = PuzzleMiner::new(8)
+        .min_gap(5)
+        .mine(&backend, &games)
+        .unwrap();
+    assert_eq!(puzzles.len(), 1);
+    assert_eq!(puzzles[0].san, vec!["Qxf7+", "Kxf7", "Bc4+"]);
+
+    let puzzles = PuzzleMiner::new(8)
+        .max_moves(1)
+        .mine(&backend, &games)
+        .unwrap();
+    assert_eq!(puzzles.len(), 2);
+
+    let puzzles = PuzzleMiner::new(8)
+        .min_gap(10_000)
+        .mine(&backend, &games)
+        .unwrap();
+    assert!(puzzles.is_empty());
+}
+
+#[test]
+fn tells_the_engine_the_variant() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    let games = parse_pgn(SCHOLARS_MATE).unwrap();
+    let puzzles = PuzzleMiner::new(8)
+        .variant(Variant::Chess960)
+        .mine(&backend, &games)
+        .unwrap();
+    assert_eq!(puzzles.len(), 2);
+    let log = std::fs::read_to_string(dir.path().join("engine.log")).unwrap();
+    assert!(
+        log.contains("setoption name UCI_Chess960 value true"),
+        "{}",
+        log
+    );
+
+    let err = PuzzleMiner::new(8)
+        .variant(Variant::Atomic)
+        .mine(&backend, &games)
+        .unwrap_err();
+    assert!(err.to_string().contains("atomic"), "{}", err);
+}
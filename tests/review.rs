@@ -0,0 +1,156 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::write_engine_script;
+use golemate::backends::NativeUci;
+use golemate::pgn::parse_pgn;
+use golemate::review::{review_game, Judgement};
+use golemate::variant::Variant;
+use shakmaty::Color;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const SCHOLARS_MATE: &str = r#"[White "Alice"]
+[Black "Bob"]
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+"#;
+
+/// The evaluations of the positions of `SCHOLARS_MATE`, by their boards,
+/// from the point of view of the side to move
+const EVALUATIONS: &[(&str, &str, &str)] = &[
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+        "cp 30",
+        "e2e4",
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR",
+        "cp -30",
+        "e7e5",
+    ),
+    (
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR",
+        "cp 30",
+        "g1f3",
+    ),
+    (
+        "rnbqkbnr/pppp1ppp/8/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR",
+        "cp 0",
+        "b8c6",
+    ),
+    (
+        "r1bqkbnr/pppp1ppp/2n5/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR",
+        "cp 0",
+        "f1c4",
+    ),
+    (
+        "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR",
+        "cp 0",
+        "g7g6",
+    ),
+    (
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR",
+        "mate 1",
+        "h5f7",
+    ),
+];
+
+fn engine(dir: &Path) -> PathBuf {
+    let mut cases = String::new();
+    for (board, score, best) in EVALUATIONS {
+        cases.push_str(&format!(
+            "*\" {} \"*) echo \"info depth 8 score {} pv {}\"; echo bestmove {};;\n",
+            board, score, best, best
+        ));
+    }
+    let body = format!(
+        "while read line; do\n\
+         case $line in\n\
+         uci) echo id name Shell; echo uciok;;\n\
+         isready) echo readyok;;\n\
+         position*) pos=$line;;\n\
+         go*) case $pos in\n{}esac;;\n\
+         quit) exit;;\n\
+         esac\n\
+         done\n",
+        cases
+    );
+    write_engine_script(dir, "engine", &body)
+}
+
+#[test]
+fn judges_the_moves() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    let game = &parse_pgn(SCHOLARS_MATE).unwrap()[0];
+    let review = review_game(&backend, game, Variant::Standard, 8).unwrap();
+
+    assert_eq!(review.moves.len(), 7);
+    let blunder = &review.moves[5];
+    assert_eq!(blunder.notation(), "3... Nf6??");
+    assert_eq!(blunder.color, Color::Black);
+    assert_eq!(blunder.best_move, "g6");
+    assert_eq!(blunder.judgement, Some(Judgement::Blunder));
+    assert_eq!((blunder.eval_before, blunder.eval_after), (0, 1000));
+    assert_eq!(blunder.centipawn_loss, 1000);
+
+    let mate = &review.moves[6];
+    assert_eq!(mate.notation(), "4. Qxf7#");
+    assert_eq!(mate.centipawn_loss, 0);
+    assert!(mate.accuracy > 99.9);
+
+    assert_eq!(review.white.name, "Alice");
+    assert_eq!(review.white.blunders, 0);
+    assert_eq!(review.white.inaccuracies, 0);
+    assert!((review.white.average_centipawn_loss - 7.5).abs() < 1e-9);
+    assert_eq!(review.black.name, "Bob");
+    assert_eq!(review.black.blunders, 1);
+    assert!(review.black.accuracy < review.white.accuracy);
+
+    let critical: Vec<_> = review
+        .critical_moments
+        .iter()
+        .map(|m| m.notation())
+        .collect();
+    assert_eq!(critical, vec!["2. Qh5", "3... Nf6??"]);
+    assert!(review
+        .describe()
+        .contains("3... Nf6?? (+0.00 -> +10.00), best was g6"));
+}
+
+#[test]
+fn fails_without_an_evaluation() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    // The engine doesn't know the positions of this game
+    let game = &parse_pgn("1. d4 d5 *").unwrap()[0];
+    assert!(review_game(&backend, game, Variant::Standard, 8).is_err());
+}
+
+#[test]
+fn goes_on_after_a_failed_position() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    // The engine doesn't know the position after 2. Nf3
+    let game = &parse_pgn("1. e4 e5 2. Nf3 *").unwrap()[0];
+    let review = review_game(&backend, game, Variant::Standard, 8).unwrap();
+
+    let judged: Vec<_> = review.moves.iter().map(|m| m.notation()).collect();
+    assert_eq!(judged, vec!["1. e4", "1... e5"]);
+    assert_eq!(review.failures.len(), 1);
+    assert_eq!(review.failures[0].ply, 3);
+    assert!(review
+        .describe()
+        .contains("The position after ply 3 wasn't analysed"));
+}
+
+#[test]
+fn refuses_the_variants_it_cannot_replay() {
+    let dir = TempDir::new().unwrap();
+    let backend = NativeUci::new(engine(dir.path()));
+    let game = &parse_pgn(SCHOLARS_MATE).unwrap()[0];
+    let err = review_game(&backend, game, Variant::Atomic, 8).unwrap_err();
+    assert!(err.to_string().contains("atomic"), "{}", err);
+}
@@ -0,0 +1,205 @@
+#![cfg(all(feature = "serve", feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+
+/// A `golemate serve` process, killed when dropped
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start(dir: &Path, engine: &Path, extra: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_golemate"))
+            .env("XDG_CACHE_HOME", dir.join("cache"))
+            .env("XDG_CONFIG_HOME", dir.join("config"))
+            .env("XDG_DATA_HOME", dir.join("data"))
+            .args(&[
+                "serve",
+                "--listen",
+                "127.0.0.1:0",
+                "--no-cache",
+                "--depth",
+                "6",
+            ])
+            .arg("--engine")
+            .arg(engine)
+            .args(extra)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("running golemate serve");
+        let mut line = String::new();
+        BufReader::new(child.stdout.as_mut().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let addr = line
+            .trim()
+            .strip_prefix("Listening on http://")
+            .unwrap_or_else(|| panic!("unexpected output: {}", line))
+            .to_owned();
+        Self { child, addr }
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let headers = format!("Content-Length: {}\r\n", body.len());
+        self.send(method, path, &headers, &body)
+    }
+
+    /// Sends a request with extra `headers`, each ending with CRLF
+    fn send(&self, method: &str, path: &str, headers: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\
+             Content-Type: application/json\r\n{}\r\n{}",
+            method, path, self.addr, headers, body
+        )
+        .unwrap();
+        let mut response = String::new();
+        // A server refusing the request may reset the connection after its
+        // response, without reading the rest of the body
+        if let Err(e) = stream.read_to_string(&mut response) {
+            assert!(!response.is_empty(), "{}", e);
+        }
+        let status = response[9..12].parse().unwrap();
+        let body = response.splitn(2, "\r\n\r\n").nth(1).unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn submit(&self, job: Value) -> u64 {
+        let (status, job) = self.request("POST", "/jobs", Some(job));
+        assert_eq!(status, 202, "{}", job);
+        job["id"].as_u64().unwrap()
+    }
+
+    /// Polls the job until its status is `status`
+    fn wait_for(&self, id: u64, status: &str) -> Value {
+        let start = Instant::now();
+        loop {
+            let (_, job) = self.request("GET", &format!("/jobs/{}", id), None);
+            if job["status"] == status {
+                return job;
+            }
+            assert!(start.elapsed() < Duration::from_secs(10), "{}", job);
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn analyses_the_submitted_positions() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5 a7a6"], "bestmove f1b5");
+    let server = Server::start(dir.path(), &fake_engine(dir.path(), &script), &[]);
+
+    let id = server.submit(json!({ "fen": FEN }));
+    let job = server.wait_for(id, "done");
+    assert_eq!(job["fen"], FEN);
+    assert_eq!(job["depth"], 6);
+
+    let (status, result) = server.request("GET", &format!("/jobs/{}/result", id), None);
+    assert_eq!(status, 200, "{}", result);
+    assert_eq!(result["best_move"], "f1b5");
+    assert_eq!(result["pv"], json!(["f1b5", "a7a6"]));
+    assert_eq!(result["depth"], 6);
+    assert_eq!(result["advantage_side"], "white");
+    assert_eq!(result["advantage"], json!({ "centipawns": 40 }));
+}
+
+#[test]
+fn cancels_running_jobs() {
+    let dir = TempDir::new().unwrap();
+    let server = Server::start(
+        dir.path(),
+        &fake_engine(dir.path(), "on go\n    hang\n"),
+        &[],
+    );
+
+    let id = server.submit(json!({ "fen": FEN, "depth": 30 }));
+    server.wait_for(id, "running");
+    let (status, job) = server.request("DELETE", &format!("/jobs/{}", id), None);
+    assert_eq!(status, 200);
+    assert_eq!(job["status"], "cancelled");
+
+    let (status, _) = server.request("GET", &format!("/jobs/{}/result", id), None);
+    assert_eq!(status, 409);
+    let (status, _) = server.request("DELETE", &format!("/jobs/{}", id), None);
+    assert_eq!(status, 409);
+}
+
+#[test]
+fn refuses_jobs_when_the_queue_is_full() {
+    let dir = TempDir::new().unwrap();
+    let engine = fake_engine(dir.path(), "on go\n    hang\n");
+    let server = Server::start(dir.path(), &engine, &["--queue-size", "1"]);
+
+    let running = server.submit(json!({ "fen": FEN }));
+    server.wait_for(running, "running");
+    let queued = server.submit(json!({ "fen": FEN }));
+    let (status, body) = server.request("POST", "/jobs", Some(json!({ "fen": FEN })));
+    assert_eq!(status, 503);
+    assert!(body["error"].is_string());
+
+    // Cancelling the queued job frees its place
+    server.request("DELETE", &format!("/jobs/{}", queued), None);
+    let next = server.submit(json!({ "fen": FEN }));
+    server.request("DELETE", &format!("/jobs/{}", next), None);
+    server.request("DELETE", &format!("/jobs/{}", running), None);
+    // A job cancelled in the queue is never analysed
+    server.wait_for(queued, "cancelled");
+    server.wait_for(next, "cancelled");
+}
+
+#[test]
+fn rejects_invalid_requests() {
+    let dir = TempDir::new().unwrap();
+    let engine = fake_engine(dir.path(), &engine_script(&[], "bestmove f1b5"));
+    let server = Server::start(dir.path(), &engine, &[]);
+
+    let (status, body) = server.request("POST", "/jobs", Some(json!({ "fen": "invalid" })));
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("invalid FEN"));
+    let job = json!({ "fen": FEN, "profile": "missing" });
+    assert_eq!(server.request("POST", "/jobs", Some(job)).0, 400);
+    let job = json!({ "fen": FEN, "engine": "/bin/sh" });
+    assert_eq!(server.request("POST", "/jobs", Some(job)).0, 400);
+    assert_eq!(server.request("GET", "/jobs/42", None).0, 404);
+    assert_eq!(server.request("GET", "/jobs/x/result", None).0, 404);
+    assert_eq!(server.request("GET", "/nothing", None).0, 404);
+}
+
+#[test]
+fn limits_the_size_of_chunked_jobs() {
+    let dir = TempDir::new().unwrap();
+    let engine = fake_engine(dir.path(), &engine_script(&[], "bestmove f1b5"));
+    let server = Server::start(dir.path(), &engine, &[]);
+
+    // The limit is exceeded by the last chunk
+    let mut body = String::new();
+    for &size in &[16 * 1024, 16 * 1024, 16 * 1024, 16 * 1024, 1024] {
+        body.push_str(&format!("{:x}\r\n{}\r\n", size, " ".repeat(size)));
+    }
+    body.push_str("0\r\n\r\n");
+    let (status, _) = server.send("POST", "/jobs", "Transfer-Encoding: chunked\r\n", &body);
+    assert_eq!(status, 413);
+}
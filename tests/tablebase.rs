@@ -0,0 +1,61 @@
+#![cfg(all(feature = "syzygy", feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::analysis::{Advantage, AnalysisSource};
+use golemate::backends::NativeUci;
+use golemate::tablebase::{analyse, tablebase_advantage, Tablebase};
+use shakmaty::{fen::Fen, Color, Square};
+use shakmaty_syzygy::{Dtz, Wdl};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+/// No castling rights, which the tables don't cover
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w - - 2 3";
+
+fn empty_tablebase() -> Tablebase {
+    Tablebase::open::<PathBuf>(&[]).unwrap()
+}
+
+#[test]
+fn maps_the_wdl_and_the_dtz() {
+    assert_eq!(
+        tablebase_advantage(Wdl::Win, Dtz(5), Color::White),
+        (Advantage::TablebaseWin(5), Color::White)
+    );
+    assert_eq!(
+        tablebase_advantage(Wdl::Loss, Dtz(-12), Color::White),
+        (Advantage::TablebaseWin(12), Color::Black)
+    );
+    assert_eq!(
+        tablebase_advantage(Wdl::Loss, Dtz(-3), Color::Black),
+        (Advantage::TablebaseWin(3), Color::White)
+    );
+    for &wdl in &[Wdl::CursedWin, Wdl::Draw, Wdl::BlessedLoss] {
+        assert_eq!(
+            tablebase_advantage(wdl, Dtz(101), Color::Black).0,
+            Advantage::Equality
+        );
+    }
+}
+
+#[test]
+fn leaves_positions_with_too_many_pieces() {
+    let tablebase = empty_tablebase();
+    assert!(tablebase.max_pieces() < 32);
+    let fen: Fen = FEN.parse().unwrap();
+    assert!(tablebase.probe(&fen).unwrap().is_none());
+}
+
+#[test]
+fn falls_back_to_the_engine() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 5 score cp 30 pv f1b5"], "bestmove f1b5");
+    let backend = NativeUci::new(fake_engine(dir.path(), &script));
+
+    let result = analyse(&empty_tablebase(), &backend, FEN.parse().unwrap(), 5).unwrap();
+    assert_eq!(result.source, AnalysisSource::Engine);
+    assert_eq!(result.best_move.to(), Square::B5);
+    assert_eq!(result.advantage, Advantage::Centipawns(30));
+}
@@ -0,0 +1,89 @@
+#![cfg(all(feature = "native", unix))]
+
+mod common;
+
+use common::{engine_script, fake_engine};
+use golemate::backends::{read_transcript, NativeUci, RecordUci, ReplayUci, UciBackend};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const FEN: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+const OTHER_FEN: &str = "8/8/8/8/8/8/8/K1k5 w - - 0 1";
+
+fn recorder(dir: &Path, script: &str) -> (RecordUci<NativeUci>, PathBuf) {
+    let transcript = dir.join("transcript.jsonl");
+    let backend = RecordUci::new(NativeUci::new(fake_engine(dir, script)), &transcript).unwrap();
+    (backend, transcript)
+}
+
+#[test]
+fn replays_the_recorded_output() {
+    let dir = TempDir::new().unwrap();
+    let script = engine_script(&["info depth 6 score cp 40 pv f1b5"], "bestmove f1b5");
+    let (backend, transcript) = recorder(dir.path(), &script);
+    let recorded = backend.execute_uci(backend.generate_uci(FEN, 6)).unwrap();
+
+    let engine_id = backend.engine_id();
+
+    // The engine isn't needed anymore
+    std::fs::remove_file(dir.path().join("engine")).unwrap();
+    let replay = ReplayUci::open(&transcript).unwrap();
+    assert_eq!(replay.get_uci_opts(), backend.get_uci_opts());
+    assert_eq!(replay.engine_id(), format!("replay:{}", engine_id));
+    let replayed = replay.execute_uci(replay.generate_uci(FEN, 6)).unwrap();
+    assert_eq!(replayed, recorded);
+
+    // Nothing was recorded for another depth
+    assert!(replay.execute_uci(replay.generate_uci(FEN, 7)).is_err());
+}
+
+#[test]
+fn replays_the_recorded_errors() {
+    let dir = TempDir::new().unwrap();
+    let (backend, transcript) = recorder(dir.path(), "on go\n    crash\n");
+    assert!(backend.execute_uci(backend.generate_uci(FEN, 6)).is_err());
+
+    let entries = read_transcript(&transcript).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].output, None);
+    assert!(entries[0].error.is_some());
+    let replay = ReplayUci::new(entries).unwrap();
+    assert!(replay.execute_uci(replay.generate_uci(FEN, 6)).is_err());
+}
+
+#[test]
+fn records_every_session_of_a_batch() {
+    let dir = TempDir::new().unwrap();
+    let (backend, transcript) = recorder(dir.path(), &engine_script(&[], "bestmove a1a2"));
+    let inputs = vec![
+        backend.generate_uci(FEN, 2),
+        backend.generate_uci(OTHER_FEN, 2),
+    ];
+    assert!(backend
+        .execute_batch(inputs.clone())
+        .iter()
+        .all(Result::is_ok));
+    // Appended to the existing transcript
+    backend.execute_uci(inputs[0].clone()).unwrap();
+
+    let entries = read_transcript(&transcript).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[1].input, inputs[1]);
+    assert_eq!(entries[0].engine, backend.engine_id());
+
+    let replay = ReplayUci::open(&transcript).unwrap();
+    let outputs = replay.execute_batch(inputs);
+    assert!(outputs.iter().all(|output| output
+        .as_ref()
+        .unwrap()
+        .contains(&"bestmove a1a2".to_owned())));
+}
+
+#[test]
+fn rejects_an_empty_transcript() {
+    let dir = TempDir::new().unwrap();
+    let transcript = dir.path().join("empty.jsonl");
+    std::fs::write(&transcript, "").unwrap();
+    assert!(ReplayUci::open(&transcript).is_err());
+    assert!(ReplayUci::open(&dir.path().join("missing.jsonl")).is_err());
+}
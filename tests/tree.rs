@@ -0,0 +1,96 @@
+use golemate::game::parse_move;
+use golemate::tree::{GameTree, MoveRow, NodeId};
+use shakmaty::Chess;
+
+const INITIAL_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+fn tree() -> GameTree {
+    GameTree::from_fen(&INITIAL_FEN.parse().unwrap()).unwrap()
+}
+
+fn play(tree: &mut GameTree, moves: &[&str]) -> Vec<NodeId> {
+    moves
+        .iter()
+        .map(|text| {
+            let m = parse_move(tree.position(), text).unwrap();
+            tree.play(&m).unwrap()
+        })
+        .collect()
+}
+
+fn labels(row: &MoveRow) -> Vec<&str> {
+    row.moves.iter().map(|(_, label)| label.as_str()).collect()
+}
+
+#[test]
+fn navigates_the_main_line() {
+    let mut tree = tree();
+    let nodes = play(&mut tree, &["e4", "e7e5", "Nf3"]);
+    assert_eq!(
+        tree.fen(),
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    );
+
+    tree.first();
+    assert_eq!(tree.current(), GameTree::ROOT);
+    assert_eq!(tree.fen(), INITIAL_FEN);
+    assert!(!tree.back());
+    assert!(tree.forward());
+    assert_eq!(tree.current(), nodes[0]);
+    tree.last();
+    assert_eq!(tree.current(), nodes[2]);
+    assert!(!tree.forward());
+    assert!(tree.back());
+    assert_eq!(tree.current(), nodes[1]);
+
+    tree.select(nodes[0]).unwrap();
+    assert_eq!(tree.current(), nodes[0]);
+    assert!(tree.select(100).is_err());
+}
+
+#[test]
+fn branches_variations() {
+    let mut tree = tree();
+    let main = play(&mut tree, &["e4", "e5", "Nf3", "Nc6"]);
+    tree.select(main[1]).unwrap();
+    let variation = play(&mut tree, &["Bc4", "Nf6"]);
+    tree.select(main[0]).unwrap();
+    play(&mut tree, &["c5"]);
+
+    // Playing a move of the tree again follows it
+    tree.select(main[1]).unwrap();
+    assert_eq!(play(&mut tree, &["Nf3"]), vec![main[2]]);
+    // The main line goes on
+    tree.select(main[1]).unwrap();
+    tree.last();
+    assert_eq!(tree.current(), main[3]);
+    tree.select(variation[1]).unwrap();
+    assert!(tree.back());
+    assert!(tree.back());
+    assert_eq!(tree.current(), main[1]);
+
+    // As in PGN, a variation follows the move it replaces
+    let rows = tree.rows();
+    assert_eq!(rows.len(), 5);
+    assert_eq!((rows[0].depth, labels(&rows[0])), (0, vec!["1. e4", "e5"]));
+    assert_eq!((rows[1].depth, labels(&rows[1])), (1, vec!["1... c5"]));
+    assert_eq!((rows[2].depth, labels(&rows[2])), (0, vec!["2. Nf3"]));
+    assert_eq!(
+        (rows[3].depth, labels(&rows[3])),
+        (1, vec!["2. Bc4", "Nf6"])
+    );
+    assert_eq!((rows[4].depth, labels(&rows[4])), (0, vec!["2... Nc6"]));
+    assert_eq!(rows[3].moves[1].0, variation[1]);
+}
+
+#[test]
+fn rejects_illegal_moves() {
+    let mut tree = tree();
+    play(&mut tree, &["e4"]);
+    assert!(parse_move(tree.position(), "e4").is_err());
+    assert!(parse_move(tree.position(), "e2e4").is_err());
+    // A move of White, with Black to move
+    let m = parse_move(&Chess::default(), "d4").unwrap();
+    assert!(tree.play(&m).is_err());
+    assert_eq!(tree.rows()[0].moves.len(), 1);
+}
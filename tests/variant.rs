@@ -0,0 +1,72 @@
+use golemate::analysis::interpret_variant_uci;
+use golemate::variant::Variant;
+use shakmaty::{fen::Fen, Role, Square};
+
+fn output(bestmove: &str) -> Vec<String> {
+    vec![
+        "info depth 5 score cp 40 pv".to_owned(),
+        format!("bestmove {}", bestmove),
+    ]
+}
+
+#[test]
+fn parses_variant_names() {
+    assert_eq!("chess".parse::<Variant>().unwrap(), Variant::Standard);
+    assert_eq!("Chess960".parse::<Variant>().unwrap(), Variant::Chess960);
+    assert_eq!(
+        "king-of-the-hill".parse::<Variant>().unwrap(),
+        Variant::KingOfTheHill
+    );
+    assert_eq!("giveaway".parse::<Variant>().unwrap(), Variant::Antichess);
+    assert_eq!("3check".parse::<Variant>().unwrap(), Variant::ThreeCheck);
+    assert!("bughouse".parse::<Variant>().is_err());
+    for variant in Variant::ALL.iter() {
+        assert_eq!(variant.name().parse::<Variant>().unwrap(), *variant);
+    }
+}
+
+#[test]
+fn checks_moves_against_the_variant_rules() {
+    // Captures are compulsory in antichess
+    let fen: Fen = "rnbqkbnr/p1pppppp/8/1p6/8/4P3/PPPP1PPP/RNBQKBNR w - - 0 2"
+        .parse()
+        .unwrap();
+    assert!(interpret_variant_uci(Variant::Standard, fen.clone(), output("d2d4")).is_ok());
+    assert!(interpret_variant_uci(Variant::Antichess, fen.clone(), output("d2d4")).is_err());
+    let result = interpret_variant_uci(Variant::Antichess, fen, output("f1b5")).unwrap();
+    assert_eq!(result.best_move.to(), Square::B5);
+}
+
+#[test]
+fn accepts_drops_in_crazyhouse() {
+    let fen: Fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[N] w KQkq - 0 1"
+        .parse()
+        .unwrap();
+    let result = interpret_variant_uci(Variant::Crazyhouse, fen, output("N@e4")).unwrap();
+    assert_eq!(result.best_move.role(), Role::Knight);
+    assert_eq!(result.best_move.to(), Square::E4);
+}
+
+#[test]
+fn rejects_positions_illegal_in_the_variant() {
+    let horde: Fen = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1"
+        .parse()
+        .unwrap();
+    assert!(Variant::Horde.validate(&horde).is_ok());
+    assert!(Variant::Standard.validate(&horde).is_err());
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn tells_the_engine_about_the_variant() {
+    use golemate::backends::{NativeUci, UciBackend};
+
+    let backend = NativeUci::new("stockfish".into());
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    let cmds = backend.generate_variant_uci(Variant::Atomic, fen, 10);
+    assert!(cmds.contains(&"setoption name UCI_Variant value atomic".to_owned()));
+    let cmds = backend.generate_variant_uci(Variant::Chess960, fen, 10);
+    assert!(cmds.contains(&"setoption name UCI_Chess960 value true".to_owned()));
+    let cmds = backend.generate_uci(fen, 10);
+    assert!(!cmds.iter().any(|cmd| cmd.contains("UCI_")));
+}